@@ -3,11 +3,40 @@
 #include <stdio.h>  // IO
 #include <assert.h> // Assertions
 #include <string.h> // memcpy
+#include <inttypes.h> // PRId64
 #include <gc.h>     // Garbage Collection
 
 typedef uint32_t bool;
 typedef uint64_t symbol;
 
+// The symbols of the builtin methods. The code generator reserves these, in
+// this order, before it numbers any other symbols.
+#define SYM_CALL 1
+#define SYM_ADD 2
+#define SYM_SUB 3
+#define SYM_MUL 4
+#define SYM_DIV 5
+#define SYM_MOD 6
+#define SYM_EQ 7
+#define SYM_NE 8
+#define SYM_LT 9
+#define SYM_LE 10
+#define SYM_GT 11
+#define SYM_GE 12
+#define SYM_NEGATE 13
+#define SYM_NOT 14
+#define SYM_LEN 15
+#define SYM_GET 16
+#define SYM_PUSH 17
+#define SYM_MAP 18
+#define SYM_FOLD 19
+#define SYM_BITAND 20
+#define SYM_BITOR 21
+#define SYM_BITXOR 22
+#define SYM_TO_STR 23
+#define SYM_TO_INT 24
+#define SYM_TO_FLOAT 25
+
 typedef struct field_entry {
   symbol symbol;
   size_t offset;
@@ -24,8 +53,11 @@ typedef struct record_def {
   // entries...
 } record_def;
 
+typedef struct value value;
+
 typedef struct record {
   record_def *def;
+  value **env; // The cells of the variables which the methods close over
   // fields...
 } record;
 
@@ -35,20 +67,48 @@ typedef enum value_tag {
   TAG_UINT32,
   TAG_BOOL,
   TAG_STRING,
-  TAG_NULL
+  TAG_NULL,
+  TAG_LIST
 } __attribute__((packed)) value_tag;
 
-typedef struct value {
+struct value {
   value_tag tag; // TODO(michael): Use a technique like NaN-boxing or pointer tagging to make this struct be PO2 sized
   size_t value;
-} value;
+};
+
+// Lists are growable arrays of values. They are shared, so pushing onto a
+// list is visible through every value which refers to it.
+typedef struct list {
+  size_t len;
+  size_t cap;
+  value *items;
+} list;
+
+// The implementations of methods, as returned by getMethod. They take the
+// record which they were called on, followed by their arguments.
+typedef value (*method0)(value);
+typedef value (*method1)(value, value);
+typedef value (*method2)(value, value, value);
+
+bool valueIsNull(value v) {
+  return v.tag == TAG_NULL;
+}
 
 bool valueIsDouble(value v) {
   return v.tag == TAG_DOUBLE;
 }
 
 double valueAsDouble(value v) {
-  return (double) v.value;
+  // The code generator stores the bits of the double directly
+  double d;
+  memcpy(&d, &v.value, sizeof(double));
+  return d;
+}
+
+value doubleAsValue(double d) {
+  value v = { .tag = TAG_DOUBLE };
+  memcpy(&v.value, &d, sizeof(double));
+  return v;
 }
 
 bool valueIsRecord(value v) {
@@ -67,7 +127,224 @@ bool valueAsBool(value v) {
   return (bool) v.value;
 }
 
-value getProperty(value v, symbol s) {
+value boolAsValue(bool b) {
+  value v = { .tag = TAG_BOOL };
+  v.value = b;
+  return v;
+}
+
+value numAdd(value self, value other) {
+  return doubleAsValue(valueAsDouble(self) + valueAsDouble(other));
+}
+
+value numSub(value self, value other) {
+  return doubleAsValue(valueAsDouble(self) - valueAsDouble(other));
+}
+
+value numMul(value self, value other) {
+  return doubleAsValue(valueAsDouble(self) * valueAsDouble(other));
+}
+
+value numDiv(value self, value other) {
+  return doubleAsValue(valueAsDouble(self) / valueAsDouble(other));
+}
+
+value numMod(value self, value other) {
+  // TODO(michael): Ints are currently represented as doubles
+  int64_t a = (int64_t) valueAsDouble(self);
+  int64_t b = (int64_t) valueAsDouble(other);
+  return doubleAsValue((double) (a % b));
+}
+
+// The bitwise operators are only defined for Ints, which are stored as
+// doubles, so they are converted to integers and back.
+value numBitAnd(value self, value other) {
+  int64_t a = (int64_t) valueAsDouble(self);
+  int64_t b = (int64_t) valueAsDouble(other);
+  return doubleAsValue((double) (a & b));
+}
+
+value numBitOr(value self, value other) {
+  int64_t a = (int64_t) valueAsDouble(self);
+  int64_t b = (int64_t) valueAsDouble(other);
+  return doubleAsValue((double) (a | b));
+}
+
+value numBitXor(value self, value other) {
+  int64_t a = (int64_t) valueAsDouble(self);
+  int64_t b = (int64_t) valueAsDouble(other);
+  return doubleAsValue((double) (a ^ b));
+}
+
+value numEq(value self, value other) {
+  return boolAsValue(valueAsDouble(self) == valueAsDouble(other));
+}
+
+value numNe(value self, value other) {
+  return boolAsValue(valueAsDouble(self) != valueAsDouble(other));
+}
+
+value numLt(value self, value other) {
+  return boolAsValue(valueAsDouble(self) < valueAsDouble(other));
+}
+
+value numLe(value self, value other) {
+  return boolAsValue(valueAsDouble(self) <= valueAsDouble(other));
+}
+
+value numGt(value self, value other) {
+  return boolAsValue(valueAsDouble(self) > valueAsDouble(other));
+}
+
+value numGe(value self, value other) {
+  return boolAsValue(valueAsDouble(self) >= valueAsDouble(other));
+}
+
+value numNegate(value self) {
+  return doubleAsValue(-valueAsDouble(self));
+}
+
+// Ints and Floats are both stored as doubles, so converting between them only
+// has to drop the fractional part
+value numToInt(value self) {
+  return doubleAsValue((double) (int64_t) valueAsDouble(self));
+}
+
+value numToFloat(value self) {
+  return self;
+}
+
+value stringAsValue(const char *str);
+
+// Whole numbers are written without a fraction, like the interpreter does.
+// Other numbers get the fewest digits which still read back as the same double.
+value numToStr(value self) {
+  double d = valueAsDouble(self);
+  char *str = GC_MALLOC_ATOMIC(32);
+
+  if (d > -1e18 && d < 1e18 && d == (double) (int64_t) d) {
+    snprintf(str, 32, "%" PRId64, (int64_t) d);
+  } else {
+    for (int precision = 1; precision <= 17; precision++) {
+      snprintf(str, 32, "%.*g", precision, d);
+      if (strtod(str, NULL) == d) break;
+    }
+  }
+  return stringAsValue(str);
+}
+
+value boolEq(value self, value other) {
+  return boolAsValue(valueAsBool(self) == valueAsBool(other));
+}
+
+value boolNe(value self, value other) {
+  return boolAsValue(valueAsBool(self) != valueAsBool(other));
+}
+
+value boolNot(value self) {
+  return boolAsValue(!valueAsBool(self));
+}
+
+value boolToStr(value self) {
+  return stringAsValue(valueAsBool(self) ? "true" : "false");
+}
+
+void *getNumMethod(symbol s) {
+  switch (s) {
+  case SYM_ADD: return numAdd;
+  case SYM_SUB: return numSub;
+  case SYM_MUL: return numMul;
+  case SYM_DIV: return numDiv;
+  case SYM_MOD: return numMod;
+  case SYM_BITAND: return numBitAnd;
+  case SYM_BITOR: return numBitOr;
+  case SYM_BITXOR: return numBitXor;
+  case SYM_EQ: return numEq;
+  case SYM_NE: return numNe;
+  case SYM_LT: return numLt;
+  case SYM_LE: return numLe;
+  case SYM_GT: return numGt;
+  case SYM_GE: return numGe;
+  case SYM_NEGATE: return numNegate;
+  case SYM_TO_INT: return numToInt;
+  case SYM_TO_FLOAT: return numToFloat;
+  case SYM_TO_STR: return numToStr;
+  }
+  // The type checker ensures that this doesn't happen
+  assert(0 && "Numbers don't have this method");
+  return NULL;
+}
+
+void *getBoolMethod(symbol s) {
+  switch (s) {
+  case SYM_EQ: return boolEq;
+  case SYM_NE: return boolNe;
+  case SYM_NOT: return boolNot;
+  case SYM_TO_STR: return boolToStr;
+  }
+  assert(0 && "Bools don't have this method");
+  return NULL;
+}
+
+bool valueIsString(value v) {
+  return v.tag == TAG_STRING;
+}
+
+// Strings are pointers to immutable, NUL-terminated bytes
+const char *valueAsString(value v) {
+  return (const char *) v.value;
+}
+
+value stringAsValue(const char *str) {
+  value v = { .tag = TAG_STRING };
+  v.value = (size_t) str;
+  return v;
+}
+
+value strAdd(value self, value other) {
+  const char *a = valueAsString(self);
+  const char *b = valueAsString(other);
+  size_t alen = strlen(a);
+  size_t blen = strlen(b);
+
+  char *str = GC_MALLOC_ATOMIC(alen + blen + 1);
+  memcpy(str, a, alen);
+  memcpy(str + alen, b, blen + 1);
+  return stringAsValue(str);
+}
+
+value strEq(value self, value other) {
+  return boolAsValue(strcmp(valueAsString(self), valueAsString(other)) == 0);
+}
+
+value strNe(value self, value other) {
+  return boolAsValue(strcmp(valueAsString(self), valueAsString(other)) != 0);
+}
+
+value strLen(value self) {
+  return doubleAsValue((double) strlen(valueAsString(self)));
+}
+
+value strToStr(value self) {
+  return self;
+}
+
+void *getStringMethod(symbol s) {
+  switch (s) {
+  case SYM_ADD: return strAdd;
+  case SYM_EQ: return strEq;
+  case SYM_NE: return strNe;
+  case SYM_LEN: return strLen;
+  case SYM_TO_STR: return strToStr;
+  }
+  assert(0 && "Strings don't have this method");
+  return NULL;
+}
+
+void *getListMethod(symbol s);
+
+// The slot in the record which holds the property s
+value *propertySlot(value v, symbol s) {
   assert(valueIsRecord(v));
   record *record = valueAsRecord(v);
   record_def *def = record->def;
@@ -85,10 +362,31 @@ value getProperty(value v, symbol s) {
     assert(idx != s % size);
   }
 
-  return ((value *)(record+1))[fields[idx].offset];
+  return &((value *)(record+1))[fields[idx].offset];
+}
+
+value getProperty(value v, symbol s) {
+  return *propertySlot(v, s);
+}
+
+// Assigns to a mutable field. The type checker ensures that it is mutable.
+value setProperty(value v, symbol s, value prop) {
+  *propertySlot(v, s) = prop;
+
+  value null = { .tag = TAG_NULL };
+  return null;
 }
 
 void *getMethod(value v, symbol s) {
+  // Builtin values don't have record defs, so their methods are looked up by symbol
+  switch (v.tag) {
+  case TAG_DOUBLE: return getNumMethod(s);
+  case TAG_BOOL: return getBoolMethod(s);
+  case TAG_STRING: return getStringMethod(s);
+  case TAG_LIST: return getListMethod(s);
+  default: break;
+  }
+
   assert(valueIsRecord(v));
   record *record = valueAsRecord(v);
   record_def *def = record->def;
@@ -110,12 +408,144 @@ void *getMethod(value v, symbol s) {
   return mthds[idx].fn;
 }
 
-value allocRecord(size_t size) {
+value allocRecord(record_def *def, value **env) {
+  record *rec = GC_MALLOC(sizeof(record) + def->prop_size * sizeof(value));
+  rec->def = def;
+  rec->env = env;
+
   value v = { .tag = TAG_RECORD };
-  v.value = (size_t) GC_MALLOC(size);
+  v.value = (size_t) rec;
   return v;
 };
 
+void initProperty(value v, size_t offset, value prop) {
+  assert(valueIsRecord(v));
+  ((value *)(valueAsRecord(v)+1))[offset] = prop;
+}
+
+value **recordEnv(value v) {
+  assert(valueIsRecord(v));
+  return valueAsRecord(v)->env;
+}
+
+// Variables live in cells on the heap, so that closures can share them
+value *allocCell() {
+  return GC_MALLOC(sizeof(value));
+}
+
+value **allocEnv(size_t len) {
+  return GC_MALLOC(len * sizeof(value *));
+}
+
+bool valueIsList(value v) {
+  return v.tag == TAG_LIST;
+}
+
+list *valueAsList(value v) {
+  return (list *) v.value;
+}
+
+value allocList(size_t cap) {
+  list *l = GC_MALLOC(sizeof(list));
+  l->len = 0;
+  l->cap = cap > 0 ? cap : 4;
+  l->items = GC_MALLOC(l->cap * sizeof(value));
+
+  value v = { .tag = TAG_LIST };
+  v.value = (size_t) l;
+  return v;
+}
+
+value listLen(value v) {
+  assert(valueIsList(v));
+  return doubleAsValue((double) valueAsList(v)->len);
+}
+
+value listGet(value v, size_t idx) {
+  assert(valueIsList(v));
+  list *l = valueAsList(v);
+
+  if (idx >= l->len) {
+    fprintf(stderr, "error[E0405]: Index %zu is out of bounds for a list of length %zu\n",
+            idx, l->len);
+    exit(1);
+  }
+
+  return l->items[idx];
+}
+
+value listPush(value v, value item) {
+  assert(valueIsList(v));
+  list *l = valueAsList(v);
+
+  if (l->len == l->cap) {
+    value *items = GC_MALLOC(l->cap * 2 * sizeof(value));
+    memcpy(items, l->items, l->len * sizeof(value));
+    l->items = items;
+    l->cap *= 2;
+  }
+  l->items[l->len++] = item;
+
+  value null = { .tag = TAG_NULL };
+  return null;
+}
+
+value listGetMethod(value v, value idx) {
+  double i = valueAsDouble(idx);
+  if (i < 0) {
+    fprintf(stderr, "error[E0405]: Index %g is out of bounds\n", i);
+    exit(1);
+  }
+  return listGet(v, (size_t) i);
+}
+
+// Calls a closure with a single argument
+value call1(value f, value a) {
+  method1 call = getMethod(f, SYM_CALL);
+  return call(f, a);
+}
+
+value call2(value f, value a, value b) {
+  method2 call = getMethod(f, SYM_CALL);
+  return call(f, a, b);
+}
+
+value listMap(value v, value f) {
+  assert(valueIsList(v));
+  list *l = valueAsList(v);
+  size_t len = l->len;
+
+  value mapped = allocList(len);
+  for (size_t i = 0; i < len; i++) {
+    listPush(mapped, call1(f, l->items[i]));
+  }
+  return mapped;
+}
+
+value listFold(value v, value init, value f) {
+  assert(valueIsList(v));
+  list *l = valueAsList(v);
+  size_t len = l->len;
+
+  value acc = init;
+  for (size_t i = 0; i < len; i++) {
+    acc = call2(f, acc, l->items[i]);
+  }
+  return acc;
+}
+
+void *getListMethod(symbol s) {
+  switch (s) {
+  case SYM_LEN: return listLen;
+  case SYM_GET: return listGetMethod;
+  case SYM_PUSH: return listPush;
+  case SYM_MAP: return listMap;
+  case SYM_FOLD: return listFold;
+  }
+  assert(0 && "Lists don't have this method");
+  return NULL;
+}
+
 void __ducky__main();
 int main() {
   // TODO(michael): Store the cmd line arguments somewhere
@@ -0,0 +1,187 @@
+use std::fmt;
+use span::Span;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Level {
+    Error,
+    Warning,
+    Note,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Level::Error => write!(f, "error"),
+            Level::Warning => write!(f, "warning"),
+            Level::Note => write!(f, "note"),
+        }
+    }
+}
+
+/// The machine-readable kind of a diagnostic. Tools should match on this
+/// rather than on the message text, which is allowed to change.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Kind {
+    // Lexing
+    UnexpectedChar,
+    UnterminatedComment,
+    UnterminatedString,
+    InvalidEscape,
+    InvalidNumber,
+    NumberOverflow,
+
+    // Parsing
+    UnexpectedToken,
+    ExpectedRecordType,
+    InvalidAssignment,
+
+    // Scoping
+    UndeclaredVariable,
+    UndeclaredType,
+    RecursiveTypeAlias,
+    TypeArgumentMismatch,
+    PrivateBrand,
+    BreakOutsideLoop,
+    ReturnOutsideFunction,
+
+    // Type Inference
+    TypeMismatch,
+    MissingProperty,
+    ArityMismatch,
+    PropertyKindMismatch,
+    BrandMismatch,
+    NonExhaustiveMatch,
+    UnreachablePattern,
+    ImmutableProperty,
+    InfiniteType,
+
+    // Interpretation
+    UndefinedMember,
+    UninitializedVariable,
+    ArithmeticError,
+    RuntimeTypeError,
+    IndexOutOfBounds,
+
+    // Code Generation
+    UnsupportedByBackend,
+}
+
+impl Kind {
+    /// Every kind of diagnostic has a stable error code
+    pub fn code(&self) -> &'static str {
+        match *self {
+            Kind::UnexpectedChar => "E0001",
+            Kind::UnterminatedComment => "E0002",
+            Kind::UnterminatedString => "E0003",
+            Kind::InvalidEscape => "E0004",
+            Kind::InvalidNumber => "E0005",
+            Kind::NumberOverflow => "E0006",
+
+            Kind::UnexpectedToken => "E0101",
+            Kind::ExpectedRecordType => "E0102",
+            Kind::InvalidAssignment => "E0103",
+
+            Kind::UndeclaredVariable => "E0201",
+            Kind::UndeclaredType => "E0202",
+            Kind::RecursiveTypeAlias => "E0203",
+            Kind::TypeArgumentMismatch => "E0204",
+            Kind::PrivateBrand => "E0205",
+            Kind::BreakOutsideLoop => "E0206",
+            Kind::ReturnOutsideFunction => "E0207",
+
+            Kind::TypeMismatch => "E0301",
+            Kind::MissingProperty => "E0302",
+            Kind::ArityMismatch => "E0303",
+            Kind::PropertyKindMismatch => "E0304",
+            Kind::BrandMismatch => "E0305",
+            Kind::NonExhaustiveMatch => "E0306",
+            Kind::UnreachablePattern => "E0307",
+            Kind::ImmutableProperty => "E0308",
+            Kind::InfiniteType => "E0309",
+
+            Kind::UndefinedMember => "E0401",
+            Kind::UninitializedVariable => "E0402",
+            Kind::ArithmeticError => "E0403",
+            Kind::RuntimeTypeError => "E0404",
+            Kind::IndexOutOfBounds => "E0405",
+
+            Kind::UnsupportedByBackend => "E0501",
+        }
+    }
+}
+
+/// A Label attaches a message to a span of the source program. Every
+/// diagnostic should have at most one primary label, which points at
+/// the place where the problem was detected.
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    pub primary: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub kind: Kind,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(level: Level, kind: Kind, message: String) -> Diagnostic {
+        Diagnostic{
+            level: level,
+            kind: kind,
+            message: message,
+            labels: vec![],
+            notes: vec![],
+        }
+    }
+
+    pub fn error(kind: Kind, message: String) -> Diagnostic {
+        Diagnostic::new(Level::Error, kind, message)
+    }
+
+    pub fn warning(kind: Kind, message: String) -> Diagnostic {
+        Diagnostic::new(Level::Warning, kind, message)
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.kind.code()
+    }
+
+    pub fn with_primary(mut self, span: Span, message: &str) -> Diagnostic {
+        self.labels.push(Label{ span: span, message: message.to_string(), primary: true });
+        self
+    }
+
+    pub fn with_secondary(mut self, span: Span, message: &str) -> Diagnostic {
+        self.labels.push(Label{ span: span, message: message.to_string(), primary: false });
+        self
+    }
+
+    pub fn with_note(mut self, note: String) -> Diagnostic {
+        self.notes.push(note);
+        self
+    }
+
+    pub fn primary_span(&self) -> Option<Span> {
+        self.labels.iter().find(|label| label.primary).map(|label| label.span)
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.level == Level::Error
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{}[{}]: {}", self.level, self.code(), self.message));
+        if let Some(span) = self.primary_span() {
+            try!(write!(f, " (at {})", span));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,543 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::cmp::Ordering;
+use libc;
+use lexer;
+use parser;
+use scope;
+use infer;
+use simplify;
+use interp;
+use gen;
+use repl;
+use emitter::Emitter;
+use il::{Ident, User};
+
+const USAGE: &'static str = "\
+Usage: duckyc [COMMAND] [OPTIONS] FILE
+       duckyc repl
+
+Commands:
+    check       Parse and typecheck FILE, without generating any code
+    build       Compile FILE to an executable (the default)
+    run         Typecheck FILE, and then run it with the interpreter
+    repl        Interactively evaluate expressions, showing their types
+
+Options:
+    --emit=KIND[,KIND...]   Output the given stages instead of an executable.
+                            KIND is one of tokens, ast, scoped, types,
+                            llvm-ir, bitcode or obj
+    -o PATH                 Write the output to PATH. At most one of llvm-ir,
+                            bitcode or obj can be emitted with it
+    --color=WHEN            Colour diagnostics: auto, always or never
+    -h, --help              Show this message
+";
+
+/// The runtime is linked into every executable we produce
+const RUNTIME: &'static str = concat!(env!("CARGO_MANIFEST_DIR"), "/rt/rt.bc");
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Command {
+    Check,
+    Build,
+    Run,
+    Repl,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Emit {
+    Tokens,
+    Ast,
+    Scoped,
+    Types,
+    LlvmIr,
+    Bitcode,
+    Obj,
+}
+
+impl Emit {
+    fn from_str(s: &str) -> Option<Emit> {
+        match s {
+            "tokens" => Some(Emit::Tokens),
+            "ast" => Some(Emit::Ast),
+            "scoped" => Some(Emit::Scoped),
+            "types" => Some(Emit::Types),
+            "llvm-ir" => Some(Emit::LlvmIr),
+            "bitcode" => Some(Emit::Bitcode),
+            "obj" => Some(Emit::Obj),
+            _ => None,
+        }
+    }
+
+    /// Whether this output is produced by the code generator
+    fn is_codegen(&self) -> bool {
+        match *self {
+            Emit::LlvmIr | Emit::Bitcode | Emit::Obj => true,
+            _ => false,
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match *self {
+            Emit::LlvmIr => "ll",
+            Emit::Bitcode => "bc",
+            Emit::Obj => "o",
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ColorConfig {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Debug)]
+pub struct Options {
+    pub command: Command,
+    pub emit: Vec<Emit>,
+    pub input: Option<String>,
+    pub output: Option<String>,
+    pub color: ColorConfig,
+}
+
+/// Parses the command line arguments (excluding the program name)
+pub fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut command = None;
+    let mut emit = vec![];
+    let mut input = None;
+    let mut output = None;
+    let mut color = ColorConfig::Auto;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let arg = arg.as_slice();
+        if arg.starts_with("--emit=") {
+            for kind in arg["--emit=".len()..].split(',') {
+                match Emit::from_str(kind) {
+                    Some(kind) => emit.push(kind),
+                    None => return Err(format!("Unknown --emit kind: {}", kind)),
+                }
+            }
+        } else if arg.starts_with("--color=") {
+            color = match &arg["--color=".len()..] {
+                "auto" => ColorConfig::Auto,
+                "always" => ColorConfig::Always,
+                "never" => ColorConfig::Never,
+                when => return Err(format!("Unknown --color setting: {}", when)),
+            };
+        } else if arg == "-o" {
+            match args.next() {
+                Some(path) => output = Some(path.clone()),
+                None => return Err(format!("-o requires a path")),
+            }
+        } else if arg.starts_with("-") {
+            return Err(format!("Unknown option: {}", arg));
+        } else if command.is_none() && input.is_none() &&
+            (arg == "check" || arg == "build" || arg == "run" || arg == "repl") {
+            command = Some(match arg {
+                "check" => Command::Check,
+                "build" => Command::Build,
+                "run" => Command::Run,
+                _ => Command::Repl,
+            });
+        } else if input.is_none() {
+            input = Some(arg.to_string());
+        } else {
+            return Err(format!("Unexpected argument: {}", arg));
+        }
+    }
+
+    let command = command.unwrap_or(Command::Build);
+    if output.is_some() && emit.iter().filter(|kind| kind.is_codegen()).count() > 1 {
+        return Err(format!("-o can't be used when emitting more than one kind of code"));
+    }
+    if command == Command::Check || command == Command::Run {
+        if let Some(kind) = emit.iter().find(|kind| kind.is_codegen()) {
+            return Err(format!("`{}` can't emit {:?}, as it doesn't generate code",
+                               if command == Command::Check { "check" } else { "run" }, kind));
+        }
+    }
+
+    match (command, &input) {
+        (Command::Repl, &Some(_)) => return Err(format!("`repl` doesn't take an input file")),
+        (Command::Repl, &None) => {}
+        (_, &None) => return Err(format!("No input file")),
+        _ => {}
+    }
+
+    Ok(Options{
+        command: command,
+        emit: emit,
+        input: input,
+        output: output,
+        color: color,
+    })
+}
+
+/// Runs the compiler with the given command line arguments, returning the exit code
+pub fn main(args: &[String]) -> i32 {
+    if args.iter().any(|arg| arg.as_slice() == "-h" || arg.as_slice() == "--help") {
+        print!("{}", USAGE);
+        return 0;
+    }
+
+    let opts = match parse_args(args) {
+        Ok(opts) => opts,
+        Err(msg) => {
+            println_err(&format!("error: {}\n\n{}", msg, USAGE));
+            return 2;
+        }
+    };
+
+    let color = match opts.color {
+        ColorConfig::Always => true,
+        ColorConfig::Never => false,
+        ColorConfig::Auto => unsafe { libc::isatty(libc::STDERR_FILENO) != 0 },
+    };
+
+    let input = match opts.input {
+        Some(ref input) => input.as_slice(),
+        None => return repl::run(color),
+    };
+
+    let mut source = String::new();
+    if let Err(err) = File::open(&Path::new(input))
+        .and_then(|mut f| f.read_to_string(&mut source)) {
+        println_err(&format!("error: couldn't read {}: {}", input, err));
+        return 1;
+    }
+
+    let emitter = Emitter::new(input, source.as_slice(), color);
+
+    match compile(&opts, input, source.as_slice(), &emitter) {
+        Ok(code) => code,
+        Err(msg) => {
+            println_err(&format!("error: {}", msg));
+            1
+        }
+    }
+}
+
+fn println_err(msg: &str) {
+    use std::io::{self, Write};
+    let _ = writeln!(&mut io::stderr(), "{}", msg);
+}
+
+/// Returns early with exit code 1 after reporting a diagnostic
+macro_rules! try_diag {
+    ($emitter:expr, $e:expr) => {
+        match $e {
+            Ok(x) => x,
+            Err(diag) => {
+                $emitter.emit(&diag);
+                return Ok(1);
+            }
+        }
+    }
+}
+
+fn compile(opts: &Options, input: &str, source: &str, emitter: &Emitter) -> Result<i32, String> {
+    let tokens = try_diag!(emitter, lexer::lex(source));
+    if opts.emit.contains(&Emit::Tokens) {
+        for tok in tokens.iter() {
+            println!("{}: {}", tok.span, tok.node);
+        }
+    }
+
+    // Syntax errors don't stop compilation straight away, so that the later
+    // stages can report the problems in the rest of the program too
+    let (ast, errors) = parser::parse_program_recovering(&mut parser::State::new(tokens.as_slice()));
+    for err in errors.iter() {
+        emitter.emit(err);
+    }
+    if opts.emit.contains(&Emit::Ast) {
+        for stmt in ast.iter() {
+            println!("{:?}", stmt);
+        }
+    }
+
+    let scoped_ast = try_diag!(emitter, scope::scoped_block(&mut scope::Scope::new(), ast.as_slice()));
+    if opts.emit.contains(&Emit::Scoped) {
+        for stmt in scoped_ast.iter() {
+            println!("{:?}", stmt);
+        }
+    }
+
+    let types = try_diag!(emitter, infer::infer_program(scoped_ast.clone()));
+    for warning in types.warnings.iter() {
+        emitter.emit(warning);
+    }
+    if opts.emit.contains(&Emit::Types) {
+        for line in type_lines(&types).iter() {
+            println!("{}", line);
+        }
+    }
+
+    if ! errors.is_empty() {
+        return Ok(1);
+    }
+
+    match opts.command {
+        Command::Check => return Ok(0),
+        Command::Run => {
+            // Running doesn't need llvm, so we interpret the program instead
+            let value = try_diag!(emitter, interp::eval_program(scoped_ast.as_slice()));
+            if let interp::Value::Null = value {} else {
+                println!("{:?}", value);
+            }
+            return Ok(0);
+        }
+        _ => {}
+    }
+
+    // Report what the backend can't compile, rather than crashing part way through
+    let unsupported = gen::unsupported(scoped_ast.as_slice());
+    for diag in unsupported.iter() {
+        emitter.emit(diag);
+    }
+    if ! unsupported.is_empty() {
+        return Ok(1);
+    }
+
+    let input = Path::new(input);
+    let codegen_emits: Vec<Emit> = opts.emit.iter().cloned().filter(|kind| kind.is_codegen()).collect();
+    if ! opts.emit.is_empty() {
+        // Only produce the outputs which were asked for
+        for &kind in codegen_emits.iter() {
+            let output = match opts.output {
+                Some(ref output) => PathBuf::new(output),
+                None => input.with_extension(kind.extension()),
+            };
+            try!(check_output(input, &output));
+            try!(emit_code(scoped_ast.clone(), kind, &output));
+        }
+        return Ok(0);
+    }
+
+    // Build an executable
+    let exe = match opts.output {
+        Some(ref output) => PathBuf::new(output),
+        None => input.with_extension(""),
+    };
+    try!(check_output(input, &exe));
+    let bitcode = temp_path(input, "bc");
+    let res = emit_code(scoped_ast, Emit::Bitcode, &bitcode).and_then(|_| {
+        invoke(process::Command::new("clang")
+               .arg(&bitcode).arg(RUNTIME)
+               .arg("-lgc").arg("-o").arg(&exe))
+    });
+    let _ = fs::remove_file(&bitcode);
+    try!(res);
+
+    Ok(0)
+}
+
+/// Refuses to write an output over the source file, which happens by default
+/// for an input without an extension, like `duckyc build prog`
+fn check_output(input: &Path, output: &Path) -> Result<(), String> {
+    if input == output {
+        Err(format!("the output would overwrite the input file {}, choose another path with -o",
+                    input.display()))
+    } else {
+        Ok(())
+    }
+}
+
+/// The type of each top-level binding, in the order in which they were made
+fn type_lines(types: &infer::InferValue) -> Vec<String> {
+    let mut bindings: Vec<_> = types.data_vars.iter().collect();
+    bindings.sort_by(|&(&Ident(_, a), _), &(&Ident(_, b), _)| {
+        match (a, b) {
+            (User(a), User(b)) => a.cmp(&b),
+            _ => Ordering::Equal,
+        }
+    });
+    bindings.iter().map(|&(id, ty)| {
+        format!("{} : {}", id.0, simplify::simplify_ty(ty, &types.type_vars))
+    }).collect()
+}
+
+/// Generates code for the program in the format requested by `kind`
+fn emit_code(ast: Vec<::il::Stmt>, kind: Emit, output: &Path) -> Result<(), String> {
+    match kind {
+        Emit::LlvmIr => unsafe { gen::gen_code(ast, gen::OutputKind::LlvmIr, output) },
+        Emit::Bitcode => unsafe { gen::gen_code(ast, gen::OutputKind::Bitcode, output) },
+        Emit::Obj => {
+            // LLVM's C API can't easily produce object files, so let llc do that
+            let bitcode = temp_path(output, "bc");
+            let res = unsafe { gen::gen_code(ast, gen::OutputKind::Bitcode, &bitcode) }.and_then(|_| {
+                invoke(process::Command::new("llc")
+                       .arg("-filetype=obj").arg(&bitcode).arg("-o").arg(output))
+            });
+            let _ = fs::remove_file(&bitcode);
+            res
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Runs an external tool, such as clang or llc
+fn invoke(cmd: &mut process::Command) -> Result<(), String> {
+    match cmd.status() {
+        Ok(ref status) if status.success() => Ok(()),
+        Ok(status) => Err(format!("{:?} failed with {}", cmd, status)),
+        Err(err) => Err(format!("couldn't execute {:?}: {}", cmd, err)),
+    }
+}
+
+/// A path in the temporary directory for intermediate build products
+fn temp_path(input: &Path, extension: &str) -> PathBuf {
+    let stem = input.file_stem().and_then(|s| s.to_str()).unwrap_or("out");
+    let name = format!("duckyc-{}-{}", unsafe { libc::getpid() }, stem);
+    env::temp_dir().join(&name).with_extension(extension)
+}
+
+#[cfg(test)]
+mod test {
+    use driver::*;
+    use lexer;
+    use parser;
+    use scope;
+    use gen;
+    use diagnostic::Kind;
+    use std::fs::{self, File};
+    use std::io::{Read, Write};
+    use std::path::Path;
+    use std::process;
+    use infer;
+    use super::{temp_path, type_lines, check_output};
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn defaults_to_build() {
+        let opts = parse_args(&args(&["foo.dk"])).unwrap();
+        assert_eq!(opts.command, Command::Build);
+        assert_eq!(opts.input, Some("foo.dk".to_string()));
+        assert!(opts.emit.is_empty());
+    }
+
+    #[test]
+    fn parses_commands_and_emit() {
+        let opts = parse_args(&args(&["check", "--emit=tokens,types", "foo.dk"])).unwrap();
+        assert_eq!(opts.command, Command::Check);
+        assert_eq!(opts.emit, vec![Emit::Tokens, Emit::Types]);
+
+        let opts = parse_args(&args(&["build", "--emit=llvm-ir", "-o", "out.ll", "foo.dk"])).unwrap();
+        assert_eq!(opts.command, Command::Build);
+        assert_eq!(opts.output, Some("out.ll".to_string()));
+    }
+
+    #[test]
+    fn doesnt_overwrite_input() {
+        assert!(check_output(Path::new("prog"), Path::new("prog")).is_err());
+        assert!(check_output(Path::new("prog.dk"), &Path::new("prog.dk").with_extension("")).is_ok());
+
+        // The default executable for an input without an extension is the input
+        let input = temp_path(Path::new("noext"), "");
+        File::create(&input).and_then(|mut f| f.write_all(b"1;")).unwrap();
+        assert_eq!(main(&args(&["build", input.to_str().unwrap()])), 1);
+        let mut source = String::new();
+        File::open(&input).and_then(|mut f| f.read_to_string(&mut source)).unwrap();
+        assert_eq!(source, "1;");
+        let _ = fs::remove_file(&input);
+    }
+
+    fn types(code: &str) -> Vec<String> {
+        let tokens = lexer::lex(code).unwrap();
+        let ast = parser::parse_program(&mut parser::State::new(tokens.as_slice())).unwrap();
+        let scoped = scope::scoped_block(&mut scope::Scope::new(), ast.as_slice()).unwrap();
+        type_lines(&infer::infer_program(scoped).unwrap())
+    }
+
+    #[test]
+    fn emits_types() {
+        // Literals have union types, and the types of functions refer to type variables
+        let lines = types("let x = 1; let s = \"a\"; let f = fn(y) { y + x };");
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("x : Int"), "{}", lines[0]);
+        assert!(lines[1].starts_with("s : Str"), "{}", lines[1]);
+        assert!(lines[2].starts_with("f : (") && lines[2].contains(") -> "), "{}", lines[2]);
+    }
+
+    fn unsupported(code: &str) -> Vec<Kind> {
+        let tokens = lexer::lex(code).unwrap();
+        let ast = parser::parse_program(&mut parser::State::new(tokens.as_slice())).unwrap();
+        let scoped = scope::scoped_block(&mut scope::Scope::new(), ast.as_slice()).unwrap();
+        gen::unsupported(scoped.as_slice()).iter().map(|diag| diag.kind).collect()
+    }
+
+    #[test]
+    fn rejects_unsupported_codegen() {
+        assert_eq!(unsupported("let f = fn(x) { x + 1 }; f(1)"), vec![]);
+        assert_eq!(unsupported("let f = fn(x) { match x { n: Int => n, _ => 0 } }"),
+                   vec![Kind::UnsupportedByBackend]);
+        assert_eq!(unsupported("let r = { a: 1 }; r with { b: 2 }"), vec![Kind::UnsupportedByBackend]);
+    }
+
+    /// Builds the program with the LLVM backend and runs it, returning whether
+    /// it succeeded. Programs check their results by indexing an empty list,
+    /// which exits with an error, when they are wrong.
+    fn build_and_run(name: &str, code: &str) -> bool {
+        let input = temp_path(Path::new(name), "dk");
+        let exe = temp_path(Path::new(name), "");
+        File::create(&input).and_then(|mut f| f.write_all(code.as_bytes())).unwrap();
+
+        let input = input.to_str().unwrap().to_string();
+        let output = exe.to_str().unwrap().to_string();
+        assert_eq!(main(&args(&["build", "-o", output.as_slice(), input.as_slice()])), 0);
+        process::Command::new(&exe).status().unwrap().success()
+    }
+
+    // These need llvm, clang and libgc, so they are only run with --ignored
+    #[test]
+    #[ignore]
+    fn builds_recursive_bindings() {
+        assert!(build_and_run("fib", stringify!{
+            let fib = fn(n) { if n < 2 { n } else { fib(n - 1) + fib(n - 2) } };
+            if fib(15) != 610 { [][0] };
+        }));
+
+        assert!(build_and_run("even_odd", stringify!{
+            let even = fn(n) { if n == 0 { true } else { odd(n - 1) } };
+            let odd = fn(n) { if n == 0 { false } else { even(n - 1) } };
+            if !even(10) { [][0] };
+            if odd(10) { [][0] };
+        }));
+    }
+
+    #[test]
+    #[ignore]
+    fn builds_conversions() {
+        assert!(build_and_run("conversions", stringify!{
+            let i = 12;
+            let f = 2.5;
+            if i:to_str() != "12" { [][0] };
+            if f:to_str() != "2.5" { [][0] };
+            if f:to_int() != 2 { [][0] };
+            if i:to_float() != 12.0 { [][0] };
+            if true:to_str() != "true" { [][0] };
+        }));
+    }
+
+    #[test]
+    fn rejects_bad_args() {
+        assert!(parse_args(&args(&[])).is_err());
+        assert!(parse_args(&args(&["--emit=exe", "foo.dk"])).is_err());
+        assert!(parse_args(&args(&["check", "--emit=obj", "foo.dk"])).is_err());
+        assert!(parse_args(&args(&["run", "--emit=llvm-ir", "foo.dk"])).is_err());
+        assert!(parse_args(&args(&["foo.dk", "bar.dk"])).is_err());
+        assert!(parse_args(&args(&["repl", "foo.dk"])).is_err());
+        assert!(parse_args(&args(&["repl"])).is_ok());
+        assert!(parse_args(&args(&["--emit=llvm-ir,obj", "-o", "out", "foo.dk"])).is_err());
+        assert!(parse_args(&args(&["--emit=llvm-ir,obj", "foo.dk"])).is_ok());
+    }
+}
@@ -0,0 +1,198 @@
+use std::io::{self, Write};
+use std::iter::repeat;
+use std::cmp;
+use diagnostic::{Diagnostic, Label, Level};
+
+// ANSI escape codes used when colour is enabled
+const RESET: &'static str = "\x1b[0m";
+const BOLD: &'static str = "\x1b[1m";
+const RED: &'static str = "\x1b[1;31m";
+const YELLOW: &'static str = "\x1b[1;33m";
+const GREEN: &'static str = "\x1b[1;32m";
+const BLUE: &'static str = "\x1b[1;34m";
+
+/// The Emitter renders diagnostics in a rustc-like style, showing the
+/// lines of the source program which the labels point at, e.g.
+///
+/// ```text
+/// error[E0201]: Use of undeclared variable: y
+///  --> test.dk:2:5
+///   |
+/// 2 | x + y
+///   |     ^ not found in this scope
+/// ```
+pub struct Emitter<'a> {
+    filename: &'a str,
+    source: &'a str,
+    color: bool,
+}
+
+impl<'a> Emitter<'a> {
+    pub fn new(filename: &'a str, source: &'a str, color: bool) -> Emitter<'a> {
+        Emitter{ filename: filename, source: source, color: color }
+    }
+
+    /// Writes the rendered diagnostic to stderr
+    pub fn emit(&self, diag: &Diagnostic) {
+        // If we can't write to stderr, there isn't anywhere else to complain to
+        let _ = io::stderr().write_all(self.render(diag).as_bytes());
+    }
+
+    pub fn render(&self, diag: &Diagnostic) -> String {
+        let mut out = String::new();
+
+        // The header line: error[E0000]: message
+        let level_color = match diag.level {
+            Level::Error => RED,
+            Level::Warning => YELLOW,
+            Level::Note => GREEN,
+        };
+        out.push_str(&self.paint(level_color, &format!("{}[{}]", diag.level, diag.code())));
+        out.push_str(&self.paint(BOLD, &format!(": {}", diag.message)));
+        out.push('\n');
+
+        // Labels which the compiler made up don't point at anything we can show
+        let labels: Vec<&Label> = diag.labels.iter().filter(|l| ! l.span.is_dummy()).collect();
+
+        let gutter = labels.iter().map(|l| l.span.line).max()
+            .map(|line| line.to_string().len()).unwrap_or(0);
+        let pad: String = repeat(' ').take(gutter).collect();
+
+        let location = labels.iter().find(|l| l.primary).or(labels.first()).map(|l| l.span);
+        if let Some(span) = location {
+            out.push_str(&format!("{}{} {}:{}:{}\n",
+                                  pad, self.paint(BLUE, "-->"), self.filename, span.line, span.col));
+        }
+
+        if ! labels.is_empty() {
+            let mut lines: Vec<u32> = labels.iter().map(|l| l.span.line).collect();
+            lines.sort();
+            lines.dedup();
+
+            out.push_str(&format!("{} {}\n", pad, self.paint(BLUE, "|")));
+            for &line in lines.iter() {
+                let text = self.line_text(line);
+                out.push_str(&format!("{} {} {}\n",
+                                      self.paint(BLUE, &format!("{:>1$}", line, gutter)),
+                                      self.paint(BLUE, "|"), text));
+
+                // Primary labels are shown before secondary ones
+                let mut on_line: Vec<&&Label> = labels.iter().filter(|l| l.span.line == line).collect();
+                on_line.sort_by(|a, b| b.primary.cmp(&a.primary));
+
+                for label in on_line.iter() {
+                    out.push_str(&format!("{} {} {}\n",
+                                          pad, self.paint(BLUE, "|"), self.underline(label, text)));
+                }
+            }
+        }
+
+        for note in diag.notes.iter() {
+            out.push_str(&format!("{} {} note: {}\n", pad, self.paint(BLUE, "="), note));
+        }
+
+        out
+    }
+
+    fn paint(&self, style: &str, text: &str) -> String {
+        if self.color {
+            format!("{}{}{}", style, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+
+    fn line_text(&self, line: u32) -> &'a str {
+        self.source.lines().nth(line as usize - 1).unwrap_or("")
+    }
+
+    /// Produces the `^^^ message` line for a label, lined up under the
+    /// text of the line which the label's span starts on.
+    fn underline(&self, label: &Label, text: &str) -> String {
+        // Spans can start past the end of the line, such as at the end of the
+        // file, in which case the marks go just after the last character
+        let len = text.chars().count();
+        let col = cmp::min(label.span.col as usize - 1, len);
+
+        // Keep tabs so that the marks line up with the source line
+        let indent: String = text.chars().take(col).map(|c| {
+            if c == '\t' { '\t' } else { ' ' }
+        }).collect();
+
+        // Spans which cover multiple lines are only underlined on the first
+        let lo = cmp::min(label.span.lo, self.source.len());
+        let hi = cmp::min(cmp::max(label.span.hi, lo), self.source.len());
+        let width = self.source[lo..hi].chars()
+            .take_while(|&c| c != '\n').count();
+        let width = cmp::max(cmp::min(width, len - col), 1);
+
+        let (mark, color) = if label.primary { ('^', RED) } else { ('-', BLUE) };
+        let marks: String = repeat(mark).take(width).collect();
+
+        if label.message.is_empty() {
+            format!("{}{}", indent, self.paint(color, &marks))
+        } else {
+            format!("{}{}", indent, self.paint(color, &format!("{} {}", marks, label.message)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use emitter::Emitter;
+    use diagnostic::{Diagnostic, Kind};
+    use span::Span;
+
+    #[test]
+    fn renders_source_line() {
+        let source = "let x = 10;\nx + y";
+        let diag = Diagnostic::error(Kind::UndeclaredVariable,
+                                     format!("Use of undeclared variable: y"))
+            .with_primary(Span{ lo: 16, hi: 17, line: 2, col: 5, end_line: 2, end_col: 6 }, "not found in this scope");
+
+        let rendered = Emitter::new("test.dk", source, false).render(&diag);
+        assert_eq!(rendered.as_slice(), "\
+error[E0201]: Use of undeclared variable: y
+ --> test.dk:2:5
+  |
+2 | x + y
+  |     ^ not found in this scope
+");
+    }
+
+    #[test]
+    fn renders_secondary_labels_and_notes() {
+        let source = "let f = 10;\nf(1)";
+        let diag = Diagnostic::error(Kind::MissingProperty, format!("Missing property `call`"))
+            .with_primary(Span{ lo: 12, hi: 16, line: 2, col: 1, end_line: 2, end_col: 5 }, "in this method call")
+            .with_secondary(Span{ lo: 12, hi: 13, line: 2, col: 1, end_line: 2, end_col: 2 }, "`call` is called on this value")
+            .with_note(format!("required by type {{}}"));
+
+        let rendered = Emitter::new("test.dk", source, false).render(&diag);
+        assert_eq!(rendered.as_slice(), "\
+error[E0302]: Missing property `call`
+ --> test.dk:2:1
+  |
+2 | f(1)
+  | ^^^^ in this method call
+  | - `call` is called on this value
+  = note: required by type {}
+");
+    }
+
+    #[test]
+    fn renders_spans_past_the_end_of_the_line() {
+        let source = "let x = (1";
+        let diag = Diagnostic::error(Kind::UnexpectedToken, format!("Unexpected end of file"))
+            .with_primary(Span{ lo: 10, hi: 12, line: 1, col: 11, end_line: 1, end_col: 13 }, "expected `)`");
+
+        let rendered = Emitter::new("test.dk", source, false).render(&diag);
+        assert_eq!(rendered.as_slice(), "\
+error[E0101]: Unexpected end of file
+ --> test.dk:1:11
+  |
+1 | let x = (1
+  |           ^ expected `)`
+");
+    }
+}
@@ -5,6 +5,8 @@
 // I will add utility functions as I find them handy in here.
 
 use std::ops::{Deref, DerefMut};
+use std::ffi::CStr;
+use std::ptr;
 use self::ffi::*;
 
 
@@ -229,6 +231,25 @@ impl Module {
     pub unsafe fn dump(self) {
         LLVMDumpModule(*self);
     }
+
+    pub unsafe fn print_to_file(self, path: &str) -> Result<(), String> {
+        let mut err = ptr::null_mut();
+        if LLVMPrintModuleToFile(*self, cstr!(path), &mut err) != 0 {
+            let msg = String::from_utf8_lossy(CStr::from_ptr(err).to_bytes()).into_owned();
+            LLVMDisposeMessage(err);
+            Err(msg)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub unsafe fn write_bitcode_to_file(self, path: &str) -> Result<(), String> {
+        if LLVMWriteBitcodeToFile(*self, cstr!(path)) != 0 {
+            Err(format!("Couldn't write bitcode to {}", path))
+        } else {
+            Ok(())
+        }
+    }
 }
 
 impl Type {
@@ -248,6 +269,14 @@ impl Type {
         Value::new(LLVMConstReal(*self, n))
     }
 
+    pub unsafe fn const_null(self) -> Value {
+        Value::new(LLVMConstNull(*self))
+    }
+
+    pub unsafe fn get_undef(self) -> Value {
+        Value::new(LLVMGetUndef(*self))
+    }
+
     pub unsafe fn dump(self) {
         LLVMDumpType(*self);
     }
@@ -262,6 +291,17 @@ impl Value {
         Type::new(LLVMTypeOf(*self))
     }
 
+    /// The nth parameter of a function
+    pub unsafe fn get_param(self, n: u32) -> Value {
+        Value::new(LLVMGetParam(*self, n))
+    }
+
+    /// Adds incoming values to a phi node, one for each block
+    pub unsafe fn add_incoming(self, values: &[Value], blocks: &[BasicBlock]) {
+        assert_eq!(values.len(), blocks.len());
+        LLVMAddIncoming(*self, values.as_ptr() as *mut _, blocks.as_ptr() as *mut _, values.len() as u32)
+    }
+
     pub unsafe fn dump(self) {
         LLVMDumpValue(*self);
     }
@@ -298,6 +338,34 @@ impl Builder {
         Value::new(LLVMBuildBitCast(*self, *val, *ty, cstr!(name)))
     }
 
+    pub unsafe fn build_trunc(self, val: Value, ty: Type, name: &str) -> Value {
+        Value::new(LLVMBuildTrunc(*self, *val, *ty, cstr!(name)))
+    }
+
+    pub unsafe fn build_zext(self, val: Value, ty: Type, name: &str) -> Value {
+        Value::new(LLVMBuildZExt(*self, *val, *ty, cstr!(name)))
+    }
+
+    pub unsafe fn build_ptr_to_int(self, val: Value, ty: Type, name: &str) -> Value {
+        Value::new(LLVMBuildPtrToInt(*self, *val, *ty, cstr!(name)))
+    }
+
+    pub unsafe fn build_insert_value(self, agg: Value, val: Value, index: u32, name: &str) -> Value {
+        Value::new(LLVMBuildInsertValue(*self, *agg, *val, index, cstr!(name)))
+    }
+
+    pub unsafe fn build_br(self, dest: BasicBlock) -> Value {
+        Value::new(LLVMBuildBr(*self, *dest))
+    }
+
+    pub unsafe fn build_cond_br(self, cond: Value, then: BasicBlock, els: BasicBlock) -> Value {
+        Value::new(LLVMBuildCondBr(*self, *cond, *then, *els))
+    }
+
+    pub unsafe fn build_phi(self, ty: Type, name: &str) -> Value {
+        Value::new(LLVMBuildPhi(*self, *ty, cstr!(name)))
+    }
+
     pub unsafe fn build_global_string(self, string: &str, name: &str) -> Value {
         Value::new(LLVMBuildGlobalString(*self, cstr!(string), cstr!(name)))
     }
@@ -309,6 +377,17 @@ impl Builder {
     pub unsafe fn position_builder_at_end(self, block: BasicBlock) {
         LLVMPositionBuilderAtEnd(*self, *block)
     }
+
+    pub unsafe fn get_insert_block(self) -> BasicBlock {
+        BasicBlock::new(LLVMGetInsertBlock(*self))
+    }
+}
+
+impl BasicBlock {
+    /// The function which contains this block
+    pub unsafe fn get_parent(self) -> Value {
+        Value::new(LLVMGetBasicBlockParent(*self))
+    }
 }
 
 pub unsafe fn function_type(return_type: Type, param_types: &[Type], is_var_arg: bool) -> Type {
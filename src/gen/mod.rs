@@ -1,6 +1,10 @@
 use std::iter::repeat;
-use std::collections::{HashMap, VecDeque};
+use std::mem;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::Path;
 use il::*;
+use span::Span;
+use diagnostic::{Diagnostic, Kind};
 
 #[cfg(test)]
 mod test;
@@ -13,11 +17,22 @@ struct SymbolTable {
     counter: u64
 }
 
+// The symbols of the builtin methods, which the runtime looks up by their
+// numbers. These must be in the same order as the SYM_ defines in rt.c.
+const BUILTIN_SYMBOLS: [&'static str; 25] = [
+    "call", "+", "-", "*", "/", "%", "==", "!=", "<", "<=", ">", ">=",
+    "negate", "not", "len", "get", "push", "map", "fold", "&", "|", "^", "to_str",
+    "to_int", "to_float"];
+
 impl SymbolTable {
     unsafe fn new() -> SymbolTable {
-        SymbolTable {
+        let mut st = SymbolTable {
             symbols: HashMap::new(), counter: 0
+        };
+        for name in BUILTIN_SYMBOLS.iter() {
+            st.lookup(Symbol::from_slice(name));
         }
+        st
     }
 
     unsafe fn lookup(&mut self, s: Symbol) -> u64 {
@@ -38,7 +53,8 @@ enum ValueTag {
     UINT32 = 2,
     BOOL = 3,
     STRING = 4,
-    NULL = 5
+    NULL = 5,
+    LIST = 6
 }
 
 #[derive(Clone)]
@@ -67,21 +83,12 @@ impl Value {
     unsafe fn mk_val_struct(ctx: &mut GenContext,
                      tag: ValueTag,
                      data: llvm::Value) -> Value {
-        let unk = ctx.builder.build_alloca(ctx.value_type(), "unk_value_struct");
-
-        ctx.builder.build_store(
+        let unk = ctx.builder.build_insert_value(
+            ctx.value_type().get_undef(),
             ctx.ctx.int8_type().const_int(tag as u64, false),
-            ctx.builder.build_gep(unk, &[
-                ctx.ctx.int32_type().const_int(0, false),
-                ctx.ctx.int32_type().const_int(0, false)], "value_tag"));
-
-        ctx.builder.build_store(
-            data,
-            ctx.builder.build_gep(unk, &[
-                ctx.ctx.int32_type().const_int(0, false),
-                ctx.ctx.int32_type().const_int(1, false)], "data"));
+            0, "value_tag");
 
-        Value::Unk{ ll: unk }
+        Value::Unk{ ll: ctx.builder.build_insert_value(unk, data, 1, "unk_value_struct") }
     }
 
     unsafe fn to_unk(&self, ctx: &mut GenContext) -> Value {
@@ -94,17 +101,15 @@ impl Value {
                 Value::mk_val_struct(ctx, ValueTag::DOUBLE, ll)
             }
             Value::KString{ll, len:_} => {
-                let ll = ctx.bit_cast(ll, i64t);
+                let ll = ctx.builder.build_ptr_to_int(ll, i64t, "str_as_bytes");
                 Value::mk_val_struct(ctx, ValueTag::STRING, ll)
             }
             Value::KBool{ll} => {
-                let ll = ctx.bit_cast(ll, i64t);
+                let ll = ctx.builder.build_zext(ll, i64t, "bool_as_bytes");
                 Value::mk_val_struct(ctx, ValueTag::BOOL, ll)
             }
-            Value::KRec{ll, ..} => {
-                let ll = ctx.bit_cast(ll, i64t);
-                Value::mk_val_struct(ctx, ValueTag::RECORD, ll)
-            },
+            // allocRecord already produces a value
+            Value::KRec{ll, ..} => Value::Unk{ll: ll},
             Value::KNull => {
                 let zero = ctx.ctx.int64_type().const_int(0, false);
                 Value::mk_val_struct(ctx, ValueTag::NULL, zero)
@@ -149,17 +154,6 @@ impl RecDef {
 
         rd
     }
-    //| The memory footprint of the record definition
-    unsafe fn size(&self) -> u64 {
-        const HEADER_SIZE: u64 = 8;
-        const PROP_SIZE: u64 = 12;
-        const METHOD_SIZE: u64 = 12;
-        let props_size = PROP_SIZE * self.props.len() as u64;
-        let mthds_size = METHOD_SIZE * self.mthds.len() as u64;
-
-        HEADER_SIZE + props_size + mthds_size
-    }
-
     unsafe fn add_prop(&mut self, symb: Symbol, offset: u64) {
         self.props.insert(symb, offset);
     }
@@ -189,7 +183,7 @@ impl RecDef {
                 let mut i = (sti as usize) % self.props.len();
                 loop {
                     if props[2*i].is_none() {
-                        props[2*i] = Some(i32t.const_int(sti, false));
+                        props[2*i] = Some(i64t.const_int(sti, false));
                         props[2*i+1] = Some(i64t.const_int(*offset, false));
                         break;
                     } else {
@@ -211,7 +205,7 @@ impl RecDef {
                 let mut i = (sti as usize) % self.mthds.len();
                 loop {
                     if mthds[2*i].is_none() {
-                        mthds[2*i] = Some(i32t.const_int(sti, false));
+                        mthds[2*i] = Some(i64t.const_int(sti, false));
                         mthds[2*i+1] = Some(*func);
                         break;
                     } else {
@@ -266,22 +260,26 @@ struct Method {
     // record: Record,
     params: Vec<Ident>,
     body: Expr,
+    // The variables in the record's env, in order
+    captures: Vec<Ident>,
     implementation: Option<llvm::Value>,
     built: bool
 }
 
 impl Method {
-    unsafe fn new(params: Vec<Ident>, body: Expr) -> Method {
+    unsafe fn new(params: Vec<Ident>, body: Expr, captures: Vec<Ident>) -> Method {
         Method{
             params: params,
             body: body,
+            captures: captures,
             implementation: None,
             built: false
         }
     }
 
     unsafe fn get_function(&mut self, ctx: &mut GenContext) -> llvm::Value {
-        let param_tys: Vec<_> = repeat(ctx.value_type()).take(self.params.len()).collect();
+        // Methods take the record which they were called on before their arguments
+        let param_tys: Vec<_> = repeat(ctx.value_type()).take(self.params.len() + 1).collect();
 
         if self.implementation.is_none() {
             self.implementation = Some(ctx.module.add_function(
@@ -297,17 +295,37 @@ impl Method {
     unsafe fn gen(&mut self, ctx: &mut GenContext) -> llvm::Value {
         let decl = self.get_function(ctx);
         if self.built { return decl }
+        self.built = true;
 
         // Create the basic block for the function!
         let fn_body = ctx.ctx.append_basic_block(decl, "function_body");
         ctx.builder.position_builder_at_end(fn_body);
 
+        // The body can only see the variables which the record captured, and
+        // the method's parameters
+        let mut vars = HashMap::new();
+        if ! self.captures.is_empty() {
+            let env = ctx.builder.build_call(ctx.bi_record_env(), &[decl.get_param(0)], "env");
+            for (i, var) in self.captures.iter().enumerate() {
+                let slot = ctx.builder.build_in_bounds_gep(
+                    env, &[ctx.ctx.int64_type().const_int(i as u64, false)], "env_slot");
+                vars.insert(var.clone(), ctx.builder.build_load(slot, "captured_cell"));
+            }
+        }
+        for (i, param) in self.params.iter().enumerate() {
+            let cell = ctx.alloc_cell();
+            ctx.builder.build_store(decl.get_param(i as u32 + 1), cell);
+            vars.insert(param.clone(), cell);
+        }
+        let outer_vars = mem::replace(&mut ctx.vars, vars);
+
         // Generate the function's body
         let ret_val = gen_expr(&self.body, ctx);
 
         // Return the resulting value from the function
         ctx.builder.build_ret(ret_val.to_unk_ll(ctx));
 
+        ctx.vars = outer_vars;
         decl
     }
 }
@@ -318,17 +336,21 @@ struct GenContext {
     module: llvm::Module,
     method_queue: VecDeque<Method>,
     symbol_table: SymbolTable,
+    // The cells which hold the variables in scope
+    vars: HashMap<Ident, llvm::Value>,
+    // The blocks which `continue` and `break` jump to, for each enclosing loop
+    loops: Vec<(llvm::BasicBlock, llvm::BasicBlock)>,
 }
 
 macro_rules! builtin_func {
-    ($rustname:ident, $cname:expr, $slf:ident, $return_ty: expr, $($pty: expr),+) => {
+    ($rustname:ident, $cname:expr, $slf:ident, $return_ty: expr $(, $pty: expr)*) => {
         // The function which will fetch it for you
         unsafe fn $rustname(&self) -> llvm::Value {
             let $slf = self;
             match self.module.get_named_function($cname) {
                 Some(x) => x,
                 None => {
-                    let func_type = llvm::function_type($return_ty, &[$($pty),+], false);
+                    let func_type = llvm::function_type($return_ty, &[$($pty),*], false);
                     let function = self.module.add_function($cname, func_type);
                     // assert_eq!(self.module.get_named_function($cname), function);
 
@@ -386,29 +408,144 @@ impl  GenContext {
         self.ctx.int64_type()
     }
 
+    // The cells which a record's methods close over
+    unsafe fn env_type(&self) -> llvm::Type {
+        self.value_type().pointer().pointer()
+    }
+
     builtin_func!(bi_alloc_record, "allocRecord", this,
                   this.value_type(),
+                  this.ctx.int8_type().pointer(), this.env_type());
+
+    builtin_func!(bi_init_property, "initProperty", this,
+                  this.ctx.void_type(),
+                  this.value_type(), this.ctx.int64_type(), this.value_type());
+
+    builtin_func!(bi_record_env, "recordEnv", this,
+                  this.env_type(),
+                  this.value_type());
+
+    builtin_func!(bi_alloc_cell, "allocCell", this,
+                  this.value_type().pointer());
+
+    builtin_func!(bi_alloc_env, "allocEnv", this,
+                  this.env_type(),
                   this.ctx.int64_type());
 
+    builtin_func!(bi_value_as_bool, "valueAsBool", this,
+                  this.ctx.int32_type(),
+                  this.value_type());
+
+    builtin_func!(bi_value_is_null, "valueIsNull", this,
+                  this.ctx.int32_type(),
+                  this.value_type());
+
     builtin_func!(bi_get_property, "getProperty", this,
                   this.value_type(),
                   this.value_type(), this.symbol_type());
 
+    builtin_func!(bi_set_property, "setProperty", this,
+                  this.value_type(),
+                  this.value_type(), this.symbol_type(), this.value_type());
+
     builtin_func!(bi_get_method, "getMethod", this,
                   this.ctx.int8_type().pointer(),
                   this.value_type(), this.symbol_type());
 
-    unsafe fn bit_cast(&self, value: llvm::Value, ty: llvm::Type) -> llvm::Value {
-        value.dump();
-        ty.dump();
+    builtin_func!(bi_alloc_list, "allocList", this,
+                  this.value_type(),
+                  this.ctx.int64_type());
+
+    builtin_func!(bi_list_push, "listPush", this,
+                  this.value_type(),
+                  this.value_type(), this.value_type());
 
+    unsafe fn bit_cast(&self, value: llvm::Value, ty: llvm::Type) -> llvm::Value {
         self.builder.build_bit_cast(value, ty, "num_as_bytes")
     }
+
+    /// Calls the method symb on recv, which is passed to it before args
+    unsafe fn call_method(&mut self, recv: llvm::Value, symb: Symbol, args: &[llvm::Value]) -> llvm::Value {
+        // TODO(michael): Directly index known types,
+        // rather than performing expensive lookups
+        let symbol_ll = self.symbol_table.lookup(symb);
+        let method_ll = self.builder.build_call(
+            self.bi_get_method(),
+            &[recv,
+                  self.ctx.int64_type().const_int(symbol_ll, false)],
+            "get_method");
+
+        // Determine the function type we want
+        let ptypes: Vec<llvm::Type> = repeat(self.value_type()).take(args.len() + 1).collect();
+        let ftype = llvm::function_type(
+            self.value_type(),
+            &ptypes,
+            false);
+
+        let method_ll = self.builder.build_bit_cast(
+            method_ll,
+            ftype.pointer(),
+            "typed_method");
+
+        let mut args_ll = Vec::with_capacity(args.len() + 1);
+        args_ll.push(recv);
+        args_ll.push_all(args);
+        self.builder.build_call(method_ll, &args_ll, "method_result")
+    }
+
+    /// Converts the value of a condition into an i1 for branching on
+    unsafe fn gen_cond(&mut self, cond: &Expr) -> llvm::Value {
+        let cond_ll = gen_expr(cond, self).to_unk_ll(self);
+        let cond_ll = self.builder.build_call(self.bi_value_as_bool(), &[cond_ll], "cond");
+        self.builder.build_trunc(cond_ll, self.ctx.int1_type(), "cond_bit")
+    }
+
+    /// Starts a new block after a jump, such as `break` or `return`. Anything
+    /// after the jump is unreachable, but it still has to be generated into a
+    /// block which doesn't have a terminator yet.
+    unsafe fn start_unreachable_block(&self, name: &str) {
+        let function = self.builder.get_insert_block().get_parent();
+        let block = self.ctx.append_basic_block(function, name);
+        self.builder.position_builder_at_end(block);
+    }
+
+    /// Allocates a cell on the heap to hold a variable
+    unsafe fn alloc_cell(&self) -> llvm::Value {
+        self.builder.build_call(self.bi_alloc_cell(), &[], "cell")
+    }
+
+    /// Allocates the env for a record, containing the cells of the captured variables
+    unsafe fn gen_env(&self, captures: &[Ident]) -> llvm::Value {
+        if captures.is_empty() {
+            return self.env_type().const_null();
+        }
+
+        let i64t = self.ctx.int64_type();
+        let env = self.builder.build_call(
+            self.bi_alloc_env(), &[i64t.const_int(captures.len() as u64, false)], "env");
+        for (i, var) in captures.iter().enumerate() {
+            let slot = self.builder.build_in_bounds_gep(env, &[i64t.const_int(i as u64, false)], "env_slot");
+            self.builder.build_store(self.vars[var.clone()], slot);
+        }
+        env
+    }
+}
+
+/// The variables which the methods of a record literal close over
+fn captured_vars(props: &[Prop], ctx: &GenContext) -> Vec<Ident> {
+    let mut vars = HashSet::new();
+    for prop in props.iter() {
+        if let Prop::Method(_, _, _, ref body, _) = *prop {
+            body.referenced_vars(&mut vars);
+        }
+    }
+    // The method's own parameters and locals aren't in scope yet
+    vars.into_iter().filter(|var| ctx.vars.contains_key(var)).collect()
 }
 
 unsafe fn gen_expr(e: &Expr, ctx: &mut GenContext) -> Value {
-    match *e {
-        Expr::Literal(ref lit) => {
+    match e.kind {
+        ExprKind::Literal(ref lit) => {
             match *lit {
                 Literal::Str(ref atom) => {
                     Value::KString{
@@ -433,23 +570,31 @@ unsafe fn gen_expr(e: &Expr, ctx: &mut GenContext) -> Value {
                 }
             }
         }
-        Expr::Ident(ref id) => {
-            // TODO(michael): Add environments, and allow looking up vars
-            Value::Unk{
-                ll: unimplemented!()
+        ExprKind::Ident(ref id) => {
+            match ctx.vars.get(id).cloned() {
+                Some(cell) => Value::Unk{ ll: ctx.builder.build_load(cell, "var") },
+                None => {
+                    match id.0.as_slice() {
+                        "null" => Value::KNull,
+                        _ => panic!("ICE: No cell for the variable {:?}", id),
+                    }
+                }
             }
         }
-        Expr::Rec(ref props) => {
+        ExprKind::Rec(ref props) => {
+            // The methods close over the variables which they refer to
+            let captures = captured_vars(props.as_slice(), ctx);
+
             // Create the record object
             let mut rec = Record::new();
             for prop in props.iter() {
                 match *prop {
-                    Prop::Val(ref s, ref expr) => {
+                    Prop::Val(ref s, ref expr, _, _) => {
                         let value = gen_expr(expr, ctx);
                         rec.add_prop(s.clone(), value);
                     }
-                    Prop::Method(ref s, ref args, ref body) => {
-                        let mthd = Method::new(args.clone(), body.clone());
+                    Prop::Method(ref s, ref args, _, ref body, _) => {
+                        let mthd = Method::new(args.clone(), body.clone(), captures.clone());
                         rec.add_mthd(s.clone(), mthd);
                     }
                 }
@@ -458,41 +603,49 @@ unsafe fn gen_expr(e: &Expr, ctx: &mut GenContext) -> Value {
             // Create the record definition object
             let mut rec_def = RecDef::new(&mut rec, ctx);
 
+            // The bodies of the methods are generated once the current function is done
+            for mthd in rec.mthds.values() {
+                ctx.method_queue.push_back(mthd.clone());
+            }
+
             // Allocate the record
+            let def_ptr = ctx.builder.build_bit_cast(
+                rec_def.gen(ctx), // Pointer to the record definition
+                ctx.ctx.int8_type().pointer(),
+                "record_def_ptr");
+            let env = ctx.gen_env(captures.as_slice());
             let alloced_rec = ctx.builder.build_call(
                 ctx.bi_alloc_record(),
-                &[ctx.ctx.int64_type().const_int(rec_def.size(), false)],
+                &[def_ptr, env],
                 "record");
 
-            let zero = ctx.ctx.int64_type().const_int(0, false);
             // Set the properties!
-            ctx.builder.build_store(
-                rec_def.gen(ctx), // Pointer to the record definition
-                ctx.builder.build_in_bounds_gep(
-                    alloced_rec,
-                    &[zero, zero],
-                    "record_def_ptr"));
-
-            let values_ptr = ctx.builder.build_bit_cast(
-                ctx.builder.build_in_bounds_gep(
-                    alloced_rec,
-                    &[ctx.ctx.int64_type().const_int(1, false)],
-                    "values_ptr_uncast"),
-                ctx.value_type().pointer(),
-                "values_ptr");
-
             for (symb, idx) in rec_def.props.iter() {
-                ctx.builder.build_store(
-                    rec.props[symb.clone()].to_unk_ll(ctx),
-                    ctx.builder.build_in_bounds_gep(
-                        values_ptr, &[
-                            ctx.ctx.int64_type().const_int(*idx, false)
-                                ], "value_ptr"));
+                let value = rec.props[symb.clone()].to_unk_ll(ctx);
+                ctx.builder.build_call(
+                    ctx.bi_init_property(),
+                    &[alloced_rec, ctx.ctx.int64_type().const_int(*idx, false), value],
+                    "");
             }
 
             Value::KRec{ll: alloced_rec, rec: rec}
         }
-        Expr::Member(ref obj, ref symb) => {
+        ExprKind::List(ref elems) => {
+            let list = ctx.builder.build_call(
+                ctx.bi_alloc_list(),
+                &[ctx.ctx.int64_type().const_int(elems.len() as u64, false)],
+                "list");
+
+            for elem in elems.iter() {
+                let elem_ll = gen_expr(elem, ctx).to_unk_ll(ctx);
+                ctx.builder.build_call(ctx.bi_list_push(), &[list, elem_ll], "list_push");
+            }
+
+            // TODO(michael): Calls on lists should go to listGet & co. rather
+            // than getMethod, once we know which values are lists
+            Value::Unk{ll: list}
+        }
+        ExprKind::Member(ref obj, ref symb) => {
             let objv = gen_expr(&**obj, ctx);
             // TODO(michael): Directly index known types,
             // rather than performing expensive lookups
@@ -507,53 +660,27 @@ unsafe fn gen_expr(e: &Expr, ctx: &mut GenContext) -> Value {
                     "get_property")
             }
         }
-        Expr::Call(ref obj, ref symb, ref args) => {
+        ExprKind::Call(ref obj, ref symb, ref args) => {
             let objv = gen_expr(&**obj, ctx);
-            // TODO(michael): Directly index known types,
-            // rather than performing expensive lookups
-
             let ll = objv.to_unk_ll(ctx);
-            let symbol_ll = ctx.symbol_table.lookup(symb.clone());
-            let get_method = ctx.bi_get_method();
-            get_method.dump();
-            ll.dump();
-            ctx.ctx.int64_type().const_int(symbol_ll, false).dump();
-
-            println!("Here");
-            let method_ll = ctx.builder.build_call(
-                get_method,
-                &[ll,
-                      ctx.ctx.int64_type().const_int(symbol_ll, false)],
-                "get_method");
-            println!("There");
-
-            // Determine the function type we want
-            let mut ptypes = Vec::with_capacity(args.len());
-            for _ in args.iter() { ptypes.push(ctx.value_type()); }
-            let ftype = llvm::function_type(
-                ctx.value_type(),
-                &ptypes,
-                false);
-
-
-            let method_ll = ctx.builder.build_bit_cast(
-                method_ll,
-                ftype.pointer(),
-                "typed_method");
 
             let mut args_ll = Vec::with_capacity(args.len());
             for arg in args.iter() {
                 args_ll.push(gen_expr(arg, ctx).to_unk_ll(ctx));
             }
 
-            Value::Unk{
-                ll: ctx.builder.build_call(
-                    method_ll,
-                    &args_ll,
-                    "method_result")
-            }
+            Value::Unk{ ll: ctx.call_method(ll, symb.clone(), &args_ll) }
         }
-        Expr::Block(ref body) => {
+        ExprKind::Block(ref body) => {
+            // Every binding in the block gets a cell before any of them are
+            // generated, so that closures can refer to later bindings
+            for stmt in body.iter() {
+                if let StmtKind::Let(ref id, _, _) = stmt.kind {
+                    let cell = ctx.alloc_cell();
+                    ctx.vars.insert(id.clone(), cell);
+                }
+            }
+
             let mut val = Value::KNull;
             for stmt in body.iter() {
                 val = gen_stmt(stmt, ctx);
@@ -561,25 +688,174 @@ unsafe fn gen_expr(e: &Expr, ctx: &mut GenContext) -> Value {
 
             val
         }
-        Expr::If(ref cond, ref cons, ref alt) => {
-            unimplemented!()
+        ExprKind::If(box ref cond, box ref cons, box ref alt) => {
+            let cond_ll = ctx.gen_cond(cond);
+
+            let function = ctx.builder.get_insert_block().get_parent();
+            let cons_block = ctx.ctx.append_basic_block(function, "then");
+            let alt_block = ctx.ctx.append_basic_block(function, "else");
+            let end_block = ctx.ctx.append_basic_block(function, "if_end");
+            ctx.builder.build_cond_br(cond_ll, cons_block, alt_block);
+
+            // The branches can finish in different blocks than the ones they started in
+            ctx.builder.position_builder_at_end(cons_block);
+            let cons_ll = gen_expr(cons, ctx).to_unk_ll(ctx);
+            let cons_end = ctx.builder.get_insert_block();
+            ctx.builder.build_br(end_block);
+
+            ctx.builder.position_builder_at_end(alt_block);
+            let alt_val = match *alt {
+                Some(ref alt) => gen_expr(alt, ctx),
+                None => Value::KNull,
+            };
+            let alt_ll = alt_val.to_unk_ll(ctx);
+            let alt_end = ctx.builder.get_insert_block();
+            ctx.builder.build_br(end_block);
+
+            ctx.builder.position_builder_at_end(end_block);
+            let phi = ctx.builder.build_phi(ctx.value_type(), "if_value");
+            phi.add_incoming(&[cons_ll, alt_ll], &[cons_end, alt_end]);
+            Value::Unk{ll: phi}
+        }
+        ExprKind::Match(..) => panic!("ICE: `match` is rejected by `unsupported`"),
+        ExprKind::While(box ref cond, box ref body) => {
+            let function = ctx.builder.get_insert_block().get_parent();
+            let cond_block = ctx.ctx.append_basic_block(function, "while_cond");
+            let body_block = ctx.ctx.append_basic_block(function, "while_body");
+            let end_block = ctx.ctx.append_basic_block(function, "while_end");
+            ctx.builder.build_br(cond_block);
+
+            ctx.builder.position_builder_at_end(cond_block);
+            let cond_ll = ctx.gen_cond(cond);
+            ctx.builder.build_cond_br(cond_ll, body_block, end_block);
+
+            ctx.builder.position_builder_at_end(body_block);
+            ctx.loops.push((cond_block, end_block));
+            gen_expr(body, ctx);
+            ctx.loops.pop();
+            ctx.builder.build_br(cond_block);
+
+            ctx.builder.position_builder_at_end(end_block);
+            Value::KNull
+        }
+        ExprKind::For(ref var, box ref iter, box ref body) => {
+            let iter_ll = gen_expr(iter, ctx).to_unk_ll(ctx);
+
+            let function = ctx.builder.get_insert_block().get_parent();
+            let next_block = ctx.ctx.append_basic_block(function, "for_next");
+            let body_block = ctx.ctx.append_basic_block(function, "for_body");
+            let end_block = ctx.ctx.append_basic_block(function, "for_end");
+            ctx.builder.build_br(next_block);
+
+            // The iterator is passed to its `next(self)`, which produces null at the end
+            ctx.builder.position_builder_at_end(next_block);
+            let item = ctx.call_method(iter_ll, Symbol::from_slice("next"), &[iter_ll]);
+            let is_null = ctx.builder.build_call(ctx.bi_value_is_null(), &[item], "is_null");
+            let is_null = ctx.builder.build_trunc(is_null, ctx.ctx.int1_type(), "is_null_bit");
+            ctx.builder.build_cond_br(is_null, end_block, body_block);
+
+            // Each iteration gets its own cell, so closures in the body keep their item
+            ctx.builder.position_builder_at_end(body_block);
+            let cell = ctx.alloc_cell();
+            ctx.builder.build_store(item, cell);
+            ctx.vars.insert(var.clone(), cell);
+
+            ctx.loops.push((next_block, end_block));
+            gen_expr(body, ctx);
+            ctx.loops.pop();
+            ctx.builder.build_br(next_block);
+
+            ctx.builder.position_builder_at_end(end_block);
+            Value::KNull
         }
+        ExprKind::Break | ExprKind::Continue => {
+            let (next_block, end_block) = *ctx.loops.last().expect("ICE: Loop control outside of a loop");
+            let target = if let ExprKind::Break = e.kind { end_block } else { next_block };
+            ctx.builder.build_br(target);
+
+            ctx.start_unreachable_block("after_jump");
+            Value::KNull
+        }
+        ExprKind::Return(ref value) => {
+            let ret_val = gen_expr(&**value, ctx);
+            ctx.builder.build_ret(ret_val.to_unk_ll(ctx));
+
+            ctx.start_unreachable_block("after_return");
+            Value::KNull
+        }
+        ExprKind::Assign(ref obj, ref symb, ref value) => {
+            let obj_ll = gen_expr(&**obj, ctx).to_unk_ll(ctx);
+            let value_ll = gen_expr(&**value, ctx).to_unk_ll(ctx);
+            let symbol_ll = ctx.symbol_table.lookup(symb.clone());
+            Value::Unk{
+                ll: ctx.builder.build_call(
+                    ctx.bi_set_property(),
+                    &[obj_ll,
+                        ctx.ctx.int64_type().const_int(symbol_ll, false),
+                        value_ll],
+                    "set_property")
+            }
+        }
+        ExprKind::Update(..) => {
+            // TODO(michael): Copy the base's properties into a new record
+            // layout, which requires getting the record def at runtime
+            panic!("ICE: `with` is rejected by `unsupported`")
+        }
+        ExprKind::Ascribe(ref expr, _) => gen_expr(expr, ctx),
+        ExprKind::Brand(_, ref rec) => gen_expr(rec, ctx),
+        ExprKind::Error => panic!("ICE: Generating code for a program with syntax errors"),
     }
 }
 
 
 unsafe fn gen_stmt(stmt: &Stmt, ctx: &mut GenContext) -> Value {
-    match *stmt {
-        Stmt::Let(ref id, ref expr) =>  {
-            unimplemented!()
+    match stmt.kind {
+        StmtKind::Let(ref id, _, ref expr) =>  {
+            // The cell was allocated when entering the block
+            let value = gen_expr(expr, ctx).to_unk_ll(ctx);
+            ctx.builder.build_store(value, ctx.vars[id.clone()]);
+            Value::KNull
+        }
+        StmtKind::Expr(ref expr) => gen_expr(expr, ctx),
+        StmtKind::Type(..) | StmtKind::Name(_) | StmtKind::Empty => Value::KNull
+    }
+}
+
+/// The expressions in the program which the backend can't generate code for
+/// yet. The driver reports these before generating any code.
+pub fn unsupported(stmts: &[Stmt]) -> Vec<Diagnostic> {
+    let mut diags = vec![];
+    let mut check = |e: &Expr| {
+        let what = match e.kind {
+            ExprKind::Match(..) => "`match` expressions are",
+            ExprKind::Update(..) => "Record updates with `with` are",
+            _ => return,
+        };
+        diags.push(Diagnostic::error(
+            Kind::UnsupportedByBackend,
+            format!("{} not supported by the LLVM backend", what))
+            .with_primary(e.span, "can't be compiled yet")
+            .with_note(format!("use `duckyc run` to run the program with the interpreter")));
+    };
+
+    for stmt in stmts.iter() {
+        match stmt.kind {
+            StmtKind::Let(_, _, ref expr) | StmtKind::Expr(ref expr) => expr.walk(&mut check),
+            StmtKind::Type(..) | StmtKind::Name(_) | StmtKind::Empty => {}
         }
-        Stmt::Expr(ref expr) => gen_expr(expr, ctx),
-        Stmt::Empty => Value::KNull
     }
+    diags
+}
+
+/// The formats which the generated module can be written out in
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum OutputKind {
+    LlvmIr,
+    Bitcode,
 }
 
-// TODO(michael): make this actually useful
-pub unsafe fn gen_code(ast: Vec<Stmt>) {
+/// Generates the code for the program, and writes the resulting module to `path`
+pub unsafe fn gen_code(ast: Vec<Stmt>, kind: OutputKind, path: &Path) -> Result<(), String> {
     let ctx = llvm::OwnedContext::new();
     let module = llvm::OwnedModule::new("module", *ctx);
     let builder = llvm::OwnedBuilder::new(*ctx);
@@ -591,26 +867,38 @@ pub unsafe fn gen_code(ast: Vec<Stmt>) {
         builder: *builder,
         module: *module,
         method_queue: method_queue,
-        symbol_table: symbol_table
+        symbol_table: symbol_table,
+        vars: HashMap::new(),
+        loops: vec![],
     };
 
-    // TODO(michael): Global variables and more!
-    // (and variables at all)
-
     // Create the main function!
     let main_function = module.add_function(
-        "__ducky_main",
+        "__ducky__main",
         llvm::function_type(ctx.void_type(), &[], false));
 
     let main_function_body = ctx.append_basic_block(main_function, "main_body");
     builder.position_builder_at_end(main_function_body);
 
     // Create the body for that main function!
-    let random_expr = Expr::Block(ast);
+    let random_expr = Expr::new(ExprKind::Block(ast), Span::dummy());
     // And generate it!
     gen_expr(&random_expr, &mut gc);
 
     builder.build_ret_void();
 
-    module.dump();
+    // Generate the methods of the records which were created. This can find
+    // more records, whose methods are generated after them.
+    while let Some(mut mthd) = gc.method_queue.pop_front() {
+        mthd.gen(&mut gc);
+    }
+
+    let path = match path.to_str() {
+        Some(path) => path,
+        None => return Err(format!("Output path {:?} is not valid unicode", path)),
+    };
+    match kind {
+        OutputKind::LlvmIr => module.print_to_file(path),
+        OutputKind::Bitcode => module.write_bitcode_to_file(path),
+    }
 }
@@ -8,9 +8,10 @@ use infer;
 use lexer;
 use parser;
 use scope;
+use diagnostic::Diagnostic;
 
 /// Compiles some code, and then does stuff.
-fn gen_code(code: &str) -> Result<(), String> {
+fn gen_code(code: &str) -> Result<(), Diagnostic> {
     // TODO: The program should probably panic if the error wasn't caused by infer_program(ast)
     // Because these tests are only supposed to be testing inference, not lexing/parsing
     let tokens = try!(lexer::lex(code));
@@ -1,5 +1,7 @@
 use intern::Atom;
+use span::{Span, Spanned};
 use std::fmt;
+use std::collections::{HashMap, HashSet};
 
 // TODO: Namespace Context
 pub use self::Context::*;
@@ -80,32 +82,45 @@ impl fmt::Debug for Symbol {
     }
 }
 
+/// Whether a field of a record can be assigned to with `<-`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mutability {
+    Immutable,
+    Mutable,
+}
+
 #[derive(Clone, PartialEq, Eq, Hash)]
 pub enum TyProp {
-    Val(Symbol, Ty),
+    Val(Symbol, Ty, Mutability),
     Method(Symbol, Vec<Ty>, Ty),
 }
 
 impl TyProp {
     pub fn symbol<'a>(&'a self) -> &'a Symbol {
         match *self {
-            TyProp::Val(ref s, _) => s,
+            TyProp::Val(ref s, _, _) => s,
             TyProp::Method(ref s, _, _) => s,
         }
     }
+
+    pub fn is_mutable(&self) -> bool {
+        if let TyProp::Val(_, _, Mutability::Mutable) = *self { true } else { false }
+    }
 }
 
 impl fmt::Debug for TyProp {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            TyProp::Val(ref symbol, ref ty) => {
+            TyProp::Val(ref symbol, ref ty, mutability) => {
+                if mutability == Mutability::Mutable { try!(write!(f, "mut ")); }
                 write!(f, "{:?}: {:?}", symbol, ty)
             }
             TyProp::Method(ref symbol, ref args, ref res) => {
                 // @TODO: This is terrible syntax, but must differentiate
                 // from ValTyProp
                 try!(write!(f, "fn {:?}(", symbol));
-                for arg in args.iter() {
+                for (i, arg) in args.iter().enumerate() {
+                    if i != 0 { try!(write!(f, ", ")); }
                     try!(write!(f, "{:?}", arg));
                 }
                 write!(f, ") -> {:?}", res)
@@ -114,11 +129,18 @@ impl fmt::Debug for TyProp {
     }
 }
 
+/// The name which a record was created with, if any. Only the code which
+/// declared the name can create records with it, so it can't be forged.
+pub type Brand = Option<Ident>;
+
 #[derive(Clone, PartialEq, Eq, Hash)]
 pub enum Ty {
     Ident(Ident),
-    Rec(Option<Box<Ty>>, Vec<TyProp>),
+    Rec(Option<Box<Ty>>, Brand, Vec<TyProp>),
     Union(Vec<Ty>),
+    /// A use of a type alias, with its type arguments. Aliases are only
+    /// expanded when their structure is needed, so they may be recursive.
+    Alias(Ident, Vec<Ty>),
 }
 
 impl Ty {
@@ -132,7 +154,7 @@ impl Ty {
     pub fn get_extends(&self) -> Option<&Ty> {
         match *self {
             Ty::Ident(_) => Some(self),
-            Ty::Rec(Some(box ref ty), _) => Some(ty),
+            Ty::Rec(Some(box ref ty), _, _) => Some(ty),
             _ => None,
         }
     }
@@ -148,26 +170,52 @@ impl Ty {
     pub fn is_union(&self) -> bool {
         if let Ty::Union(..) = *self { true } else { false }
     }
+
+    /// Replaces the identifiers in subs, such as the parameters of a type alias
+    pub fn subst(&self, subs: &HashMap<Ident, Ty>) -> Ty {
+        match *self {
+            Ty::Ident(ref id) => subs.get(id).cloned().unwrap_or_else(|| self.clone()),
+            Ty::Rec(ref extends, ref brand, ref props) => {
+                let extends = extends.as_ref().map(|x| box x.subst(subs));
+                let props = props.iter().map(|prop| {
+                    match *prop {
+                        TyProp::Val(ref symb, ref ty, mutability) => {
+                            TyProp::Val(symb.clone(), ty.subst(subs), mutability)
+                        }
+                        TyProp::Method(ref symb, ref args, ref res) => {
+                            TyProp::Method(symb.clone(),
+                                           args.iter().map(|x| x.subst(subs)).collect(),
+                                           res.subst(subs))
+                        }
+                    }
+                }).collect();
+
+                Ty::Rec(extends, brand.clone(), props)
+            }
+            Ty::Union(ref opts) => Ty::Union(opts.iter().map(|x| x.subst(subs)).collect()),
+            Ty::Alias(ref id, ref args) => {
+                Ty::Alias(id.clone(), args.iter().map(|x| x.subst(subs)).collect())
+            }
+        }
+    }
 }
 
 impl fmt::Debug for Ty {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Ty::Ident(ref id) => write!(f, "{:?}", id),
-            Ty::Rec(ref maybe_ty, ref props) => {
+            Ty::Rec(ref maybe_ty, ref brand, ref props) => {
                 if let Some(box ref ty) = *maybe_ty {
-                    try!(write!(f, "{:?}:{:?} ", ty, "{"));
-                    for prop in props.iter() {
-                        try!(write!(f, "{:?}, ", prop));
-                    }
-                    write!(f, "{:?}", "}")
-                } else {
-                    try!(write!(f, "{:?} ", "{"));
-                    for prop in props.iter() {
-                        try!(write!(f, "{:?}, ", prop));
-                    }
-                    write!(f, "{:?}", "}")
+                    try!(write!(f, "{:?}:", ty));
+                }
+                if let Some(ref brand) = *brand {
+                    try!(write!(f, "#{:?}#", brand));
+                }
+                try!(write!(f, "{{ "));
+                for prop in props.iter() {
+                    try!(write!(f, "{:?}, ", prop));
                 }
+                write!(f, "}}")
             }
             Ty::Union(ref options) => {
                 try!(write!(f, "("));
@@ -176,6 +224,127 @@ impl fmt::Debug for Ty {
                 }
                 write!(f, ")")
             }
+            Ty::Alias(ref id, ref args) => {
+                try!(write!(f, "{:?}", id));
+                if ! args.is_empty() {
+                    try!(write!(f, "{:?}", args));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+
+/// Types are displayed to the user in the notation of doc/types.md, e.g.
+/// `(a, { x: Int }) -> a{ y: Int }`. Internal type variables don't have
+/// meaningful names, so they are renamed to a, b, c... in the order in
+/// which they first appear.
+impl fmt::Display for Ty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        TyPrinter{ names: HashMap::new() }.ty(f, self, false)
+    }
+}
+
+struct TyPrinter {
+    names: HashMap<Ident, String>,
+}
+
+impl TyPrinter {
+    fn var_name(&mut self, id: &Ident) -> String {
+        if let Some(name) = self.names.get(id) {
+            return name.clone();
+        }
+
+        let n = self.names.len();
+        let letter = (b'a' + (n % 26) as u8) as char;
+        let name = if n < 26 { letter.to_string() } else { format!("{}{}", letter, n / 26) };
+        self.names.insert(id.clone(), name.clone());
+        name
+    }
+
+    fn tys(&mut self, f: &mut fmt::Formatter, tys: &[Ty]) -> fmt::Result {
+        for (i, ty) in tys.iter().enumerate() {
+            if i != 0 { try!(write!(f, ", ")); }
+            try!(self.ty(f, ty, false));
+        }
+        Ok(())
+    }
+
+    /// Unions which are nested inside of other types are parenthesized
+    fn ty(&mut self, f: &mut fmt::Formatter, ty: &Ty, nested: bool) -> fmt::Result {
+        match *ty {
+            Ty::Ident(ref id @ Ident(_, Internal(_))) => {
+                let name = self.var_name(id);
+                write!(f, "{}", name)
+            }
+            Ty::Ident(Ident(ref atom, _)) => write!(f, "{}", atom),
+            // The builtin types are branded records, but we just show their name
+            Ty::Rec(None, Some(Ident(ref atom, BuiltIn)), _) => write!(f, "{}", atom),
+            Ty::Rec(None, None, ref props) if props.len() == 1 && props[0].symbol().0 == "call" => {
+                // Records which only have a call method are functions
+                match props[0] {
+                    TyProp::Method(_, ref params, ref res) => {
+                        try!(write!(f, "("));
+                        try!(self.tys(f, params.as_slice()));
+                        try!(write!(f, ") -> "));
+                        self.ty(f, res, true)
+                    }
+                    TyProp::Val(_, ref ty, _) => {
+                        try!(write!(f, "{{ call: "));
+                        try!(self.ty(f, ty, false));
+                        write!(f, " }}")
+                    }
+                }
+            }
+            Ty::Rec(ref extends, ref brand, ref props) => {
+                if let Some(box ref base) = *extends {
+                    try!(self.ty(f, base, true));
+                }
+                if let Some(Ident(ref atom, _)) = *brand {
+                    try!(write!(f, "#{}#", atom));
+                    if props.is_empty() { return Ok(()) }
+                }
+                if props.is_empty() {
+                    return write!(f, "{{}}");
+                }
+
+                try!(write!(f, "{{ "));
+                for (i, prop) in props.iter().enumerate() {
+                    if i != 0 { try!(write!(f, ", ")); }
+                    match *prop {
+                        TyProp::Val(ref symbol, ref ty, mutability) => {
+                            if mutability == Mutability::Mutable { try!(write!(f, "mut ")); }
+                            try!(write!(f, "{:?}: ", symbol));
+                            try!(self.ty(f, ty, false));
+                        }
+                        TyProp::Method(ref symbol, ref params, ref res) => {
+                            try!(write!(f, "fn {:?}(", symbol));
+                            try!(self.tys(f, params.as_slice()));
+                            try!(write!(f, ") -> "));
+                            try!(self.ty(f, res, true));
+                        }
+                    }
+                }
+                write!(f, " }}")
+            }
+            Ty::Union(ref options) => {
+                if nested { try!(write!(f, "(")); }
+                for (i, option) in options.iter().enumerate() {
+                    if i != 0 { try!(write!(f, " | ")); }
+                    try!(self.ty(f, option, true));
+                }
+                if nested { write!(f, ")") } else { Ok(()) }
+            }
+            Ty::Alias(Ident(ref atom, _), ref args) => {
+                try!(write!(f, "{}", atom));
+                if ! args.is_empty() {
+                    try!(write!(f, "["));
+                    try!(self.tys(f, args.as_slice()));
+                    try!(write!(f, "]"));
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -200,28 +369,314 @@ impl Literal {
     }
 }
 
+/// The text of the `///` doc comments attached to a binding, if any
+pub type Doc = Option<Atom>;
+
+/// A type annotation written by the user, such as the `Int` in `let x: Int = 5`
+pub type Annot = Spanned<Ty>;
+
+/// The annotations on a method's parameters (one for each parameter), and
+/// on its result
+#[derive(Debug, Clone)]
+pub struct Sig {
+    pub params: Vec<Option<Annot>>,
+    pub res: Option<Annot>,
+}
+
 #[derive(Debug, Clone)]
 pub enum Prop {
-    Val(Symbol, Expr),
-    Method(Symbol, Vec<Ident>, Expr),
+    Val(Symbol, Expr, Mutability, Doc),
+    Method(Symbol, Vec<Ident>, Sig, Expr, Doc),
 }
 
 #[derive(Debug, Clone)]
-pub enum Expr {
+pub enum ExprKind {
     Literal(Literal),
     Ident(Ident),
     Rec(Vec<Prop>),
+    /// `#Name#{ ... }`, which creates a record branded with Name. The inner
+    /// expression is always a `Rec`.
+    Brand(Ident, Box<Expr>),
+    /// `[a, b, c]`, which creates a new `List`
+    List(Vec<Expr>),
+    /// `base with { ... }`, a copy of the record base with the given
+    /// properties added, or replacing the properties of the same name
+    Update(Box<Expr>, Vec<Prop>),
 
     Member(Box<Expr>, Symbol),
     Call(Box<Expr>, Symbol, Vec<Expr>),
+    /// `obj.symb <- value`, which assigns to a mutable field. It produces null.
+    Assign(Box<Expr>, Symbol, Box<Expr>),
 
     Block(Vec<Stmt>),
     If(Box<Expr>, Box<Expr>, Box<Option<Expr>>),
+    /// `match expr { pat => expr, ... }`. The first arm which matches is run.
+    Match(Box<Expr>, Vec<Arm>),
+    /// `while cond { body }`, which produces null
+    While(Box<Expr>, Box<Expr>),
+    /// `for x in iter { body }`. It calls `iter:next(iter)` until that produces
+    /// null, running body with x bound to each other value. It produces null.
+    For(Ident, Box<Expr>, Box<Expr>),
+    /// Leaves the innermost loop
+    Break,
+    /// Skips to the next iteration of the innermost loop
+    Continue,
+    /// `return value`, which leaves the innermost method with value as its result
+    Return(Box<Expr>),
+    /// `(expr : Ty)`, which requires expr to have the type Ty
+    Ascribe(Box<Expr>, Annot),
+
+    /// A placeholder for code which couldn't be parsed. The error has
+    /// already been reported, so later stages should accept it quietly.
+    Error,
+}
+
+#[derive(Debug, Clone)]
+pub enum PatKind {
+    /// `_`, which matches anything
+    Wild,
+    /// `x`, which matches anything, and binds it to x
+    Bind(Ident),
+    /// `x: Ty`, which matches values with the shape of Ty: builtin values of
+    /// the same type, or records with its name, fields and methods
+    Typed(Ident, Annot),
+    /// `#Name#{ a, b: pat }`, which matches records which have the fields `a`
+    /// and `b` (and the name, if there is one), where `b` matches pat
+    Rec(Brand, Vec<(Symbol, Pat)>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Pat {
+    pub kind: PatKind,
+    pub span: Span,
+}
+
+impl Pat {
+    pub fn new(kind: PatKind, span: Span) -> Pat {
+        Pat{ kind: kind, span: span }
+    }
+
+    /// The variables which are bound by matching this pattern
+    pub fn bindings(&self) -> Vec<Ident> {
+        match self.kind {
+            PatKind::Wild => vec![],
+            PatKind::Bind(ref id) | PatKind::Typed(ref id, _) => vec![id.clone()],
+            PatKind::Rec(_, ref fields) => {
+                fields.iter().flat_map(|&(_, ref pat)| pat.bindings().into_iter()).collect()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Arm {
+    pub pat: Pat,
+    pub body: Expr,
+}
+
+#[derive(Debug, Clone)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Expr {
+        Expr{ kind: kind, span: span }
+    }
+
+    /// Calls f on this expression and each of the expressions within it,
+    /// including the bodies of methods and match arms, outermost first
+    pub fn walk<F: FnMut(&Expr)>(&self, f: &mut F) {
+        f(self);
+        match self.kind {
+            ExprKind::Rec(ref props) => props_walk(props.as_slice(), f),
+            ExprKind::Update(box ref base, ref props) => {
+                base.walk(f);
+                props_walk(props.as_slice(), f);
+            }
+            ExprKind::List(ref exprs) => {
+                for expr in exprs.iter() { expr.walk(f) }
+            }
+            ExprKind::Call(box ref obj, _, ref args) => {
+                obj.walk(f);
+                for arg in args.iter() { arg.walk(f) }
+            }
+            ExprKind::Block(ref stmts) => {
+                for stmt in stmts.iter() {
+                    match stmt.kind {
+                        StmtKind::Let(_, _, ref expr) | StmtKind::Expr(ref expr) => expr.walk(f),
+                        StmtKind::Type(..) | StmtKind::Name(_) | StmtKind::Empty => {}
+                    }
+                }
+            }
+            ExprKind::If(box ref cond, box ref thn, box ref els) => {
+                cond.walk(f);
+                thn.walk(f);
+                if let Some(ref els) = *els { els.walk(f) }
+            }
+            ExprKind::Match(box ref scrut, ref arms) => {
+                scrut.walk(f);
+                for arm in arms.iter() { arm.body.walk(f) }
+            }
+            ExprKind::Assign(box ref a, _, box ref b) |
+            ExprKind::While(box ref a, box ref b) |
+            ExprKind::For(_, box ref a, box ref b) => {
+                a.walk(f);
+                b.walk(f);
+            }
+            ExprKind::Brand(_, box ref expr) | ExprKind::Member(box ref expr, _) |
+            ExprKind::Return(box ref expr) | ExprKind::Ascribe(box ref expr, _) => expr.walk(f),
+            ExprKind::Ident(_) | ExprKind::Literal(_) | ExprKind::Break |
+            ExprKind::Continue | ExprKind::Error => {}
+        }
+    }
+
+    /// Adds the variables which this expression refers to, including those in
+    /// the bodies of its methods, to vars. Once the expression has been scoped,
+    /// every variable has a unique name, so the variables which it binds itself
+    /// can't be confused with those of the same name outside of it.
+    pub fn referenced_vars(&self, vars: &mut HashSet<Ident>) {
+        match self.kind {
+            ExprKind::Ident(ref id) => { vars.insert(id.clone()); }
+            ExprKind::Rec(ref props) => props_referenced_vars(props.as_slice(), vars),
+            ExprKind::Update(box ref base, ref props) => {
+                base.referenced_vars(vars);
+                props_referenced_vars(props.as_slice(), vars);
+            }
+            ExprKind::List(ref exprs) => {
+                for expr in exprs.iter() { expr.referenced_vars(vars) }
+            }
+            ExprKind::Call(box ref obj, _, ref args) => {
+                obj.referenced_vars(vars);
+                for arg in args.iter() { arg.referenced_vars(vars) }
+            }
+            ExprKind::Block(ref stmts) => {
+                for stmt in stmts.iter() {
+                    match stmt.kind {
+                        StmtKind::Let(_, _, ref expr) | StmtKind::Expr(ref expr) => expr.referenced_vars(vars),
+                        StmtKind::Type(..) | StmtKind::Name(_) | StmtKind::Empty => {}
+                    }
+                }
+            }
+            ExprKind::If(box ref cond, box ref thn, box ref els) => {
+                cond.referenced_vars(vars);
+                thn.referenced_vars(vars);
+                if let Some(ref els) = *els { els.referenced_vars(vars) }
+            }
+            ExprKind::Match(box ref scrut, ref arms) => {
+                scrut.referenced_vars(vars);
+                for arm in arms.iter() { arm.body.referenced_vars(vars) }
+            }
+            ExprKind::Assign(box ref a, _, box ref b) |
+            ExprKind::While(box ref a, box ref b) |
+            ExprKind::For(_, box ref a, box ref b) => {
+                a.referenced_vars(vars);
+                b.referenced_vars(vars);
+            }
+            ExprKind::Brand(_, box ref expr) | ExprKind::Member(box ref expr, _) |
+            ExprKind::Return(box ref expr) | ExprKind::Ascribe(box ref expr, _) => {
+                expr.referenced_vars(vars)
+            }
+            ExprKind::Literal(_) | ExprKind::Break | ExprKind::Continue | ExprKind::Error => {}
+        }
+    }
+}
+
+fn props_walk<F: FnMut(&Expr)>(props: &[Prop], f: &mut F) {
+    for prop in props.iter() {
+        match *prop {
+            Prop::Val(_, ref expr, _, _) | Prop::Method(_, _, _, ref expr, _) => expr.walk(f),
+        }
+    }
+}
+
+fn props_referenced_vars(props: &[Prop], vars: &mut HashSet<Ident>) {
+    for prop in props.iter() {
+        match *prop {
+            Prop::Val(_, ref expr, _, _) | Prop::Method(_, _, _, ref expr, _) => expr.referenced_vars(vars),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
-pub enum Stmt {
-    Let(Ident, Expr),
+pub enum StmtKind {
+    Let(Ident, Option<Annot>, Expr),
+    /// `type Name[A, B] = Ty`
+    Type(Ident, Vec<Ident>, Annot),
+    /// `name Name`, which declares a brand for records
+    Name(Ident),
     Expr(Expr),
     Empty,
 }
+
+#[derive(Debug, Clone)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+    pub doc: Doc,
+}
+
+impl Stmt {
+    pub fn new(kind: StmtKind, span: Span) -> Stmt {
+        Stmt{ kind: kind, span: span, doc: None }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use il::*;
+    use intern::Atom;
+
+    fn var(n: u32) -> Ty {
+        Ty::Ident(Ident(Atom::from_slice("X"), Internal(n)))
+    }
+
+    fn int() -> Ty {
+        Ty::Ident(Ident::from_builtin_slice("Int"))
+    }
+
+    fn func(params: Vec<Ty>, res: Ty) -> Ty {
+        Ty::Rec(None, None, vec![TyProp::Method(Symbol::from_slice("call"), params, res)])
+    }
+
+    #[test]
+    fn renames_internal_vars() {
+        let ty = func(vec![var(17), var(4)], var(17));
+        assert_eq!(format!("{}", ty), "(a, b) -> a");
+    }
+
+    #[test]
+    fn records_and_extension() {
+        let ty = Ty::Rec(Some(box var(3)), None, vec![
+            TyProp::Val(Symbol::from_slice("x"), int(), Mutability::Immutable),
+            TyProp::Val(Symbol::from_slice("n"), int(), Mutability::Mutable),
+            TyProp::Method(Symbol::from_slice("m"), vec![int(), var(9)], var(9)),
+        ]);
+        assert_eq!(format!("{}", ty), "a{ x: Int, mut n: Int, fn m(Int, b) -> b }");
+        assert_eq!(format!("{}", Ty::Rec(None, None, vec![])), "{}");
+    }
+
+    #[test]
+    fn nested_unions_are_parenthesized() {
+        let ty = func(vec![Ty::Union(vec![int(), var(1)])],
+                      Ty::Union(vec![int(), var(2)]));
+        assert_eq!(format!("{}", ty), "(Int | a) -> (Int | b)");
+    }
+
+    #[test]
+    fn aliases_are_shown_by_name() {
+        let list = Ty::Alias(Ident::from_slice("List"), vec![int()]);
+        assert_eq!(format!("{}", Ty::Rec(Some(box list), None, vec![])), "List[Int]{}");
+        assert_eq!(format!("{}", Ty::Alias(Ident::from_slice("Nat"), vec![])), "Nat");
+    }
+
+    #[test]
+    fn named_records_show_their_brand() {
+        let p = Some(Ident::from_slice("P"));
+        assert_eq!(format!("{}", Ty::Rec(None, p.clone(), vec![])), "#P#");
+        assert_eq!(format!("{}", Ty::Rec(None, p, vec![
+            TyProp::Val(Symbol::from_slice("x"), int(), Mutability::Immutable)])), "#P#{ x: Int }");
+        assert_eq!(format!("{}", Ty::Rec(None, Some(Ident::from_builtin_slice("Int")), vec![])), "Int");
+    }
+}
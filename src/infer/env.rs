@@ -1,7 +1,8 @@
 use std::collections::{HashMap, HashSet};
 use intern::Atom;
 use il::*;
-use infer::util::free_vars;
+use diagnostic::Diagnostic;
+use infer::util::{free_vars, mutable_vars};
 use infer::InferValue;
 
 /// A struct implementing Env has access to a set of type_vars.
@@ -12,6 +13,9 @@ pub trait Env {
 
     fn lookup_data_var(&mut self, id: &Ident) -> Ty;
 
+    /// The parameters and body of the type alias id
+    fn lookup_alias(&self, id: &Ident) -> Option<&(Vec<Ident>, Ty)>;
+
     fn introduce_type_var(&mut self) -> Ty;
 
     fn substitute(&mut self, id: Ident, ty: Ty);
@@ -19,13 +23,34 @@ pub trait Env {
     fn as_infervalue(&self) -> InferValue;
 }
 
-#[derive(Debug)]
+/// The type of a data variable, which is polymorphic in vars. Each use of the
+/// variable gets fresh instances of vars. The type of a variable which isn't
+/// bound by `let` has no vars, so every use of it shares the same type.
+#[derive(Debug, Clone)]
+pub struct TypeScheme {
+    pub vars: HashSet<Ident>,
+    pub ty: Ty,
+}
+
+impl TypeScheme {
+    pub fn monomorphic(ty: Ty) -> TypeScheme {
+        TypeScheme{ vars: HashSet::new(), ty: ty }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Scope {
-    data_vars: HashMap<Ident, Ty>,
+    data_vars: HashMap<Ident, TypeScheme>,
     type_vars: HashMap<Ident, Ty>,
+    aliases: HashMap<Ident, (Vec<Ident>, Ty)>,
     counter: u32,
 
-    bound_vars: Vec<HashSet<Ident>>,
+    /// The data vars which are in scope, innermost last. The type variables
+    /// which are free in their types can't be generalized.
+    in_scope: Vec<HashSet<Ident>>,
+    /// The result types of the methods being inferred, innermost last
+    results: Vec<Ty>,
+    warnings: Vec<Diagnostic>,
 }
 
 impl Scope {
@@ -36,103 +61,253 @@ impl Scope {
         // TODO: Builtins shouldn't be declared here, this is very sloppy.
         // we should have a better way of declaring the builtins
         // (possibly using macros so that we can use nice syntax?)
-        type_vars.insert(Ident::from_builtin_slice("Int"),
-                         Ty::Rec(None, vec![TyProp::Method(Symbol::from_slice("+"),
-                                                           vec![Ty::Ident(Ident::from_builtin_slice("Int"))],
-                                                           Ty::Ident(Ident::from_builtin_slice("Int"))),
-                                            TyProp::Method(Symbol::from_slice("*"),
-                                                           vec![Ty::Ident(Ident::from_builtin_slice("Int"))],
-                                                           Ty::Ident(Ident::from_builtin_slice("Int")))]));
-
-        Scope {
+        let int = Ty::Ident(Ident::from_builtin_slice("Int"));
+        let float = Ty::Ident(Ident::from_builtin_slice("Float"));
+        let str_ = Ty::Ident(Ident::from_builtin_slice("Str"));
+        let bool_ = Ty::Ident(Ident::from_builtin_slice("Bool"));
+
+        // The builtin types are branded with their own names, so that user
+        // records with the same methods can't be passed off as them.
+        let methods = |names: &[&str], params: Vec<Ty>, res: &Ty| -> Vec<TyProp> {
+            names.iter().map(|name| {
+                TyProp::Method(Symbol::from_slice(name), params.clone(), res.clone())
+            }).collect()
+        };
+        let branded = |name: &str, props: Vec<TyProp>| {
+            Ty::Rec(None, Some(Ident::from_builtin_slice(name)), props)
+        };
+        let comparisons = ["==", "!=", "<", "<=", ">", ">="];
+
+        let mut int_ops = methods(&["+", "-", "*", "/", "%", "&", "|", "^"], vec![int.clone()], &int);
+        int_ops.extend(methods(&comparisons, vec![int.clone()], &bool_).into_iter());
+        int_ops.extend(methods(&["negate"], vec![], &int).into_iter());
+        int_ops.extend(methods(&["to_float"], vec![], &float).into_iter());
+        int_ops.extend(methods(&["to_str"], vec![], &str_).into_iter());
+        type_vars.insert(Ident::from_builtin_slice("Int"), branded("Int", int_ops));
+
+        let mut float_ops = methods(&["+", "-", "*", "/"], vec![float.clone()], &float);
+        float_ops.extend(methods(&comparisons, vec![float.clone()], &bool_).into_iter());
+        float_ops.extend(methods(&["negate"], vec![], &float).into_iter());
+        float_ops.extend(methods(&["to_int"], vec![], &int).into_iter());
+        float_ops.extend(methods(&["to_str"], vec![], &str_).into_iter());
+        type_vars.insert(Ident::from_builtin_slice("Float"), branded("Float", float_ops));
+
+        let mut str_ops = methods(&["+"], vec![str_.clone()], &str_);
+        str_ops.extend(methods(&["==", "!="], vec![str_.clone()], &bool_).into_iter());
+        str_ops.extend(methods(&["len"], vec![], &int).into_iter());
+        str_ops.extend(methods(&["to_str"], vec![], &str_).into_iter());
+        type_vars.insert(Ident::from_builtin_slice("Str"), branded("Str", str_ops));
+
+        let mut bool_ops = methods(&["==", "!="], vec![bool_.clone()], &bool_);
+        bool_ops.extend(methods(&["not"], vec![], &bool_).into_iter());
+        bool_ops.extend(methods(&["to_str"], vec![], &str_).into_iter());
+        type_vars.insert(Ident::from_builtin_slice("Bool"), branded("Bool", bool_ops));
+
+        // Null is the value of a block without a result. It has no methods, but
+        // it is still named, so that `match` can tell it apart from records.
+        type_vars.insert(Ident::from_builtin_slice("Null"), branded("Null", vec![]));
+
+        // `List[A]` is an alias, as it takes a parameter. B is instantiated
+        // whenever the alias is expanded, so `map` and `fold` are polymorphic.
+        let a = Ty::Ident(Ident::from_builtin_slice("A"));
+        let b = Ty::Ident(Ident::from_builtin_slice("B"));
+        let list = |elem: &Ty| Ty::Alias(Ident::from_builtin_slice("List"), vec![elem.clone()]);
+        let func = |params: Vec<Ty>, res: &Ty| {
+            Ty::Rec(None, None, vec![TyProp::Method(Symbol::from_slice("call"), params, res.clone())])
+        };
+        let null = Ty::Ident(Ident::from_builtin_slice("Null"));
+
+        let mut list_ops = methods(&["len"], vec![], &int);
+        list_ops.extend(methods(&["get"], vec![int.clone()], &a).into_iter());
+        list_ops.extend(methods(&["push"], vec![a.clone()], &null).into_iter());
+        list_ops.extend(methods(&["map"], vec![func(vec![a.clone()], &b)], &list(&b)).into_iter());
+        list_ops.extend(methods(&["fold"], vec![b.clone(), func(vec![b.clone(), a.clone()], &b)], &b).into_iter());
+
+        let mut aliases = HashMap::new();
+        aliases.insert(Ident::from_builtin_slice("List"),
+                       (vec![Ident::from_builtin_slice("A")], branded("List", list_ops)));
+
+        let mut scope = Scope {
             type_vars: type_vars,
             data_vars: HashMap::new(),
+            aliases: aliases,
             counter: 0,
 
-            bound_vars: vec![HashSet::new()],
-        }
+            in_scope: vec![HashSet::new()],
+            results: vec![],
+            warnings: vec![],
+        };
+
+        // Like any other value, null can be used where a union is expected
+        let any = scope.introduce_type_var();
+        let mut vars = HashSet::new();
+        vars.insert(any.unwrap_ident());
+        scope.data_vars.insert(Ident::from_builtin_slice("null"), TypeScheme{
+            vars: vars,
+            ty: Ty::Union(vec![null, any]),
+        });
+
+        scope
     }
 
-    pub fn push_child(&mut self, bound_vars: HashSet<Ident>) {
-        // Also add all free variables in the bound vars to the list of bound vars!
-        let bvs = bound_vars.iter().fold(HashSet::new(), |mut v, bv| {
-            v.extend(free_vars(self, &Ty::Ident(bv.clone())).iter().cloned());
-            v
-        });
+    /// The substitutions which have been made for type variables so far
+    pub fn type_vars(&self) -> &HashMap<Ident, Ty> {
+        &self.type_vars
+    }
+
+    /// Aliases are only expanded when unification needs their structure, so
+    /// they can be defined before the aliases which they refer to
+    pub fn define_alias(&mut self, id: Ident, params: Vec<Ident>, body: Ty) {
+        self.aliases.insert(id, (params, body));
+    }
+
+    /// Records a warning, which is reported once inference has finished
+    pub fn warn(&mut self, warning: Diagnostic) {
+        self.warnings.push(warning);
+    }
+
+    /// Removes the warnings which have been recorded so far
+    pub fn take_warnings(&mut self) -> Vec<Diagnostic> {
+        ::std::mem::replace(&mut self.warnings, vec![])
+    }
 
-        self.bound_vars.push(bvs);
+    /// Enters a scope in which the data vars ids are bound. Until it is
+    /// popped, the type variables in their types won't be generalized.
+    pub fn push_child(&mut self, ids: HashSet<Ident>) {
+        self.in_scope.push(ids);
     }
 
     pub fn pop_child(&mut self) {
-        self.bound_vars.pop();
+        self.in_scope.pop();
+    }
+
+    /// Enters the body of a method, which `return` produces values of type result for
+    pub fn push_result(&mut self, result: Ty) {
+        self.results.push(result);
+    }
+
+    pub fn pop_result(&mut self) {
+        self.results.pop();
+    }
+
+    /// The result type of the innermost method
+    pub fn result(&self) -> Option<&Ty> {
+        self.results.last()
+    }
+
+    /// The type scheme of the data var id
+    pub fn lookup_scheme(&mut self, id: &Ident) -> TypeScheme {
+        if let Some(scheme) = self.data_vars.get(id) {
+            return scheme.clone();
+        }
+
+        let ty = self.introduce_type_var();
+        self.data_vars.insert(id.clone(), TypeScheme::monomorphic(ty.clone()));
+        TypeScheme::monomorphic(ty)
     }
 
-    fn is_bound(&self, var: &Ident) -> bool {
-        // Theoretically should be done in reverse, but makes no difference
-        self.bound_vars.iter().any(|bv| bv.contains(var))
+    /// The type variables which are free in the types of the data vars in scope
+    fn env_vars(&mut self) -> HashSet<Ident> {
+        let ids: Vec<Ident> = self.in_scope.iter().flat_map(|ids| ids.iter().cloned()).collect();
+
+        let mut vars = HashSet::new();
+        for id in ids.iter() {
+            let scheme = self.lookup_scheme(id);
+            vars.extend(free_vars(self, &scheme.ty).into_iter().filter(|var| {
+                ! scheme.vars.contains(var)
+            }));
+        }
+        vars
+    }
+
+    /// Generalizes the types of the `let`-bound data vars ids, which have been
+    /// inferred together, and brings them into the innermost scope. They are
+    /// polymorphic in the type variables which aren't free in the scope.
+    pub fn generalize(&mut self, ids: &[Ident]) {
+        let env_vars = self.env_vars();
+
+        for id in ids.iter() {
+            let ty = self.lookup_data_var(id);
+            // Mutable fields are shared by every use of the record, so the
+            // type variables in them can't be generalized. Otherwise, each
+            // use could store a value of a different type in the field.
+            let shared = mutable_vars(self, &ty);
+            let vars = free_vars(self, &ty).into_iter().filter(|var| {
+                ! env_vars.contains(var) && ! shared.contains(var)
+            }).collect();
+
+            self.data_vars.insert(id.clone(), TypeScheme{ vars: vars, ty: ty });
+        }
+
+        self.in_scope.last_mut().unwrap().extend(ids.iter().cloned());
+    }
+
+    /// Instantiates scheme, with fresh type variables in place of its quantified ones
+    pub fn instantiate(&mut self, scheme: &TypeScheme) -> Ty {
+        if scheme.vars.is_empty() {
+            return scheme.ty.clone();
+        }
+
+        let mut mappings = HashMap::new();
+        for var in scheme.vars.iter() {
+            let fresh = self.introduce_type_var();
+            mappings.insert(var.clone(), fresh);
+        }
+        self.instantiate_ty(&scheme.ty, &mut mappings)
     }
 
-    pub fn instantiate(&mut self, ty: &Ty, mappings: &mut HashMap<Ident, Ty>) -> Ty {
+    fn instantiate_ty(&mut self, ty: &Ty, mappings: &mut HashMap<Ident, Ty>) -> Ty {
         match *ty {
             Ty::Ident(ref id) => {
                 if let Some(ty) = mappings.get(id) {
                     // It's been handled already, just go with it!
                     return ty.clone()
                 } /* else */
-                if self.is_bound(id) {
-                    // Bound type vars are explicitly not initialized
-                    ty.clone()
-                } else {
-                    // Create a type var to represent the instantiated version
+                if let Some(ty) = self.lookup_type_var(id).cloned() {
+                    // Create a type var to represent the instantiated version.
+                    // It is mapped before instantiating the type which is
+                    // being pointed to, as that type may refer to itself.
                     let ty_var = self.introduce_type_var();
                     mappings.insert(id.clone(), ty_var.clone());
 
-                    if let Some(ty) = self.lookup_type_var(id).cloned() {
-                        // Instantiate the type which is being pointed to
-                        let instantiated = self.instantiate(&ty, mappings);
-
-                        // Make the ty_var point to the instantiated type
-                        self.substitute(ty_var.unwrap_ident(), instantiated);
-                    }
+                    let instantiated = self.instantiate_ty(&ty, mappings);
 
+                    // Make the ty_var point to the instantiated type
+                    self.substitute(ty_var.unwrap_ident(), instantiated);
                     ty_var
+                } else {
+                    // Type vars which aren't quantified are shared
+                    ty.clone()
                 }
             }
-            Ty::Rec(ref extends, ref props) => {
+            Ty::Rec(ref extends, ref brand, ref props) => {
                 // Instantiate all of the properties!
-                let extends = extends.as_ref().map(|x| box self.instantiate(&**x, mappings));
+                let extends = extends.as_ref().map(|x| box self.instantiate_ty(&**x, mappings));
 
                 let props = props.iter().map(|prop| {
                     match *prop {
-                        TyProp::Val(ref symb, ref ty) => {
-                            TyProp::Val(symb.clone(), self.instantiate(ty, mappings))
+                        TyProp::Val(ref symb, ref ty, mutability) => {
+                            TyProp::Val(symb.clone(), self.instantiate_ty(ty, mappings), mutability)
                         }
                         TyProp::Method(ref symb, ref args, ref res) => {
-                            let nargs = args.iter().map(|x| self.instantiate(x, mappings)).collect();
-                            let nres = self.instantiate(res, mappings);
+                            let nargs = args.iter().map(|x| self.instantiate_ty(x, mappings)).collect();
+                            let nres = self.instantiate_ty(res, mappings);
                             TyProp::Method(symb.clone(), nargs, nres)
                         }
                     }
                 }).collect();
 
-                Ty::Rec(extends, props)
+                Ty::Rec(extends, brand.clone(), props)
             }
             Ty::Union(ref options) => {
-                let nopts = options.iter().map(|x| self.instantiate(x, mappings)).collect();
+                let nopts = options.iter().map(|x| self.instantiate_ty(x, mappings)).collect();
                 Ty::Union(nopts)
             }
-        }
-    }
-
-    fn maybe_bind(&mut self, id: &Ident, maybe_binds: &[Ident]) {
-        for bv in self.bound_vars.iter_mut().rev() {
-            if bv.contains(id) {
-                bv.extend(maybe_binds.iter().cloned());
-                return;
+            Ty::Alias(ref id, ref args) => {
+                let nargs = args.iter().map(|x| self.instantiate_ty(x, mappings)).collect();
+                Ty::Alias(id.clone(), nargs)
             }
         }
-
-        panic!()
     }
 }
 
@@ -142,13 +317,11 @@ impl Env for Scope {
     }
 
     fn lookup_data_var(&mut self, id: &Ident) -> Ty {
-        if let Some(ty) = self.data_vars.get(id) {
-            return ty.clone();
-        }
+        self.lookup_scheme(id).ty
+    }
 
-        let ty = self.introduce_type_var();
-        self.data_vars.insert(id.clone(), ty.clone());
-        ty
+    fn lookup_alias(&self, id: &Ident) -> Option<&(Vec<Ident>, Ty)> {
+        self.aliases.get(id)
     }
 
     // Creating a unique type variable
@@ -166,26 +339,19 @@ impl Env for Scope {
     // id _must_ be unbound at the point of substitution
     fn substitute(&mut self, id: Ident, ty: Ty) {
         // Substitute the type variable
-        let prev = self.type_vars.insert(id.clone(), ty.clone());
-
-        if self.is_bound(&id) {
-            // Determine what new variables have to be bound
-            let free = free_vars(self, &ty);
-            let newly_bound: Vec<_> = free.iter().filter(|x| {
-                ! self.is_bound(*x)
-            }).cloned().collect();
-
-            self.maybe_bind(&id, newly_bound.as_slice());
-        }
-
+        let prev = self.type_vars.insert(id, ty);
         assert!(prev.is_none());
     }
 
     fn as_infervalue(&self) -> InferValue {
         // TODO: Remove
         InferValue{
-            data_vars: self.data_vars.clone(),
+            // The builtins, such as null, aren't bindings made by the program
+            data_vars: self.data_vars.iter().filter(|&(id, _)| id.1 != BuiltIn).map(|(id, scheme)| {
+                (id.clone(), scheme.ty.clone())
+            }).collect(),
             type_vars: self.type_vars.clone(),
+            warnings: self.warnings.clone(),
         }
     }
 }
@@ -0,0 +1,130 @@
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use il::*;
+
+// The `let`s in a block can refer to each other in any order, so they are
+// grouped into strongly connected components of the graph of which bindings
+// refer to which. Each group is inferred after the groups which it refers to,
+// with its own variables monomorphic until the whole group has been inferred.
+
+struct Tarjan<'a> {
+    deps: &'a [Vec<usize>],
+    index: Vec<Option<usize>>,
+    lowlink: Vec<usize>,
+    on_stack: Vec<bool>,
+    stack: Vec<usize>,
+    counter: usize,
+    groups: Vec<Vec<usize>>,
+}
+
+impl<'a> Tarjan<'a> {
+    fn visit(&mut self, v: usize) {
+        self.index[v] = Some(self.counter);
+        self.lowlink[v] = self.counter;
+        self.counter += 1;
+        self.stack.push(v);
+        self.on_stack[v] = true;
+
+        let deps = self.deps;
+        for &w in deps[v].iter() {
+            match self.index[w] {
+                None => {
+                    self.visit(w);
+                    self.lowlink[v] = cmp::min(self.lowlink[v], self.lowlink[w]);
+                }
+                Some(w_index) if self.on_stack[w] => {
+                    self.lowlink[v] = cmp::min(self.lowlink[v], w_index);
+                }
+                Some(_) => {}
+            }
+        }
+
+        // v is the first binding of its group which was visited, so the rest
+        // of the group is above it on the stack
+        if self.index[v] == Some(self.lowlink[v]) {
+            let mut group = vec![];
+            loop {
+                let w = self.stack.pop().unwrap();
+                self.on_stack[w] = false;
+                group.push(w);
+                if w == v { break }
+            }
+            group.sort();
+            self.groups.push(group);
+        }
+    }
+}
+
+/// Groups the `let`s in stmts into sets of mutually recursive bindings. Each
+/// group is a list of indices into stmts, and comes after the groups which
+/// its bindings refer to. Otherwise, the bindings stay in source order.
+pub fn binding_groups(stmts: &[Stmt]) -> Vec<Vec<usize>> {
+    let lets: Vec<(usize, &Ident, &Expr)> = stmts.iter().enumerate().filter_map(|(i, stmt)| {
+        match stmt.kind {
+            StmtKind::Let(ref id, _, ref expr) => Some((i, id, expr)),
+            _ => None,
+        }
+    }).collect();
+
+    let positions: HashMap<&Ident, usize> = lets.iter().enumerate().map(|(pos, &(_, id, _))| {
+        (id, pos)
+    }).collect();
+
+    let deps: Vec<Vec<usize>> = lets.iter().map(|&(_, _, expr)| {
+        let mut vars = HashSet::new();
+        expr.referenced_vars(&mut vars);
+        let mut deps: Vec<usize> = vars.iter().filter_map(|var| positions.get(var).cloned()).collect();
+        deps.sort();
+        deps
+    }).collect();
+
+    let mut tarjan = Tarjan{
+        deps: deps.as_slice(),
+        index: lets.iter().map(|_| None).collect(),
+        lowlink: lets.iter().map(|_| 0).collect(),
+        on_stack: lets.iter().map(|_| false).collect(),
+        stack: vec![],
+        counter: 0,
+        groups: vec![],
+    };
+    for v in 0..lets.len() {
+        if tarjan.index[v].is_none() {
+            tarjan.visit(v);
+        }
+    }
+
+    tarjan.groups.into_iter().map(|group| {
+        group.into_iter().map(|pos| lets[pos].0).collect()
+    }).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use lexer;
+    use parser;
+    use scope;
+    use super::binding_groups;
+
+    fn groups(code: &str) -> Vec<Vec<usize>> {
+        let tokens = lexer::lex(code).unwrap();
+        let ast = parser::parse_program(&mut parser::State::new(tokens.as_slice())).unwrap();
+        let scoped = scope::scoped_block(&mut scope::Scope::new(), ast.as_slice()).unwrap();
+        binding_groups(scoped.as_slice())
+    }
+
+    #[test]
+    fn dependencies_come_first() {
+        assert_eq!(groups("let a = b; let b = 1; a"), vec![vec![1], vec![0]]);
+        assert_eq!(groups("let a = 1; 2; let b = a"), vec![vec![0], vec![2]]);
+    }
+
+    #[test]
+    fn groups_mutual_recursion() {
+        assert_eq!(groups(stringify!{
+            let even = fn(n) { if n == 0 { true } else { odd(n - 1) } };
+            let odd = fn(n) { if n == 0 { false } else { even(n - 1) } };
+            let fib = fn(n) { if n < 2 { n } else { fib(n - 1) + fib(n - 2) } };
+            let main = fn() { even(fib(5)) };
+        }), vec![vec![0, 1], vec![2], vec![3]]);
+    }
+}
@@ -1,12 +1,16 @@
 use std::fmt;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use intern::Atom;
+use span::Span;
+use diagnostic::{Diagnostic, Kind};
 use il::*;
 use self::env::{Scope, Env};
 
 mod util;
-mod env;
+pub mod env;
 mod unify;
+mod pattern;
+mod group;
 
 #[cfg(test)]
 mod test;
@@ -15,6 +19,8 @@ mod test;
 pub struct InferValue {
     pub data_vars: HashMap<Ident, Ty>,
     pub type_vars: HashMap<Ident, Ty>,
+    /// Problems which were found, but which don't stop the program from running
+    pub warnings: Vec<Diagnostic>,
 }
 
 impl fmt::Debug for InferValue {
@@ -35,28 +41,138 @@ impl fmt::Debug for InferValue {
 }
 
 
-fn infer_body(scope: &mut Scope, params: &Vec<Ident>, body: &Expr) -> Result<Ty, String> {
-    let bound = params.iter().map(|x| {
-        if let Ty::Ident(id) = scope.lookup_data_var(x) {
-            id
-        } else { unreachable!() }
-    }).collect();
-
-    scope.push_child(bound);
+fn infer_body(scope: &mut Scope, params: &Vec<Ident>, body: &Expr) -> Result<Ty, Diagnostic> {
+    // Every `return` in the body is unified with the result type
+    let result = scope.introduce_type_var();
+    scope.push_child(params.iter().cloned().collect());
+    scope.push_result(result.clone());
     let res = infer_expr(scope, body);
+    scope.pop_result();
     scope.pop_child();
 
-    res
+    // The method produces either the value of its body, or a returned value.
+    // The result type is only bound if the body contains a `return`.
+    let body_ty = try!(res);
+    if scope.lookup_type_var(&result.unwrap_ident()).is_some() {
+        Ok(Ty::Union(vec![body_ty, result]))
+    } else {
+        Ok(body_ty)
+    }
+}
+
+/// Unify two types, attributing any failure to the given span
+fn unify_at(scope: &mut Scope, span: Span, label: &str, a: &Ty, b: &Ty) -> Result<(), Diagnostic> {
+    unify::unify(scope, a, b).map_err(|err| {
+        if err.primary_span().is_some() { err } else { err.with_primary(span, label) }
+    })
+}
+
+/// Unify the type of an expression with the annotation which it was written
+/// with. The annotation is what the user asked for, so errors point at it.
+fn unify_annot(scope: &mut Scope, annot: &Annot, span: Span, ty: &Ty) -> Result<(), Diagnostic> {
+    unify_at(scope, annot.span, "expected because of this annotation", &annot.node, ty)
+        .map_err(|err| err.with_secondary(span, "this value doesn't match the annotation"))
 }
 
-pub fn infer_expr(scope: &mut Scope, e: &Expr) -> Result<Ty, String> {
-    match *e {
-        Expr::Literal(ref lit) => { Ok(util::val_ty(scope, lit.ty())) } // We probably can just inline that
-        Expr::Ident(ref ident) => {
-            let uninst = scope.lookup_data_var(ident);
-            Ok(scope.instantiate(&uninst, &mut HashMap::new()))
+/// Type aliases are visible throughout their block, so they are all
+/// defined before any of its statements are inferred
+fn define_aliases(scope: &mut Scope, stmts: &[Stmt]) {
+    for stmt in stmts.iter() {
+        if let StmtKind::Type(ref id, ref params, ref body) = stmt.kind {
+            scope.define_alias(id.clone(), params.clone(), body.node.clone());
         }
-        Expr::Call(ref obj, ref symb, ref params) => {
+    }
+}
+
+/// Infers the types of the properties of a record literal
+fn infer_props(scope: &mut Scope, props: &[Prop]) -> Result<Vec<TyProp>, Diagnostic> {
+    let mut prop_tys = Vec::with_capacity(props.len());
+
+    for prop in props.iter() {
+        match *prop {
+            Prop::Val(ref symb, ref expr, mutability, _) => {
+                let mut ty = try!(infer_expr(scope, expr));
+                if mutability == Mutability::Mutable {
+                    ty = util::exact_ty(&*scope, &ty);
+                }
+                prop_tys.push(TyProp::Val(symb.clone(), ty, mutability))
+            }
+            Prop::Method(ref symb, ref params, ref sig, ref body, _) => {
+                // The annotated parameters must be known before the body is inferred
+                for (param, annot) in params.iter().zip(sig.params.iter()) {
+                    if let Some(ref annot) = *annot {
+                        let param_ty = scope.lookup_data_var(param);
+                        try!(unify_at(scope, annot.span, "in this annotation",
+                                      &annot.node, &param_ty));
+                    }
+                }
+
+                let body_ty = try!(infer_body(scope, params, body));
+                if let Some(ref res) = sig.res {
+                    try!(unify_annot(scope, res, body.span, &body_ty));
+                }
+
+                let mut param_tys = Vec::with_capacity(params.len());
+                for param in params.iter() {
+                    param_tys.push(scope.lookup_data_var(param));
+                }
+                prop_tys.push(
+                    TyProp::Method(symb.clone(), param_tys, body_ty))
+            }
+        }
+    }
+
+    Ok(prop_tys)
+}
+
+/// Infers the type of a record literal, which was created with the given brand
+fn infer_rec(scope: &mut Scope, props: &[Prop], brand: Brand) -> Result<Ty, Diagnostic> {
+    let prop_tys = try!(infer_props(scope, props));
+    Ok(util::val_ty(scope, Ty::Rec(None, brand, prop_tys)))
+}
+
+/// Infers the type of `base with { props }`. Each record type which base could
+/// have is copied, without the properties which props replace. The copy isn't
+/// named, as only the code which declares a name can create records with it.
+fn infer_update(scope: &mut Scope, base: &Expr, props: &[Prop]) -> Result<Ty, Diagnostic> {
+    let base_ty = try!(infer_expr(scope, base));
+    let prop_tys = try!(infer_props(scope, props));
+
+    let mut tys = vec![];
+    for alt in pattern::alternatives(scope, &base_ty).into_iter() {
+        tys.push(match alt {
+            Ty::Rec(_, Some(Ident(ref atom, BuiltIn)), _) => {
+                return Err(Diagnostic::error(
+                    Kind::TypeMismatch,
+                    format!("Values of type {} aren't records, so they can't be updated", atom)
+                ).with_primary(base.span, "this value might not be a record"));
+            }
+            Ty::Rec(extends, _, alt_props) => {
+                let mut nprops: Vec<TyProp> = alt_props.into_iter().filter(|alt_prop| {
+                    ! prop_tys.iter().any(|prop| prop.symbol() == alt_prop.symbol())
+                }).collect();
+                nprops.extend(prop_tys.iter().cloned());
+                Ty::Rec(extends, None, nprops)
+            }
+            // We don't know which properties a value of unknown type has, so
+            // props are added to it. TODO: This breaks if it later turns out to
+            // have one of them, as record types can't have a property twice.
+            unknown => Ty::Rec(Some(box unknown), None, prop_tys.clone()),
+        });
+    }
+
+    let ty = if tys.len() == 1 { tys.pop().unwrap() } else { Ty::Union(tys) };
+    Ok(util::val_ty(scope, ty))
+}
+
+pub fn infer_expr(scope: &mut Scope, e: &Expr) -> Result<Ty, Diagnostic> {
+    match e.kind {
+        ExprKind::Literal(ref lit) => { Ok(util::val_ty(scope, lit.ty())) } // We probably can just inline that
+        ExprKind::Ident(ref ident) => {
+            let scheme = scope.lookup_scheme(ident);
+            Ok(scope.instantiate(&scheme))
+        }
+        ExprKind::Call(ref obj, ref symb, ref params) => {
             let obj_ty = try!(infer_expr(scope, &**obj));
 
             let mut param_tys = Vec::with_capacity(params.len());
@@ -69,74 +185,75 @@ pub fn infer_expr(scope: &mut Scope, e: &Expr) -> Result<Ty, String> {
 
             let res = scope.introduce_type_var();
             // The object must have the method with the correct type. UNIFY!
-            let require_ty = Ty::Rec(Some(box scope.introduce_type_var()),
+            let require_ty = Ty::Rec(Some(box scope.introduce_type_var()), None,
                                      vec![TyProp::Method(symb.clone(), param_tys, res.clone())]);
-            try!(unify::unify(scope, &obj_ty, &require_ty));
+            try!(unify_at(scope, e.span, "in this method call", &obj_ty, &require_ty)
+                 .map_err(|err| {
+                     err.with_secondary(obj.span, &format!("`{:?}` is called on this value", symb))
+                 }));
             Ok(res)
         }
-        Expr::Member(ref obj, ref symb) => {
+        ExprKind::List(ref elems) => {
+            // Like the branches of an `if`, the elements can have different types
+            let elem_ty = match elems.len() {
+                0 => scope.introduce_type_var(),
+                1 => try!(infer_expr(scope, &elems[0])),
+                _ => Ty::Union(try!(elems.iter().map(|elem| infer_expr(scope, elem)).collect())),
+            };
+            Ok(util::val_ty(scope, Ty::Alias(Ident::from_builtin_slice("List"), vec![elem_ty])))
+        }
+        ExprKind::Update(box ref base, ref props) => infer_update(scope, base, props.as_slice()),
+        ExprKind::Member(ref obj, ref symb) => {
             let obj_ty = try!(infer_expr(scope, &**obj));
 
             let ty = scope.introduce_type_var();
 
-            let require_ty = Ty::Rec(Some(box scope.introduce_type_var()),
-                                     vec![TyProp::Val(symb.clone(), ty.clone())]);
-            try!(unify::unify(scope, &obj_ty, &require_ty));
+            let require_ty = Ty::Rec(Some(box scope.introduce_type_var()), None,
+                                     vec![TyProp::Val(symb.clone(), ty.clone(), Mutability::Immutable)]);
+            try!(unify_at(scope, e.span, "in this property access", &obj_ty, &require_ty)
+                 .map_err(|err| {
+                     err.with_secondary(obj.span, &format!("`{:?}` is read from this value", symb))
+                 }));
 
             Ok(ty)
         }
-        Expr::Rec(ref props) => {
-            let self_type = scope.introduce_type_var();
+        ExprKind::Assign(box ref obj, ref symb, box ref value) => {
+            let obj_ty = try!(infer_expr(scope, obj));
 
-            let mut prop_tys = Vec::with_capacity(props.len());
+            // The field is required before the value is inferred, such that
+            // reading from the field in the value sees that it is mutable
+            let ty = scope.introduce_type_var();
+            let require_ty = Ty::Rec(Some(box scope.introduce_type_var()), None,
+                                     vec![TyProp::Val(symb.clone(), ty.clone(), Mutability::Mutable)]);
+            try!(unify_at(scope, e.span, "in this assignment", &obj_ty, &require_ty)
+                 .map_err(|err| {
+                     err.with_secondary(obj.span, &format!("`{:?}` is assigned to on this value", symb))
+                 }));
 
-            for prop in props.iter() {
-                match *prop {
-                    Prop::Val(ref symb, ref expr) => {
-                        prop_tys.push(
-                            TyProp::Val(symb.clone(), try!(infer_expr(scope, expr))))
-                    }
-                    Prop::Method(ref symb, ref params, ref body) => {
-                        // Unify the first variable's type with self_type
-                        // TODO: Do this at the end?
-                        let first_type = scope.lookup_data_var(&params[0]);
-                        try!(unify::unify(scope, &first_type, &self_type));
-
-                        let body_ty = try!(infer_body(scope, params, body));
-                        let mut param_tys = Vec::with_capacity(params.len());
-                        for param in params.iter() {
-                            param_tys.push(scope.lookup_data_var(param));
-                        }
-                        prop_tys.push(
-                            TyProp::Method(symb.clone(), param_tys, body_ty))
-                    }
-                }
-            }
+            let value_ty = try!(infer_expr(scope, value));
+            try!(unify_at(scope, value.span, "this value doesn't fit in the field", &ty, &value_ty));
 
-            Ok(util::val_ty(scope, Ty::Rec(None, prop_tys)))
+            Ok(util::val_ty(scope, Ty::Ident(Ident(Atom::from_slice("Null"), BuiltIn))))
         }
-        Expr::Block(ref stmts) => {
-            // Infer for each value but the last one
-            for stmt in stmts.init().iter() {
-                try!(infer_stmt(scope, stmt));
-            }
-            // Run the last one
-            match stmts.last() {
-                Some(&Stmt::Expr(ref expr)) => {
-                    return infer_expr(scope, expr);
-                }
-                Some(stmt) => {
-                    try!(infer_stmt(scope, stmt));
-                }
-                None => {}
+        ExprKind::Rec(ref props) => infer_rec(scope, props.as_slice(), None),
+        ExprKind::Brand(ref brand, box ref rec) => {
+            match rec.kind {
+                ExprKind::Rec(ref props) => infer_rec(scope, props.as_slice(), Some(brand.clone())),
+                _ => panic!("ICE: Only record literals can be branded"),
             }
-            // If the last element isn't an Expression, the value is Null ({})
-            Ok(util::val_ty(scope, Ty::Ident(Ident(Atom::from_slice("Null"), BuiltIn))))
         }
-        Expr::If(box ref cond, box ref thn, box ref els) => {
+        ExprKind::Block(ref stmts) => {
+            // The block's bindings are only in scope within it
+            scope.push_child(HashSet::new());
+            let res = infer_block(scope, stmts.as_slice());
+            scope.pop_child();
+            res
+        }
+        ExprKind::If(box ref cond, box ref thn, box ref els) => {
             // Infer the type of the condition, and ensure it is Bool
             let cond_ty = try!(infer_expr(scope, cond));
-            try!(unify::unify(scope, &cond_ty, &Ty::Ident(Ident(Atom::from_slice("Bool"), BuiltIn))));
+            try!(unify_at(scope, cond.span, "condition must be a Bool", &cond_ty,
+                          &Ty::Ident(Ident(Atom::from_slice("Bool"), BuiltIn))));
 
             // Infer the type of the different branches
             let thn_ty = try!(infer_expr(scope, thn));
@@ -152,27 +269,244 @@ pub fn infer_expr(scope: &mut Scope, e: &Expr) -> Result<Ty, String> {
 
             Ok(Ty::Union(vec![thn_ty, els_ty]))
         }
+        ExprKind::Match(box ref scrut, ref arms) => pattern::infer_match(scope, scrut, arms.as_slice()),
+        ExprKind::While(box ref cond, box ref body) => {
+            let cond_ty = try!(infer_expr(scope, cond));
+            try!(unify_at(scope, cond.span, "condition must be a Bool", &cond_ty,
+                          &Ty::Ident(Ident(Atom::from_slice("Bool"), BuiltIn))));
+            try!(infer_expr(scope, body));
+
+            Ok(util::val_ty(scope, Ty::Ident(Ident(Atom::from_slice("Null"), BuiltIn))))
+        }
+        ExprKind::For(ref var, box ref iter, box ref body) => infer_for(scope, var, iter, body),
+        // Neither produces a value, so they fit in anywhere
+        ExprKind::Break | ExprKind::Continue => Ok(scope.introduce_type_var()),
+        ExprKind::Return(box ref value) => {
+            let ty = try!(infer_expr(scope, value));
+            let result = scope.result().cloned().expect("ICE: `return` outside of a method");
+            try!(unify_at(scope, value.span, "in this return", &result, &ty));
+
+            // Like break, return doesn't produce a value
+            Ok(scope.introduce_type_var())
+        }
+        ExprKind::Ascribe(box ref expr, ref annot) => {
+            let ty = try!(infer_expr(scope, expr));
+            try!(unify_annot(scope, annot, expr.span, &ty));
+            Ok(annot.node.clone())
+        }
+        ExprKind::Error => {
+            // Anything goes, so that we don't report errors caused by the syntax error
+            Ok(scope.introduce_type_var())
+        }
     }
 }
 
-pub fn infer_stmt(scope: &mut Scope, stmt: &Stmt) -> Result<(), String> {
-    match *stmt {
-        Stmt::Expr(ref expr) => {
+/// Infers the type of `for var in iter { body }`. Iterators are records with a
+/// `next(self)` method, which the loop calls as `iter:next(iter)`. It produces
+/// null once there are no items left, so var has the other types which it can produce.
+fn infer_for(scope: &mut Scope, var: &Ident, iter: &Expr, body: &Expr) -> Result<Ty, Diagnostic> {
+    let iter_ty = try!(infer_expr(scope, iter));
+    let item_ty = scope.introduce_type_var();
+    let require_ty = Ty::Rec(Some(box scope.introduce_type_var()), None,
+                             vec![TyProp::Method(Symbol::from_slice("next"), vec![iter_ty.clone()],
+                                                 item_ty.clone())]);
+    try!(unify_at(scope, iter.span, "expected an iterator", &iter_ty, &require_ty)
+         .map_err(|err| {
+             err.with_note(format!("iterators have a `next(self)` method, which is given the \
+                                    iterator and produces null once they are done"))
+         }));
+
+    let items: Vec<Ty> = pattern::alternatives(scope, &item_ty).into_iter().filter(|alt| {
+        match *alt {
+            Ty::Rec(_, Some(Ident(ref atom, BuiltIn)), _) => atom.as_slice() != "Null",
+            _ => true,
+        }
+    }).collect();
+
+    // Like parameters, the loop variable isn't generalized
+    let var_ty = scope.lookup_data_var(var);
+    let mut bound = HashSet::new();
+    bound.insert(var.clone());
+    scope.push_child(bound);
+    let item_ty = pattern::union_of(scope, items);
+    let res = unify_at(scope, iter.span, "in this loop", &var_ty, &item_ty).and_then(|_| {
+        infer_expr(scope, body)
+    });
+    scope.pop_child();
+    try!(res);
+
+    Ok(util::val_ty(scope, Ty::Ident(Ident(Atom::from_slice("Null"), BuiltIn))))
+}
+
+pub fn infer_stmt(scope: &mut Scope, stmt: &Stmt) -> Result<(), Diagnostic> {
+    match stmt.kind {
+        StmtKind::Expr(ref expr) => {
             try!(infer_expr(scope, expr));
             Ok(())
         }
-        Stmt::Let(ref ident, ref expr) => {
-            let ty = try!(infer_expr(scope, expr));
+        StmtKind::Let(ref ident, ref annot, ref expr) => {
+            let mut ty = try!(infer_expr(scope, expr));
+            if let Some(ref annot) = *annot {
+                // The variable has the annotated type, rather than the inferred one
+                try!(unify_annot(scope, annot, expr.span, &ty));
+                ty = annot.node.clone();
+            }
             // TODO: Better error message on failure
             let ident = scope.lookup_data_var(ident);
-            unify::unify(scope, &ident, &ty)
+            unify_at(scope, stmt.span, "in this binding", &ident, &ty)
+        }
+        // Aliases are defined when entering their block
+        StmtKind::Type(..) | StmtKind::Name(_) | StmtKind::Empty => Ok(())
+    }
+}
+
+/// The `let`s of a block, grouped so that bindings which refer to each other
+/// are inferred together, and can only use each other monomorphically. Once a
+/// group has been inferred, its variables are generalized, so later groups
+/// and statements can use them at different types.
+struct Lets<'a> {
+    stmts: &'a [Stmt],
+    groups: Vec<Vec<usize>>,
+    /// The index of the group of each variable which the block binds
+    group_of: HashMap<Ident, usize>,
+    inferred: Vec<bool>,
+}
+
+impl<'a> Lets<'a> {
+    fn new(stmts: &'a [Stmt]) -> Lets<'a> {
+        let groups = group::binding_groups(stmts);
+        let mut group_of = HashMap::new();
+        for (g, group) in groups.iter().enumerate() {
+            for &i in group.iter() {
+                if let StmtKind::Let(ref ident, _, _) = stmts[i].kind {
+                    group_of.insert(ident.clone(), g);
+                }
+            }
+        }
+
+        Lets{
+            stmts: stmts,
+            inferred: groups.iter().map(|_| false).collect(),
+            groups: groups,
+            group_of: group_of,
+        }
+    }
+
+    /// Infers the statement at index i, returning the type of an expression
+    /// statement. Statements are inferred in source order, so constraints from
+    /// earlier statements are known when a group is generalized. A group is
+    /// inferred at its last `let`, or earlier if a statement refers to it first.
+    fn infer_stmt(&mut self, scope: &mut Scope, i: usize) -> Result<Option<Ty>, Diagnostic> {
+        let stmts = self.stmts;
+        match stmts[i].kind {
+            StmtKind::Let(ref ident, _, _) => {
+                let g = self.group_of[ident.clone()];
+                if self.groups[g].last() == Some(&i) {
+                    try!(self.infer_group(scope, g));
+                }
+                Ok(None)
+            }
+            StmtKind::Expr(ref expr) => {
+                try!(self.infer_referenced(scope, expr));
+                Ok(Some(try!(infer_expr(scope, expr))))
+            }
+            // Aliases are defined when entering their block
+            StmtKind::Type(..) | StmtKind::Name(_) | StmtKind::Empty => Ok(None)
+        }
+    }
+
+    /// Infers the groups which expr refers to, if they haven't been already
+    fn infer_referenced(&mut self, scope: &mut Scope, expr: &Expr) -> Result<(), Diagnostic> {
+        let mut vars = HashSet::new();
+        expr.referenced_vars(&mut vars);
+        let mut groups: Vec<usize> = vars.iter().filter_map(|var| self.group_of.get(var).cloned()).collect();
+        groups.sort();
+
+        for &g in groups.iter() {
+            try!(self.infer_group(scope, g));
+        }
+        Ok(())
+    }
+
+    fn infer_group(&mut self, scope: &mut Scope, g: usize) -> Result<(), Diagnostic> {
+        if self.inferred[g] { return Ok(()) }
+        self.inferred[g] = true;
+
+        // The groups which this one refers to come first
+        let stmts = self.stmts;
+        let group = self.groups[g].clone();
+        for &i in group.iter() {
+            if let StmtKind::Let(_, _, ref expr) = stmts[i].kind {
+                try!(self.infer_referenced(scope, expr));
+            }
+        }
+
+        let idents: Vec<Ident> = group.iter().map(|&i| {
+            match stmts[i].kind {
+                StmtKind::Let(ref ident, _, _) => ident.clone(),
+                _ => unreachable!(),
+            }
+        }).collect();
+
+        scope.push_child(idents.iter().cloned().collect());
+        let mut res = Ok(());
+        for &i in group.iter() {
+            res = infer_stmt(scope, &stmts[i]);
+            if res.is_err() { break }
+        }
+        scope.pop_child();
+        try!(res);
+
+        scope.generalize(idents.as_slice());
+        Ok(())
+    }
+}
+
+fn infer_block(scope: &mut Scope, stmts: &[Stmt]) -> Result<Ty, Diagnostic> {
+    define_aliases(scope, stmts);
+
+    // The value of the block is its last statement, if it is an expression
+    let mut lets = Lets::new(stmts);
+    let mut last = None;
+    for i in 0..stmts.len() {
+        last = try!(lets.infer_stmt(scope, i));
+    }
+
+    match last {
+        Some(ty) => Ok(ty),
+        // If the last element isn't an Expression, the value is Null ({})
+        None => Ok(util::val_ty(scope, Ty::Ident(Ident(Atom::from_slice("Null"), BuiltIn)))),
+    }
+}
+
+/// Infers the types of toplevel statements within an existing scope, such that
+/// the variables which they bind remain available to later calls. Returns the
+/// type of each binding and expression statement, in order.
+pub fn infer_toplevel(scope: &mut Scope, stmts: &[Stmt]) -> Result<Vec<(Option<Ident>, Ty)>, Diagnostic> {
+    define_aliases(scope, stmts);
+
+    let mut lets = Lets::new(stmts);
+    let mut expr_tys = Vec::with_capacity(stmts.len());
+    for i in 0..stmts.len() {
+        expr_tys.push(try!(lets.infer_stmt(scope, i)));
+    }
+
+    // A binding's type is only known once its whole group has been inferred
+    let mut tys = vec![];
+    for (stmt, expr_ty) in stmts.iter().zip(expr_tys.into_iter()) {
+        match stmt.kind {
+            StmtKind::Let(ref ident, _, _) => {
+                tys.push((Some(ident.clone()), scope.lookup_data_var(ident)));
+            }
+            StmtKind::Expr(_) => tys.push((None, expr_ty.unwrap())),
+            StmtKind::Type(..) | StmtKind::Name(_) | StmtKind::Empty => {}
         }
-        Stmt::Empty => Ok(())
     }
+    Ok(tys)
 }
 
-pub fn infer_program(body: Vec<Stmt>) -> Result<InferValue, String> {
+pub fn infer_program(body: Vec<Stmt>) -> Result<InferValue, Diagnostic> {
     let mut scope = Scope::new();
-    try!(infer_expr(&mut scope, &Expr::Block(body)));
+    try!(infer_expr(&mut scope, &Expr::new(ExprKind::Block(body), Span::dummy())));
     Ok(scope.as_infervalue())
 }
@@ -0,0 +1,276 @@
+use std::cmp;
+use il::*;
+use span::Span;
+use simplify;
+use diagnostic::{Diagnostic, Kind};
+use infer::env::{Scope, Env};
+use infer::util::expand_alias;
+use infer::{infer_expr, unify_at};
+
+// Patterns are checked against the alternatives of the scrutinee's type: the
+// options of its union, with all of the type variables and aliases which we
+// know about expanded. Each arm only sees the alternatives which the earlier
+// arms didn't match completely, which narrows the types of its bindings.
+
+/// How much of a type is matched by a pattern
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+enum Coverage {
+    /// No values of the type match
+    Never,
+    /// Some values of the type might match
+    Maybe,
+    /// Every value of the type matches
+    Always,
+}
+
+/// The types which a value of type ty may have. Unbound type variables stand
+/// for values of unknown type. Those in a union alongside known types are left
+/// out, as literals and `if` add them to every union which they create.
+pub fn alternatives(scope: &Scope, ty: &Ty) -> Vec<Ty> {
+    let mut alts = vec![];
+    collect(scope, ty, &None, &[], &mut alts, &mut vec![]);
+
+    let known: Vec<Ty> = alts.iter().filter(|alt| ! alt.is_ident()).cloned().collect();
+    if known.is_empty() { alts.truncate(1); alts } else { known }
+}
+
+/// Adds the alternatives of ty, extended with brand and props, to out
+fn collect(scope: &Scope, ty: &Ty, brand: &Brand, props: &[TyProp],
+           out: &mut Vec<Ty>, seen: &mut Vec<Ident>) {
+    let alt = match *ty {
+        Ty::Ident(ref id) => {
+            if ! seen.contains(id) {
+                if let Some(nty) = scope.lookup_type_var(id) {
+                    seen.push(id.clone());
+                    collect(scope, nty, brand, props, out, seen);
+                    seen.pop();
+                    return;
+                }
+            }
+
+            if brand.is_none() && props.is_empty() {
+                ty.clone()
+            } else {
+                Ty::Rec(Some(box ty.clone()), brand.clone(), props.to_vec())
+            }
+        }
+        Ty::Rec(ref extends, ref rbrand, ref rprops) => {
+            let brand = brand.clone().or(rbrand.clone());
+            let props: Vec<TyProp> = props.iter().chain(rprops.iter()).cloned().collect();
+            match *extends {
+                Some(box ref extends) => return collect(scope, extends, &brand, props.as_slice(), out, seen),
+                None => Ty::Rec(None, brand, props),
+            }
+        }
+        Ty::Union(ref opts) => {
+            for opt in opts.iter() {
+                collect(scope, opt, brand, props, out, seen);
+            }
+            return;
+        }
+        Ty::Alias(ref id, ref args) => {
+            let expanded = expand_alias(scope, id, args.as_slice());
+            return collect(scope, &expanded, brand, props, out, seen);
+        }
+    };
+
+    if ! out.contains(&alt) { out.push(alt); }
+}
+
+/// How much of alt is matched by the runtime test for the given name,
+/// fields and methods. Builtin values aren't records, so they are only
+/// matched by their own name.
+fn shape_coverage(brand: &Brand, vals: &[Symbol], methods: &[Symbol], alt: &Ty) -> Coverage {
+    let (open, abrand, props) = match *alt {
+        Ty::Rec(ref extends, ref abrand, ref props) => (extends.is_some(), abrand, props),
+        _ => return Coverage::Maybe,
+    };
+
+    if let Some(Ident(_, BuiltIn)) = *abrand {
+        return if brand == abrand { Coverage::Always } else { Coverage::Never };
+    }
+
+    let mut coverage = Coverage::Always;
+    match (brand, abrand) {
+        (&Some(_), &Some(_)) if brand != abrand => return Coverage::Never,
+        (&Some(_), &None) if ! open => return Coverage::Never,
+        (&Some(_), &None) => coverage = Coverage::Maybe,
+        // An open record which we don't know any fields of could be a builtin
+        (&None, &None) if open && vals.is_empty() => coverage = Coverage::Maybe,
+        _ => {}
+    }
+
+    for (symb, is_val) in vals.iter().map(|s| (s, true)).chain(methods.iter().map(|s| (s, false))) {
+        match props.iter().find(|prop| prop.symbol() == symb) {
+            Some(&TyProp::Val(..)) if is_val => {}
+            Some(&TyProp::Method(..)) if ! is_val => {}
+            Some(_) => return Coverage::Never,
+            None if open => coverage = Coverage::Maybe,
+            None => return Coverage::Never,
+        }
+    }
+
+    coverage
+}
+
+/// The type of the property symb in the record type alt, if it has one
+fn field_ty(alt: &Ty, symb: &Symbol) -> Option<Ty> {
+    if let Ty::Rec(_, _, ref props) = *alt {
+        for prop in props.iter() {
+            if let TyProp::Val(ref psymb, ref ty, _) = *prop {
+                if psymb == symb { return Some(ty.clone()) }
+            }
+        }
+    }
+    None
+}
+
+fn coverage(scope: &Scope, pat: &Pat, alt: &Ty) -> Coverage {
+    match pat.kind {
+        PatKind::Wild | PatKind::Bind(_) => Coverage::Always,
+        PatKind::Typed(_, ref annot) => {
+            let mut shapes = vec![];
+            collect(scope, &annot.node, &None, &[], &mut shapes, &mut vec![]);
+            shapes.iter().map(|shape| {
+                match *shape {
+                    Ty::Rec(_, ref brand, ref props) => {
+                        let vals: Vec<Symbol> = props.iter().filter_map(|prop| {
+                            if let TyProp::Val(ref symb, _, _) = *prop { Some(symb.clone()) } else { None }
+                        }).collect();
+                        let methods: Vec<Symbol> = props.iter().filter_map(|prop| {
+                            if let TyProp::Method(ref symb, _, _) = *prop { Some(symb.clone()) } else { None }
+                        }).collect();
+                        shape_coverage(brand, vals.as_slice(), methods.as_slice(), alt)
+                    }
+                    // A type variable matches anything
+                    _ => Coverage::Always,
+                }
+            }).max().unwrap_or(Coverage::Never)
+        }
+        PatKind::Rec(ref brand, ref fields) => {
+            let vals: Vec<Symbol> = fields.iter().map(|&(ref symb, _)| symb.clone()).collect();
+            let mut cov = shape_coverage(brand, vals.as_slice(), &[], alt);
+
+            for &(ref symb, ref sub) in fields.iter() {
+                if cov == Coverage::Never { break }
+                let sub_cov = match field_ty(alt, symb) {
+                    Some(ty) => {
+                        let covs: Vec<Coverage> = alternatives(scope, &ty).iter().map(|falt| {
+                            coverage(scope, sub, falt)
+                        }).collect();
+                        if covs.iter().all(|&c| c == Coverage::Always) {
+                            Coverage::Always
+                        } else if covs.iter().all(|&c| c == Coverage::Never) {
+                            Coverage::Never
+                        } else {
+                            Coverage::Maybe
+                        }
+                    }
+                    None => match sub.kind {
+                        PatKind::Wild | PatKind::Bind(_) => Coverage::Always,
+                        _ => Coverage::Maybe,
+                    },
+                };
+                cov = cmp::min(cov, sub_cov);
+            }
+
+            cov
+        }
+    }
+}
+
+/// The union of tys, or an unknown type if there are none
+pub fn union_of(scope: &mut Scope, mut tys: Vec<Ty>) -> Ty {
+    match tys.len() {
+        0 => scope.introduce_type_var(),
+        1 => tys.pop().unwrap(),
+        _ => Ty::Union(tys),
+    }
+}
+
+fn bind(scope: &mut Scope, id: &Ident, tys: Vec<Ty>, span: Span) -> Result<(), Diagnostic> {
+    let ty = union_of(scope, tys);
+    let var = scope.lookup_data_var(id);
+    unify_at(scope, span, "in this pattern", &var, &ty)
+}
+
+/// Binds the variables in pat, which is matched against a value with
+/// one of the types in alts
+fn bind_pat(scope: &mut Scope, pat: &Pat, alts: &[Ty]) -> Result<(), Diagnostic> {
+    let narrowed: Vec<Ty> = alts.iter().filter(|alt| {
+        coverage(scope, pat, alt) != Coverage::Never
+    }).cloned().collect();
+
+    match pat.kind {
+        PatKind::Wild => Ok(()),
+        PatKind::Bind(ref id) => bind(scope, id, narrowed, pat.span),
+        PatKind::Typed(ref id, ref annot) => {
+            // Values of unknown type are known to have the annotated type
+            let tys = narrowed.into_iter().map(|alt| {
+                if alt.is_rec() { alt } else { annot.node.clone() }
+            }).collect();
+            bind(scope, id, tys, pat.span)
+        }
+        PatKind::Rec(_, ref fields) => {
+            for &(ref symb, ref sub) in fields.iter() {
+                let mut tys = vec![];
+                for alt in narrowed.iter() {
+                    tys.push(match field_ty(alt, symb) {
+                        Some(ty) => ty,
+                        None => scope.introduce_type_var(),
+                    });
+                }
+                let ty = union_of(scope, tys);
+                let sub_alts = alternatives(scope, &ty);
+                try!(bind_pat(scope, sub, sub_alts.as_slice()));
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Infers the type of `match scrut { arms }`, which is the union of the
+/// types of its arms. Every alternative of the scrutinee's type must be
+/// matched by some arm.
+pub fn infer_match(scope: &mut Scope, scrut: &Expr, arms: &[Arm]) -> Result<Ty, Diagnostic> {
+    let scrut_ty = try!(infer_expr(scope, scrut));
+    let mut remaining = alternatives(scope, &scrut_ty);
+
+    let mut arm_tys = Vec::with_capacity(arms.len());
+    for arm in arms.iter() {
+        if ! remaining.iter().any(|alt| coverage(scope, &arm.pat, alt) != Coverage::Never) {
+            scope.warn(Diagnostic::warning(Kind::UnreachablePattern, format!("Unreachable pattern"))
+                       .with_primary(arm.pat.span, "no values reach this arm")
+                       .with_note(format!("the earlier arms match everything which this one could")));
+        }
+
+        // Like parameters, the variables bound by a pattern aren't generalized
+        scope.push_child(arm.pat.bindings().into_iter().collect());
+        let res = bind_pat(scope, &arm.pat, remaining.as_slice()).and_then(|_| {
+            infer_expr(scope, &arm.body)
+        });
+        scope.pop_child();
+        arm_tys.push(try!(res));
+
+        let matched = &*scope;
+        remaining.retain(|alt| coverage(matched, &arm.pat, alt) != Coverage::Always);
+    }
+
+    if ! remaining.is_empty() {
+        let missing: Vec<String> = remaining.iter().map(|alt| {
+            if alt.is_ident() {
+                format!("values of unknown type")
+            } else {
+                format!("`{}`", simplify::simplify_ty(alt, scope.type_vars()))
+            }
+        }).collect();
+
+        return Err(Diagnostic::error(
+            Kind::NonExhaustiveMatch,
+            format!("Non-exhaustive match: {} not matched", missing.connect(", "))
+        ).with_primary(scrut.span, "not every value of this is matched")
+         .with_note(format!("add a `_ => ...` arm to match everything else")));
+    }
+
+    Ok(Ty::Union(arm_tys))
+}
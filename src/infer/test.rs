@@ -2,9 +2,11 @@ use infer;
 use lexer;
 use parser;
 use scope;
+use simplify;
+use diagnostic::{Diagnostic, Kind};
 
 /// Compiles some code, and then infers its type.
-fn infer_code(code: &str) -> Result<infer::InferValue, String> {
+fn infer_code(code: &str) -> Result<infer::InferValue, Diagnostic> {
     // TODO: The program should probably panic if the error wasn't caused by infer_program(ast)
     // Because these tests are only supposed to be testing inference, not lexing/parsing
     let tokens = try!(lexer::lex(code));
@@ -59,6 +61,563 @@ fn mul_ints() {
     });
 }
 
+#[test]
+fn compare_ints() {
+    infer_ok(stringify!{
+        let max = fn(a, b) { if a > b { a } else { b } };
+        max(1, 2) + 1;
+        1 + 2 == 3 && 4 != 5 || 1 <= 0;
+    });
+}
+
+#[test]
+fn compare_random_records() {
+    infer_err(stringify!{
+        {a: 5} < 10;
+    });
+}
+
+#[test]
+fn methods_have_independent_params() {
+    infer_ok(stringify!{
+        let r = { fn inc(n) { n + 1 }, fn size(s) { s:len() }, fn zero() { 0 } };
+        r:inc(1) + r:size("ab") + r:zero();
+    });
+}
+
+#[test]
+fn builtin_types() {
+    infer_ok(stringify!{
+        let s = "a" + "b";
+        if true { s:len() } else { 0 } + 1;
+    });
+
+    infer_err(stringify!{
+        "a" + 1;
+    });
+}
+
+#[test]
+fn annotations() {
+    infer_ok(stringify!{
+        let x: { name: Str } = { name: "duck" };
+        let f = fn(a: Int, b) -> Int { a + b };
+        (f(1, 2) : Int) * 2;
+    });
+
+    // Type variables are shared within a signature
+    infer_ok(stringify!{
+        let id = fn(x: a) -> a { x };
+        id(5) + 1;
+        id({ b: 1 }).b;
+    });
+
+    infer_err(stringify!{
+        let f = fn(a: Int) { a };
+        f({});
+    });
+
+    infer_err(stringify!{
+        let f = fn(a) -> Int { {} };
+    });
+}
+
+#[test]
+fn annotation_mismatch_points_at_annotation() {
+    let err = infer_code("let x = { a: 1 };\nlet y: { b: Int } = x").unwrap_err();
+    assert_eq!(err.kind, Kind::MissingProperty);
+    assert!(err.notes.iter().any(|note| note.contains("`{ b: Int }`")), "{:?}", err.notes);
+    let span = err.primary_span().unwrap();
+    assert_eq!((span.line, span.col), (2, 8));
+}
+
+#[test]
+fn type_aliases() {
+    infer_ok(stringify!{
+        type Point = { x: Int, y: Int };
+        let origin: Point = { x: 0, y: 0 };
+        origin.x + 1;
+    });
+
+    // Aliases can be recursive, and used before they are declared
+    infer_ok(stringify!{
+        type Named[A] = List[A]{ name: Str };
+        type List[A] = { car: A, cdr: List[A] };
+        let second = fn(l: Named[Int]) -> Int { l.cdr.car + 1 };
+    });
+
+    infer_err(stringify!{
+        type Point = { x: Int, y: Int };
+        let p: Point = { x: 0 };
+    });
+
+    infer_err(stringify!{
+        type List[A] = { car: A, cdr: List[A] };
+        let f = fn(l: List[Int]) { l.cdr.cdr.car.foo };
+    });
+}
+
+#[test]
+fn aliases_are_simplified_to_their_name() {
+    let iv = simplify::simplify(&infer_code("
+        type Point = { x: Int, y: Int };
+        let p: Point = { x: 1, y: 2 };
+    ").unwrap());
+
+    let (_, ty) = iv.data_vars.iter().find(|&(id, _)| id.0 == "p").unwrap();
+    assert_eq!(format!("{}", ty), "Point");
+}
+
+#[test]
+fn named_records() {
+    infer_ok(stringify!{
+        name P;
+        let mk = fn(x) { #P#{ x: x } };
+        let f = fn(p: #P#{ x: Int }) { p.x };
+        f(mk(1)) + 1;
+    });
+
+    // Property access doesn't care about names
+    infer_ok(stringify!{
+        name P;
+        let get_x = fn(r) { r.x };
+        get_x(#P#{ x: 1 }) + get_x({ x: 2 });
+    });
+
+    // A record with the same properties isn't a P
+    let err = infer_code(stringify!{
+        name P;
+        let f = fn(p: #P#{ x: Int }) { p.x };
+        f({ x: 1 });
+    }).unwrap_err();
+    assert_eq!(err.kind, Kind::BrandMismatch);
+    assert!(err.notes[0].contains("#P#{ x: Int"), "{:?}", err.notes);
+    assert!(err.notes.iter().all(|note| ! note.contains("::") && ! note.contains("~")),
+            "{:?}", err.notes);
+
+    infer_err(stringify!{
+        name P;
+        name Q;
+        let f = fn(p: #P#) { p };
+        f(#Q#{});
+    });
+
+    // Records can have operator methods, but that doesn't make them Ints
+    infer_ok(stringify!{
+        let v = { x: 1, fn +(o) { o.x + 1 } };
+        (v + { x: 2 }) * 2;
+    });
+
+    infer_err(stringify!{
+        let f = fn(i: Int) { i + 1 };
+        f({ fn +(o) { o }, fn *(o) { o } });
+    });
+}
+
+#[test]
+fn match_narrows_unions() {
+    infer_ok(stringify!{
+        let f = fn(c) {
+            let v = if c { { x: 1, y: 2 } } else { 5 };
+            match v {
+                {x, y} => x + y,
+                n: Int => n * 2,
+            } + 1
+        };
+    });
+
+    // Named records are told apart by their names
+    infer_ok(stringify!{
+        name Cat;
+        name Dog;
+        let speak = fn(pet: #Cat#{ lives: Int } | #Dog#{ name: Str }) {
+            match pet {
+                #Cat#{ lives } => lives:to_str(),
+                #Dog#{ name } => name,
+            } + "!"
+        };
+    });
+
+    // Values of unknown type need a catch-all arm
+    infer_ok(stringify!{
+        let f = fn(x) { match x { n: Int => n + 1, _ => 0 } };
+    });
+
+    infer_err(stringify!{
+        let f = fn(x) { match x { n: Int => n + 1 } };
+    });
+}
+
+#[test]
+fn non_exhaustive_match() {
+    let err = infer_code(stringify!{
+        let f = fn(c) {
+            match if c { { x: 1 } } else { "s" } {
+                {x} => x,
+            }
+        };
+    }).unwrap_err();
+    assert_eq!(err.kind, Kind::NonExhaustiveMatch);
+}
+
+#[test]
+fn unreachable_arms_are_warned_about() {
+    let iv = infer_code("match 1 { n: Int => n, s: Str => 0, _ => 1 }").unwrap();
+    let kinds: Vec<_> = iv.warnings.iter().map(|warning| warning.kind).collect();
+    assert_eq!(kinds, vec![Kind::UnreachablePattern, Kind::UnreachablePattern]);
+
+    // A value of type Int doesn't have the field x
+    let iv = infer_code("match 1 { {x} => x, _ => 1 }").unwrap();
+    assert_eq!(iv.warnings.len(), 1);
+}
+
+#[test]
+fn record_update() {
+    infer_ok(stringify!{
+        let p = { x: 1, y: "a" };
+        let q = p with { y: 2, z: true };
+        q.x + q.y;
+        if q.z { p.y + "b" } else { "c" };
+    });
+
+    // Properties which aren't known yet are added
+    infer_ok(stringify!{
+        let f = fn(r) { r with { extra: 1 } };
+        f({ a: "s" }).a + f({ a: "t" }).a;
+        f({}).extra + 1;
+    });
+
+    // Replaced properties get their new type
+    infer_err(stringify!{
+        let p = { x: 1 } with { x: "s" };
+        p.x + 1;
+    });
+
+    // The copy isn't named, so it can't be passed off as the original
+    infer_err(stringify!{
+        name P;
+        let f = fn(p: #P#{ x: Int }) { p.x };
+        f(#P#{ x: 1 } with { x: 2 });
+    });
+
+    infer_err(stringify!{
+        5 with { x: 1 };
+    });
+}
+
+#[test]
+fn mutable_fields() {
+    infer_ok(stringify!{
+        let r = { mut n: 0 };
+        r.n <- r.n + 1;
+        r.n * 2;
+    });
+
+    // Functions which assign to a field require it to be mutable
+    infer_ok(stringify!{
+        let inc = fn(r) { r.n <- r.n + 1 };
+        inc({ mut n: 1 });
+        inc({ mut n: 2, s: "a" });
+    });
+
+    infer_err(stringify!{
+        { n: 0 }.n <- 1;
+    });
+
+    let err = infer_code(stringify!{
+        let inc = fn(r) { r.n <- r.n + 1 };
+        inc({ n: 1 });
+    }).unwrap_err();
+    assert_eq!(err.kind, Kind::ImmutableProperty);
+    assert!(err.notes.iter().any(|note| note.contains("mut n: ")), "{:?}", err.notes);
+    assert!(err.notes.iter().all(|note| ! note.contains("::") && ! note.contains("~")),
+            "{:?}", err.notes);
+
+    // Mutable fields are invariant
+    infer_err(stringify!{
+        let r = { mut n: 0 };
+        r.n <- "s";
+    });
+
+    // The types in mutable fields aren't generalized
+    infer_err(stringify!{
+        let r = { mut f: fn(y) { y } };
+        r.f <- fn(y) { y + 1 };
+        r.f("s");
+    });
+
+    // Nor are the types of the elements of lists, which are mutable through `push`
+    infer_err(stringify!{
+        let fs = [fn(y) { y }];
+        fs:push(fn(y) { y + 1 });
+        fs[0]("s");
+    });
+
+    infer_err(stringify!{
+        let r = { xs: [] };
+        r.xs:push(1);
+        r.xs:push("s");
+        r.xs[0] + 1;
+    });
+}
+
+#[test]
+fn loops() {
+    infer_ok(stringify!{
+        let s = { mut i: 0, mut total: 0 };
+        while s.i < 10 {
+            s.i <- s.i + 1;
+            if s.i == 5 { continue };
+            s.total <- s.total + s.i;
+        };
+        s.total + 1;
+    });
+
+    // The loop variable doesn't include the null which ends the loop
+    infer_ok(stringify!{
+        let range = fn(lo, hi) {
+            let s = { mut i: lo };
+            { fn next(self) { if s.i < hi { let i = s.i; s.i <- i + 1; i } else { null } } }
+        };
+        for x in range(0, 10) {
+            if x == 5 { break };
+            x + 1;
+        };
+        for s in { fn next(self) { if true { "a" } else { null } } } { s + "b" };
+    });
+
+    // The iterator is passed to `next`, so it can keep its state in itself
+    infer_ok(stringify!{
+        let count = {
+            mut i: 0,
+            fn next(self) { if self.i < 3 { self.i <- self.i + 1; self.i } else { null } }
+        };
+        for x in count { x + 1 };
+    });
+
+    infer_err(stringify!{
+        for x in { fn next() { null } } { x };
+    });
+
+    infer_err(stringify!{
+        while 1 { 2 };
+    });
+
+    infer_err(stringify!{
+        for x in 5 { x };
+    });
+
+    infer_err(stringify!{
+        for s in { fn next(self) { if true { "a" } else { null } } } { s + 1 };
+    });
+
+    // Loops produce null
+    infer_err(stringify!{
+        let n = while false { 1 };
+        n + 1;
+    });
+}
+
+#[test]
+fn early_return() {
+    infer_ok(stringify!{
+        let index_of = fn(xs, x) {
+            let s = { mut i: 0 };
+            while s.i < xs:len() {
+                if xs[s.i] == x { return s.i };
+                s.i <- s.i + 1;
+            };
+            xs:len()
+        };
+        index_of([1, 2], 2) + 1;
+    });
+
+    infer_ok(stringify!{
+        let f = fn(x) { if x { return "a" }; "b" };
+        f(true) + "c";
+    });
+
+    // Returned values are part of the result type
+    infer_err(stringify!{
+        let f = fn(x) -> Int { if x { return "a" }; 1 };
+    });
+
+    infer_err(stringify!{
+        let f = fn(x) { if x { return 1 }; 2 };
+        f(true) + "s";
+    });
+}
+
+#[test]
+fn recursive_bindings() {
+    infer_ok(stringify!{
+        let fib = fn(n) { if n < 2 { n } else { fib(n - 1) + fib(n - 2) } };
+        fib(10) + 1;
+    });
+
+    // Bindings can refer to ones which are declared after them
+    infer_ok(stringify!{
+        let even = fn(n) { if n == 0 { true } else { odd(n - 1) } };
+        let odd = fn(n) { if n == 0 { false } else { even(n - 1) } };
+        if even(4) { 1 } else { 2 };
+    });
+
+    // Within its own definition, a binding isn't generalized yet
+    infer_err(stringify!{
+        let f = fn(n) { f("s"); n + 1 };
+    });
+
+    infer_err(stringify!{
+        let even = fn(n) { if n == 0 { true } else { odd(n - 1) } };
+        let odd = fn(n) { if n == 0 { false } else { even(n - 1) } };
+        even(4) + 1;
+    });
+
+    // Once the group has been inferred, it is generalized
+    infer_ok(stringify!{
+        let use_id = fn() { id(1) + 1; id("a") + "b" };
+        let id = fn(x) { x };
+    });
+
+    // A statement can use a binding before its `let`
+    infer_ok(stringify!{
+        id(1) + 1;
+        let id = fn(x) { x };
+        id("a") + "b";
+    });
+
+    // Statements are inferred in source order, so the error is at the later one
+    let err = infer_code("let xs = [];\nxs:push(\"s\");\nlet y = xs[0] + 1;").unwrap_err();
+    assert_eq!(err.primary_span().unwrap().line, 3);
+}
+
+#[test]
+fn let_polymorphism() {
+    infer_ok(stringify!{
+        let id = fn(x) { x };
+        let pair = { a: id(1), b: id("a") };
+        pair.a + 1;
+        pair.b + "b";
+    });
+
+    // Bindings within functions are generalized too
+    infer_ok(stringify!{
+        let f = fn(x) {
+            let id = fn(y) { y };
+            id(x);
+            id(1) + 1
+        };
+        f("s") + 1;
+    });
+
+    // Parameters aren't generalized, so each use of g has the same type
+    infer_err(stringify!{
+        let f = fn(g) { g(1); g("a") };
+        f(fn(x) { x + 1 });
+    });
+
+    // A binding which shares the type of a parameter isn't generalized either
+    infer_err(stringify!{
+        let f = fn(x) { let y = x; y + 1; y:len() };
+        f(1);
+    });
+
+    infer_err(stringify!{
+        let f = fn(x) {
+            let get = fn() { x };
+            get() + 1;
+            get():len()
+        };
+        f(1);
+    });
+
+    // Nor are the variables bound by patterns
+    infer_err(stringify!{
+        let f = fn(r) { match r { {g} => { g(1); g("a") } } };
+        f({ g: fn(x) { x + 1 } });
+    });
+
+    // Functions which create mutable state make it anew for each call, so they are
+    // still generalized
+    infer_ok(stringify!{
+        let wrap = fn(x) { [x] };
+        wrap(1)[0] + 1;
+        wrap("a")[0] + "b";
+
+        let cell = fn(x) { { mut v: x } };
+        cell(1).v + 1;
+        cell("a").v + "b";
+    });
+
+    // null can be used as part of any union
+    infer_ok(stringify!{
+        let f = fn(x) { if x { 1 } else { null } };
+        let g = fn(x) { if x { "a" } else { null } };
+    });
+}
+
+#[test]
+fn recursive_types() {
+    // Records can refer to themselves through their properties
+    infer_ok(stringify!{
+        let len = fn(l) { if l.empty { 0 } else { 1 + len(l.rest) } };
+        let walk = fn(it) { walk(it:next()) };
+        let same = fn(a) { a:eq(a) };
+    });
+
+    // A union which includes the type itself only needs the other options
+    infer_ok(stringify!{
+        let f = fn(c, x) { f(c, if c { x } else { 1 }) };
+    });
+
+    // Functions can't, as their types would be infinite
+    let err = infer_code("let f = fn(x) { x(x) }").unwrap_err();
+    assert_eq!(err.kind, Kind::InfiniteType);
+    assert!(err.notes[0].starts_with("its type would be `Self = "), "{}", err.notes[0]);
+    assert!(err.notes[0].split("Self").count() >= 3, "{}", err.notes[0]);
+
+    let err = infer_code("let f = fn() { f }").unwrap_err();
+    assert_eq!(err.kind, Kind::InfiniteType);
+}
+
+#[test]
+fn lists() {
+    infer_ok(stringify!{
+        let xs = [1, 2, 3];
+        xs[0] + xs:len();
+        xs:push(4);
+        xs:fold(0, fn(acc, x) { acc + x }) * 2;
+    });
+
+    // Each call to map can produce a different type of list
+    infer_ok(stringify!{
+        let xs: List[Int] = [1, 2];
+        xs:map(fn(x) { x:to_str() })[0] + "!";
+        xs:map(fn(x) { x * 2 })[0] + 1;
+    });
+
+    infer_err(stringify!{
+        [1, 2][0] + "a";
+    });
+
+    infer_err(stringify!{
+        let xs: List[Int] = [1];
+        xs:push("a");
+    });
+
+    infer_err(stringify!{
+        [1]["a"];
+    });
+
+    // Elements can be pushed onto a list, so its element type isn't generalized
+    infer_err(stringify!{
+        let xs = [];
+        xs:push(1);
+        xs:push("s");
+        xs:get(0) + 1;
+    });
+}
+
 #[test]
 fn mul_random_records() {
     infer_err(stringify!{
@@ -259,3 +818,17 @@ fn union_as_fn_arg() {
         };
     });
 }
+
+#[test]
+fn toplevel_bindings_persist() {
+    use infer::env::Scope;
+
+    let mut sscope = scope::Scope::new();
+    let mut iscope = Scope::new();
+    for code in ["let id = fn(x) { x }", "id(5) + 1", "id({ a: 1 }).a"].iter() {
+        let tokens = lexer::lex(code).unwrap();
+        let ast = parser::parse_program(&mut parser::State::new(tokens.as_slice())).unwrap();
+        let scoped = scope::scoped_toplevel(&mut sscope, ast.as_slice()).unwrap();
+        infer::infer_toplevel(&mut iscope, scoped.as_slice()).unwrap();
+    }
+}
@@ -1,9 +1,11 @@
 use std::vec::Vec;
 use std::collections::{HashMap, HashSet};
-use infer::util::{free_vars, toplevel_vars};
+use infer::util::{free_vars, expand_alias, instantiate_alias};
 use infer::env::Env;
 use infer::InferValue;
+use simplify;
 use il::*;
+use diagnostic::{Diagnostic, Kind};
 
 /// A stage is an extension of a parsing environment. It wraps around
 /// the internal environment, and acts as a staging ground for substitutions
@@ -32,11 +34,8 @@ impl <'a> Stage<'a> {
 
 impl <'a> Env for Stage<'a> {
     fn substitute(&mut self, a: Ident, b: Ty) {
-        // Verify that the substitutions are non-recursive
-        if toplevel_vars(self, &b).contains(&a) {
-            panic!("Cannot perform a toplevel-recursive substitution\n    ({:?} => {:?})", a, b);
-        }
-
+        // Substitutions which `_unify` makes go through `bind`, which checks
+        // that they don't create an infinite type
         self.subs.insert(a, b);
     }
 
@@ -48,6 +47,10 @@ impl <'a> Env for Stage<'a> {
         self.subs.get(id).or_else(|| self.env.lookup_type_var(id))
     }
 
+    fn lookup_alias<'b>(&self, id: &'b Ident) -> Option<&(Vec<Ident>, Ty)> {
+        self.env.lookup_alias(id)
+    }
+
     fn introduce_type_var(&mut self) -> Ty {
         self.env.introduce_type_var()
     }
@@ -59,14 +62,17 @@ impl <'a> Env for Stage<'a> {
     }
 }
 
-fn unify_props<'a>(stage: &mut Stage<'a>, a: &TyProp, b: &TyProp) -> Result<(), String> {
+fn unify_props<'a>(stage: &mut Stage<'a>, a: &TyProp, b: &TyProp) -> Result<(), Diagnostic> {
     match (a, b) {
-        (&TyProp::Val(_, ref aty), &TyProp::Val(_, ref bty)) => {
+        (&TyProp::Val(_, ref aty, _), &TyProp::Val(_, ref bty, _)) => {
             _unify(stage, aty.clone(), bty.clone())
         }
         (&TyProp::Method(_, ref aargs, ref ares), &TyProp::Method(_, ref bargs, ref bres)) => {
             if aargs.len() != bargs.len() {
-                return Err(format!("Cannot unify {:?} and {:?}", a, b));
+                return Err(Diagnostic::error(
+                    Kind::ArityMismatch,
+                    format!("Method `{:?}` is used with both {} and {} arguments",
+                            a.symbol(), aargs.len(), bargs.len())));
             }
 
             // Unify each of the arguments
@@ -77,14 +83,172 @@ fn unify_props<'a>(stage: &mut Stage<'a>, a: &TyProp, b: &TyProp) -> Result<(),
             _unify(stage, ares.clone(), bres.clone())
         }
         _ => {
-            Err(format!("Cannot unify properties: {:?} and {:?}", a, b))
+            Err(Diagnostic::error(
+                Kind::PropertyKindMismatch,
+                format!("`{:?}` is used as both a method and a value", a.symbol())))
+        }
+    }
+}
+
+/// The diagnostic produced when the closed record `lacks` is used where a record
+/// with the given brand is required. Brands can't be forged, so it can't have it.
+fn missing_brand<'a>(stage: &Stage<'a>, has: &Ty, lacks: &Ty, brand: &Ident) -> Diagnostic {
+    let type_vars = stage.as_infervalue().type_vars;
+    Diagnostic::error(
+        Kind::BrandMismatch,
+        format!("Expected a record named `{}`", brand.0))
+        .with_note(format!("required by type `{}`", simplify::simplify_ty(has, &type_vars)))
+        .with_note(format!("but type `{}` wasn't created with `#{}#{{...}}`",
+                           simplify::simplify_ty(lacks, &type_vars), brand.0))
+}
+
+/// The diagnostic produced when a field is assigned to in `has`, but the closed
+/// record `lacks` doesn't declare it as mutable
+fn immutable_field<'a>(stage: &Stage<'a>, has: &Ty, lacks: &Ty, symb: &Symbol) -> Diagnostic {
+    let type_vars = stage.as_infervalue().type_vars;
+    Diagnostic::error(
+        Kind::ImmutableProperty,
+        format!("Field `{:?}` isn't mutable", symb))
+        .with_note(format!("required to be mutable by type `{}`",
+                           simplify::simplify_ty(has, &type_vars)))
+        .with_note(format!("but type `{}` doesn't declare it with `mut`",
+                           simplify::simplify_ty(lacks, &type_vars)))
+}
+
+/// Where a type variable occurs within the type which it would be bound to
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Occurrence {
+    /// Outside of any property, such as in the record's extension
+    Toplevel,
+    /// Within the result of a function
+    Result,
+    /// Within a parameter of a function
+    Param,
+}
+
+/// Finds where the type variable id occurs within ty. Records can refer to
+/// themselves through their fields and methods (equi-recursive types), which
+/// lets lists, trees and binary methods like `a:eq(b)` be typed. Functions are
+/// only their `call` method, which doesn't count, as the type would have to
+/// be infinite, like `x` in `x(x)`. Aliases are named, so they may be recursive.
+fn occurrence<'a>(stage: &Stage<'a>, id: &Ident, ty: &Ty, pos: Occurrence,
+                  checked: &mut HashSet<(Ident, Occurrence)>) -> Option<Occurrence> {
+    match *ty {
+        Ty::Ident(ref var) => {
+            if var == id { return Some(pos) }
+            if ! checked.insert((var.clone(), pos)) { return None }
+
+            stage.lookup_type_var(var).cloned().and_then(|nty| {
+                occurrence(stage, id, &nty, pos, checked)
+            })
+        }
+        Ty::Rec(ref extends, _, ref props) => {
+            if let Some(box ref extends) = *extends {
+                if let Some(occ) = occurrence(stage, id, extends, pos, checked) {
+                    return Some(occ)
+                }
+            }
+
+            for prop in props.iter() {
+                if let TyProp::Method(ref symb, ref args, ref res) = *prop {
+                    if symb.0 != "call" { continue }
+
+                    for arg in args.iter() {
+                        if let Some(occ) = occurrence(stage, id, arg, Occurrence::Param, checked) {
+                            return Some(occ)
+                        }
+                    }
+
+                    let res_pos = if pos == Occurrence::Param { pos } else { Occurrence::Result };
+                    if let Some(occ) = occurrence(stage, id, res, res_pos, checked) {
+                        return Some(occ)
+                    }
+                }
+            }
+            None
+        }
+        Ty::Union(ref opts) => {
+            opts.iter().filter_map(|opt| occurrence(stage, id, opt, pos, checked)).next()
+        }
+        Ty::Alias(..) => None,
+    }
+}
+
+/// The diagnostic produced when binding id to ty would create an infinite type
+fn infinite_type<'a>(stage: &Stage<'a>, id: &Ident, ty: &Ty, occ: Occurrence) -> Diagnostic {
+    let message = match occ {
+        Occurrence::Toplevel => "This type would have to contain itself",
+        Occurrence::Result => "This function would have to return itself",
+        Occurrence::Param => "This function would have to take itself as an argument",
+    };
+
+    // Show the type with the variable itself called `Self`
+    let ty = simplify::simplify_ty(ty, &stage.as_infervalue().type_vars);
+    let mut subs = HashMap::new();
+    subs.insert(id.clone(), Ty::Ident(Ident::from_slice("Self")));
+
+    Diagnostic::error(Kind::InfiniteType, message.to_string())
+        .with_note(format!("its type would be `Self = {}`, which is infinite", ty.subst(&subs)))
+        .with_note("types can only refer to themselves through the properties of a record, \
+                    other than `call`".to_string())
+}
+
+/// Substitutes ty for the type variable id, unless that would create an infinite type
+fn bind<'a>(stage: &mut Stage<'a>, id: &Ident, ty: Ty) -> Result<(), Diagnostic> {
+    // A union with id as one of its options only requires id to include the
+    // other options, which leaves room for more with a fresh option instead
+    let own = Ty::Ident(id.clone());
+    let ty = match ty {
+        Ty::Union(ref opts) if opts.contains(&own) => {
+            let mut opts: Vec<Ty> = opts.iter().filter(|opt| **opt != own).cloned().collect();
+            opts.push(stage.introduce_type_var());
+            Ty::Union(opts)
+        }
+        ty => ty,
+    };
+
+    if let Some(occ) = occurrence(stage, id, &ty, Occurrence::Toplevel, &mut HashSet::new()) {
+        return Err(infinite_type(stage, id, &ty, occ));
+    }
+
+    stage.substitute(id.clone(), ty);
+    Ok(())
+}
+
+/// The properties of a record type, with each symbol once. A record which is read
+/// from before it is assigned to has an immutable and a mutable entry for the
+/// field (see `_unify`), in which case the mutable one is the one which counts.
+fn distinct_props(props: &[TyProp]) -> Vec<&TyProp> {
+    let mut distinct: Vec<&TyProp> = vec![];
+    for prop in props.iter() {
+        match distinct.iter().position(|seen| seen.symbol() == prop.symbol()) {
+            Some(i) => if prop.is_mutable() { distinct[i] = prop },
+            None => distinct.push(prop),
         }
     }
+    distinct
+}
+
+/// The diagnostic produced when `has` has properties which the closed record `lacks` doesn't
+fn missing_props<'a>(stage: &Stage<'a>, has: &Ty, lacks: &Ty,
+                     props: &HashMap<Symbol, &TyProp>) -> Diagnostic {
+    let mut names: Vec<_> = props.keys().map(|symb| format!("`{:?}`", symb)).collect();
+    names.sort();
+
+    let type_vars = stage.as_infervalue().type_vars;
+    Diagnostic::error(
+        Kind::MissingProperty,
+        format!("Missing {} {}", if names.len() == 1 { "property" } else { "properties" },
+                names.connect(", ")))
+        .with_note(format!("required by type `{}`", simplify::simplify_ty(has, &type_vars)))
+        .with_note(format!("but not present in type `{}`",
+                           simplify::simplify_ty(lacks, &type_vars)))
 }
 
 /// Reduces the type to it's "standard form". In this form, all
 /// "root" Ty::Idents are not bound in the stage, and there are no
-/// nested unions.
+/// nested unions. Root aliases are left alone, so that a variable
+/// unified with one is bound to the alias's name.
 fn std_form<'a, 'b>(stage: &'b Stage<'a>, ty: Ty) -> Ty {
     match ty {
         Ty::Ident(ref id) => {
@@ -92,17 +256,19 @@ fn std_form<'a, 'b>(stage: &'b Stage<'a>, ty: Ty) -> Ty {
                 std_form(stage, nty.clone())
             } else { ty.clone() }
         }
-        Ty::Rec(ref extends, ref props) => {
+        Ty::Rec(ref extends, ref brand, ref props) => {
             if let Some(box ref extends) = *extends {
                 let nextends = std_form(stage, extends.clone());
 
                 match nextends {
                     Ty::Ident(_) => {
-                        Ty::Rec(Some(box nextends), props.clone())
+                        Ty::Rec(Some(box nextends), brand.clone(), props.clone())
                     }
-                    Ty::Rec(ref extends, ref nprops) => {
+                    Ty::Rec(ref extends, ref nbrand, ref nprops) => {
                         // XXX: Invariant. props & nprops must not contain common symbols!
+                        // Similarly, at most one of brand & nbrand may be set.
                         Ty::Rec(extends.clone(),
+                                brand.clone().or(nbrand.clone()),
                                 props.iter().chain(
                                     nprops.iter()).map(|x| x.clone()).collect())
                     }
@@ -110,7 +276,7 @@ fn std_form<'a, 'b>(stage: &'b Stage<'a>, ty: Ty) -> Ty {
                         // Add props to every option!
                         let opts: Vec<Ty> = opts.iter().map(|opt| {
                             std_form(stage,
-                                     Ty::Rec(Some(box opt.clone()), props.clone()))
+                                     Ty::Rec(Some(box opt.clone()), brand.clone(), props.clone()))
                         }).collect();
 
                         // None of these should be unions, so we don't have to
@@ -119,6 +285,11 @@ fn std_form<'a, 'b>(stage: &'b Stage<'a>, ty: Ty) -> Ty {
 
                         Ty::Union(opts)
                     }
+                    Ty::Alias(ref id, ref args) => {
+                        // The base's properties are needed, so expand it
+                        let base = expand_alias(stage, id, args.as_slice());
+                        std_form(stage, Ty::Rec(Some(box base), brand.clone(), props.clone()))
+                    }
                 }
             } else { ty.clone() }
         }
@@ -142,7 +313,7 @@ fn std_form<'a, 'b>(stage: &'b Stage<'a>, ty: Ty) -> Ty {
     }
 }
 
-fn _unify<'a, 'b>(stage: &'a mut Stage<'b>, a: Ty, b: Ty) -> Result<(), String> {
+fn _unify<'a, 'b>(stage: &'a mut Stage<'b>, a: Ty, b: Ty) -> Result<(), Diagnostic> {
     let ty_pairs = (a.clone(), b.clone());
     if stage.unified.contains(&(a.clone(), b.clone())) {
         return Ok(());
@@ -163,27 +334,51 @@ fn _unify<'a, 'b>(stage: &'a mut Stage<'b>, a: Ty, b: Ty) -> Result<(), String>
     match pair {
         (&Ty::Ident(ref id), _) => {
             if a != b {
-                stage.substitute(id.clone(), b.clone());
+                try!(bind(stage, id, b.clone()));
             }
 
             Ok(())
         }
         (_, &Ty::Ident(ref id)) => {
             if a != b {
-                stage.substitute(id.clone(), a.clone());
+                try!(bind(stage, id, a.clone()));
             }
 
             Ok(())
         }
-        (&Ty::Rec(ref aextends, ref aprops), &Ty::Rec(ref bextends, ref bprops)) => {
+        // Aliases are expanded lazily, when their structure is needed. Recursive
+        // aliases terminate, as each pair of types is only unified once.
+        (&Ty::Alias(ref id, ref args), _) => {
+            let expanded = instantiate_alias(stage, id, args.as_slice());
+            _unify(stage, expanded, b.clone())
+        }
+        (_, &Ty::Alias(ref id, ref args)) => {
+            let expanded = instantiate_alias(stage, id, args.as_slice());
+            _unify(stage, a.clone(), expanded)
+        }
+        (&Ty::Rec(ref aextends, ref abrand, ref aprops), &Ty::Rec(ref bextends, ref bbrand, ref bprops)) => {
+            // A brand is like a property which can't be forged: a record can
+            // only gain the other record's brand through its extension variable.
+            let (only_a_brand, only_b_brand) = match (abrand, bbrand) {
+                (&Some(ref abrand), &Some(ref bbrand)) if abrand != bbrand => {
+                    return Err(Diagnostic::error(
+                        Kind::BrandMismatch,
+                        format!("Records named `{}` and `{}` have different types",
+                                abrand.0, bbrand.0)));
+                }
+                (&Some(_), &None) => (abrand.clone(), None),
+                (&None, &Some(_)) => (None, bbrand.clone()),
+                _ => (None, None),
+            };
+
             let mut only_a = HashMap::new();
             let mut only_b = HashMap::new();
             let mut joint = HashMap::new();
 
-            for aprop in aprops.iter() {
+            for aprop in distinct_props(aprops.as_slice()).into_iter() {
                 only_a.insert(aprop.symbol().clone(), aprop);
             }
-            for bprop in bprops.iter() {
+            for bprop in distinct_props(bprops.as_slice()).into_iter() {
                 if let Some(aprop) = only_a.remove(bprop.symbol()) {
                     joint.insert(bprop.symbol().clone(), (aprop, bprop));
                 } else {
@@ -191,9 +386,21 @@ fn _unify<'a, 'b>(stage: &'a mut Stage<'b>, a: Ty, b: Ty) -> Result<(), String>
                 }
             }
 
-            for &(aprop, bprop) in joint.values() {
+            for (symb, &(aprop, bprop)) in joint.iter() {
                 // TODO: (possibly unnecessary clones)
                 try!(unify_props(stage, &aprop.clone(), &bprop.clone()));
+
+                // A field which one record only reads is assigned to in the other.
+                // If the reading record is open, the field may still turn out to be
+                // mutable, so its extension also gets the mutable entry. This breaks
+                // the rule that a record type has each property once.
+                match (aprop.is_mutable(), bprop.is_mutable()) {
+                    (false, true) if aextends.is_some() => { only_b.insert(symb.clone(), bprop); }
+                    (false, true) => return Err(immutable_field(stage, &b, &a, symb)),
+                    (true, false) if bextends.is_some() => { only_a.insert(symb.clone(), aprop); }
+                    (true, false) => return Err(immutable_field(stage, &a, &b, symb)),
+                    _ => {}
+                }
             }
 
             let common_free = if aextends.is_none() || bextends.is_none() {
@@ -204,26 +411,32 @@ fn _unify<'a, 'b>(stage: &'a mut Stage<'b>, a: Ty, b: Ty) -> Result<(), String>
 
             if let Some(box Ty::Ident(ref ident)) = *bextends {
                 // We need to unify bextends with something
-                stage.substitute(ident.clone(),
-                                 Ty::Rec(common_free.clone(),
-                                         only_a.values().map(|x| (**x).clone()).collect()));
+                try!(bind(stage, ident,
+                          Ty::Rec(common_free.clone(),
+                                  only_a_brand,
+                                  only_a.values().map(|x| (**x).clone()).collect())));
             } else if ! only_a.is_empty() {
-                return Err(format!("Cannot unify {:?} and {:?}", a, b));
+                return Err(missing_props(stage, &a, &b, &only_a));
+            } else if let Some(ref brand) = only_a_brand {
+                return Err(missing_brand(stage, &a, &b, brand));
             }
 
             // Merge the remaining values into the other maps
             if let Some(box Ty::Ident(ref ident)) = *aextends {
                 // We need to unify bextends with something
-                stage.substitute(ident.clone(),
-                                 Ty::Rec(common_free.clone(),
-                                         only_b.values().map(|x| (**x).clone()).collect()));
+                try!(bind(stage, ident,
+                          Ty::Rec(common_free.clone(),
+                                  only_b_brand,
+                                  only_b.values().map(|x| (**x).clone()).collect())));
             } else if ! only_b.is_empty() {
-                return Err(format!("Cannot unify {:?} and {:?}", a, b));
+                return Err(missing_props(stage, &b, &a, &only_b));
+            } else if let Some(ref brand) = only_b_brand {
+                return Err(missing_brand(stage, &b, &a, brand));
             }
 
             Ok(())
         }
-        (&Ty::Rec(_, _), &Ty::Union(ref opts)) => {
+        (&Ty::Rec(..), &Ty::Union(ref opts)) => {
             // We can't unify a concrete record with a union, so let's not even try!
             let mut subs: HashMap<Ident, Vec<Ty>> = HashMap::new();
 
@@ -268,7 +481,7 @@ fn _unify<'a, 'b>(stage: &'a mut Stage<'b>, a: Ty, b: Ty) -> Result<(), String>
 
             Ok(())
         }
-        (&Ty::Union(_), &Ty::Rec(_, _)) => {
+        (&Ty::Union(_), &Ty::Rec(..)) => {
             // This simply delegates to the above branch.
             // It doesn't do it in the most efficient way, but that is OK
             _unify(stage, b.clone(), a.clone())
@@ -323,7 +536,7 @@ fn _unify<'a, 'b>(stage: &'a mut Stage<'b>, a: Ty, b: Ty) -> Result<(), String>
                 }
             }
 
-            fn something<'a>(stage: &mut Stage<'a>, aopt: &Ty, uniopts: &Vec<UniOpt>) -> Result<(), String> {
+            fn something<'a>(stage: &mut Stage<'a>, aopt: &Ty, uniopts: &Vec<UniOpt>) -> Result<(), Diagnostic> {
                 let filtered = uniopts.iter().filter(|x| x.aopt == *aopt);
 
                 // unify the objects together, woo!
@@ -350,10 +563,10 @@ fn _unify<'a, 'b>(stage: &'a mut Stage<'b>, a: Ty, b: Ty) -> Result<(), String>
                                 // Unify the uniopt's tyvar with the opt's extension,
                                 // This ensures that the two sides will use the same tyvar
                                 match *opt {
-                                    Ty::Rec(Some(box ref extends), _) => {
+                                    Ty::Rec(Some(box ref extends), _, _) => {
                                         try!(_unify(stage, extends.clone(), uniopt.tyvar.clone()));
                                     }
-                                    Ty::Rec(None, _) => {/* Can this happen? */}
+                                    Ty::Rec(None, _, _) => {/* Can this happen? */}
                                     Ty::Ident(_) => {
                                         try!(_unify(stage, aopt.clone(), opt.clone()));
                                     }
@@ -366,7 +579,9 @@ fn _unify<'a, 'b>(stage: &'a mut Stage<'b>, a: Ty, b: Ty) -> Result<(), String>
                             Ok(())
                         }
                     } else {
-                        Err("Can't unify, because we're missing stuff! woop! I'm not sure what went wrong, lets find out later".to_string())
+                        Err(Diagnostic::error(
+                            Kind::TypeMismatch,
+                            format!("Cannot unify {:?} with any option of the union", aopt)))
                     }
                 } else {
                     // It looks like every element in the other side unified just fine?
@@ -388,7 +603,7 @@ fn _unify<'a, 'b>(stage: &'a mut Stage<'b>, a: Ty, b: Ty) -> Result<(), String>
     }
 }
 
-pub fn unify<'a>(env: &mut (Env + 'a), a: &Ty, b: &Ty) -> Result<(), String> {
+pub fn unify<'a>(env: &mut (Env + 'a), a: &Ty, b: &Ty) -> Result<(), Diagnostic> {
     let mut stage = Stage::new(env);
 
     try!(_unify(&mut stage, a.clone(), b.clone()));
@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use il::*;
 use infer::env::Env;
 
@@ -18,14 +18,14 @@ fn _free_vars<'a>(stage: &mut (Env + 'a), ty: &Ty, checked: &mut HashSet<Ty>) ->
                 idents.insert(id.clone());
             }
         }
-        Ty::Rec(ref extends, ref props) => {
+        Ty::Rec(ref extends, _, ref props) => {
             if let Some(box ref extends) = *extends {
                 idents.extend(_free_vars(stage, extends, checked).iter().cloned());
             }
 
             for prop in props.iter() {
                 match *prop {
-                    TyProp::Val(_, ref ty) => {
+                    TyProp::Val(_, ref ty, _) => {
                         idents.extend(_free_vars(stage, ty, checked).iter().cloned());
                     }
                     TyProp::Method(_, ref args, ref res) => {
@@ -42,37 +42,121 @@ fn _free_vars<'a>(stage: &mut (Env + 'a), ty: &Ty, checked: &mut HashSet<Ty>) ->
                 idents.extend(_free_vars(stage, opt, checked).iter().cloned());
             }
         }
+        Ty::Alias(_, ref args) => {
+            // The body of an alias only refers to its parameters
+            for arg in args.iter() {
+                idents.extend(_free_vars(stage, arg, checked).iter().cloned());
+            }
+        }
     }
 
     idents
 }
 
-pub fn toplevel_vars<'a>(stage: &mut (Env + 'a), ty: &Ty) -> HashSet<Ident> {
+/// The free type variables within the mutable state of ty: its mutable
+/// fields, and the elements of builtin containers like lists
+pub fn mutable_vars<'a>(stage: &mut (Env + 'a), ty: &Ty) -> HashSet<Ident> {
+    _mutable_vars(stage, ty, &mut HashSet::new())
+}
+
+fn _mutable_vars<'a>(stage: &mut (Env + 'a), ty: &Ty, checked: &mut HashSet<Ty>) -> HashSet<Ident> {
     let mut idents = HashSet::new();
+    if checked.contains(ty) { return idents } else { checked.insert(ty.clone()); }
 
     match *ty {
         Ty::Ident(ref id) => {
             if let Some(ty) = stage.lookup_type_var(id).cloned() {
-                return toplevel_vars(stage, &ty.clone())
-            } else {
-                idents.insert(id.clone());
+                return _mutable_vars(stage, &ty, checked)
             }
         }
-        Ty::Rec(ref extends, _) => {
+        Ty::Rec(ref extends, _, ref props) => {
             if let Some(box ref extends) = *extends {
-                idents.extend(toplevel_vars(stage, extends).iter().cloned());
+                idents.extend(_mutable_vars(stage, extends, checked).into_iter());
+            }
+
+            for prop in props.iter() {
+                match *prop {
+                    TyProp::Val(_, ref ty, Mutability::Mutable) => {
+                        idents.extend(free_vars(stage, ty).into_iter());
+                    }
+                    TyProp::Val(_, ref ty, Mutability::Immutable) => {
+                        idents.extend(_mutable_vars(stage, ty, checked).into_iter());
+                    }
+                    // Each call makes its own state, so only the state which is
+                    // reachable without calling a method counts
+                    TyProp::Method(..) => {}
+                }
             }
         }
         Ty::Union(ref opts) => {
             for opt in opts.iter() {
-                idents.extend(toplevel_vars(stage, opt).iter().cloned());
+                idents.extend(_mutable_vars(stage, opt, checked).into_iter());
+            }
+        }
+        Ty::Alias(Ident(_, BuiltIn), ref args) => {
+            // The builtin aliases are containers, such as `List[A]`, which can
+            // be added to with methods like `push`, so their elements are mutable
+            for arg in args.iter() {
+                idents.extend(free_vars(stage, arg).into_iter());
             }
         }
+        Ty::Alias(ref id, ref args) => {
+            // The alias's parameters may be used within mutable fields
+            let expanded = expand_alias(stage, id, args.as_slice());
+            return _mutable_vars(stage, &expanded, checked)
+        }
     }
 
     idents
 }
 
+/// Expands one level of the type alias id, substituting args for its parameters
+pub fn expand_alias<'a>(env: &(Env + 'a), id: &Ident, args: &[Ty]) -> Ty {
+    let &(ref params, ref body) = env.lookup_alias(id).expect("ICE: Unknown type alias");
+    let subs = params.iter().cloned().zip(args.iter().cloned()).collect();
+    body.subst(&subs)
+}
+
+/// Expands one level of the type alias id, like `expand_alias`. The methods of
+/// the builtin aliases can be polymorphic (such as `List[A]`'s `map`), so the
+/// builtin type variables in the body, other than its parameters, are fresh.
+pub fn instantiate_alias<'a>(env: &mut (Env + 'a), id: &Ident, args: &[Ty]) -> Ty {
+    let (params, body) = env.lookup_alias(id).cloned().expect("ICE: Unknown type alias");
+    let mut subs: HashMap<Ident, Ty> = params.into_iter().zip(args.iter().cloned()).collect();
+    for var in free_vars(env, &body).into_iter() {
+        if let Ident(_, BuiltIn) = var {
+            if ! subs.contains_key(&var) {
+                let fresh = env.introduce_type_var();
+                subs.insert(var, fresh);
+            }
+        }
+    }
+    body.subst(&subs)
+}
+
 pub fn val_ty(scope: &mut Env, ty: Ty) -> Ty {
     Ty::Union(vec![ty, scope.introduce_type_var()])
 }
+
+/// The type of a value without the type variables which `val_ty` adds. Those
+/// let the value be used where a union is expected, but mutable fields are
+/// invariant, so they keep exactly the type of the value which they start with.
+pub fn exact_ty<'a>(env: &(Env + 'a), ty: &Ty) -> Ty {
+    if let Ty::Union(ref opts) = *ty {
+        let mut known = vec![];
+        for opt in opts.iter() {
+            match exact_ty(env, opt) {
+                Ty::Ident(ref id) if env.lookup_type_var(id).is_none() => {}
+                Ty::Union(inner) => known.extend(inner.into_iter()),
+                opt => known.push(opt),
+            }
+        }
+
+        return match known.len() {
+            0 => ty.clone(),
+            1 => known.pop().unwrap(),
+            _ => Ty::Union(known),
+        };
+    }
+    ty.clone()
+}
@@ -0,0 +1,799 @@
+use std::fmt;
+use std::i64;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use il::*;
+use span::Span;
+use diagnostic::{Diagnostic, Kind};
+
+#[cfg(test)]
+mod test;
+
+// The interpreter evaluates scoped IL directly. It is much slower than
+// the code which we generate, but it doesn't need llvm, and it acts as
+// the reference semantics for the language.
+
+#[derive(Clone)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(Rc<String>),
+    /// Lists are shared, so pushing onto one is visible through every copy
+    List(Rc<RefCell<Vec<Value>>>),
+    Rec(Rc<Record>),
+}
+
+impl Value {
+    /// The name of the kind of value, for use in error messages
+    pub fn type_name(&self) -> &'static str {
+        match *self {
+            Value::Null => "Null",
+            Value::Bool(_) => "Bool",
+            Value::Int(_) => "Int",
+            Value::Float(_) => "Float",
+            Value::Str(_) => "Str",
+            Value::List(_) => "List",
+            Value::Rec(_) => "record",
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Int(i) => write!(f, "{}", i),
+            Value::Float(n) => write!(f, "{}", n),
+            Value::Str(ref s) => write!(f, "{}", s),
+            Value::List(ref items) => {
+                try!(write!(f, "["));
+                for (i, item) in items.borrow().iter().enumerate() {
+                    try!(write!(f, "{}{:?}", if i == 0 { "" } else { ", " }, item));
+                }
+                write!(f, "]")
+            }
+            Value::Rec(ref rec) => write!(f, "{}", rec),
+        }
+    }
+}
+
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Str(ref s) => write!(f, "{:?}", s),
+            _ => write!(f, "{}", self),
+        }
+    }
+}
+
+/// A method, along with the environment which it closes over
+pub struct Method {
+    params: Vec<Ident>,
+    body: Expr,
+    env: Env,
+}
+
+pub struct Record {
+    pub brand: Brand,
+    /// Records are shared, so the fields in `mutable` can be assigned through any copy
+    pub props: RefCell<HashMap<Symbol, Value>>,
+    pub mutable: HashSet<Symbol>,
+    pub methods: HashMap<Symbol, Rc<Method>>,
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let props = self.props.borrow();
+        let mut props: Vec<_> = props.iter().collect();
+        props.sort_by(|&(a, _), &(b, _)| a.cmp(b));
+        let mut methods: Vec<_> = self.methods.keys().collect();
+        methods.sort();
+
+        if let Some(ref brand) = self.brand {
+            try!(write!(f, "#{}#", brand.0));
+        }
+        try!(write!(f, "{{"));
+        let mut first = true;
+        for &(symb, value) in props.iter() {
+            try!(write!(f, "{} {}{:?}: {:?}", if first { "" } else { "," },
+                        if self.mutable.contains(symb) { "mut " } else { "" }, symb, value));
+            first = false;
+        }
+        for symb in methods.iter() {
+            try!(write!(f, "{} fn {:?}", if first { "" } else { "," }, symb));
+            first = false;
+        }
+        write!(f, " }}")
+    }
+}
+
+struct Frame {
+    /// Variables which have been declared, but not yet bound, map to None
+    vars: RefCell<HashMap<Ident, Option<Value>>>,
+    /// The type aliases declared in this frame, which patterns can refer to
+    aliases: RefCell<HashMap<Ident, (Vec<Ident>, Ty)>>,
+    parent: Option<Env>,
+}
+
+/// An Env is a chain of frames. Identifiers are unique after scoping, but
+/// the same identifier can be bound in many frames at once (e.g. when a
+/// function recurs), so we always look for the innermost binding.
+#[derive(Clone)]
+pub struct Env(Rc<Frame>);
+
+impl Env {
+    pub fn new() -> Env {
+        Env(Rc::new(Frame{
+            vars: RefCell::new(HashMap::new()),
+            aliases: RefCell::new(HashMap::new()),
+            parent: None,
+        }))
+    }
+
+    fn child(&self) -> Env {
+        Env(Rc::new(Frame{
+            vars: RefCell::new(HashMap::new()),
+            aliases: RefCell::new(HashMap::new()),
+            parent: Some(self.clone()),
+        }))
+    }
+
+    /// A new frame with the same bindings as this one. Bindings made in the
+    /// copy aren't visible through this frame, though the values are shared.
+    fn copy_frame(&self) -> Env {
+        Env(Rc::new(Frame{
+            vars: RefCell::new(self.0.vars.borrow().clone()),
+            aliases: RefCell::new(self.0.aliases.borrow().clone()),
+            parent: self.0.parent.clone(),
+        }))
+    }
+
+    fn declare(&self, id: &Ident) {
+        self.0.vars.borrow_mut().insert(id.clone(), None);
+    }
+
+    fn define(&self, id: Ident, value: Value) {
+        self.0.vars.borrow_mut().insert(id, Some(value));
+    }
+
+    fn lookup(&self, id: &Ident) -> Option<Option<Value>> {
+        if let Some(value) = self.0.vars.borrow().get(id) {
+            return Some(value.clone());
+        }
+        match self.0.parent {
+            Some(ref parent) => parent.lookup(id),
+            None => None,
+        }
+    }
+
+    /// Expands one level of the type alias id
+    fn expand_alias(&self, id: &Ident, args: &[Ty]) -> Ty {
+        if let Some(&(ref params, ref body)) = self.0.aliases.borrow().get(id) {
+            let subs = params.iter().cloned().zip(args.iter().cloned()).collect();
+            return body.subst(&subs);
+        }
+        match self.0.parent {
+            Some(ref parent) => parent.expand_alias(id, args),
+            None => panic!("ICE: Unknown type alias"),
+        }
+    }
+}
+
+/// Why evaluation stopped before producing a value. Scoping ensures that
+/// `break`, `continue` and `return` never leave the function which they are in.
+pub enum Unwind {
+    Error(Diagnostic),
+    /// `break`, which is caught by the innermost loop
+    Break,
+    /// `continue`, which is caught by the innermost loop
+    Continue,
+    /// `return`, which is caught by the innermost method call
+    Return(Value),
+}
+
+pub type Eval = Result<Value, Unwind>;
+
+/// The result of evaluating a function body or program, which only stops
+/// early because of errors
+fn finish(res: Eval) -> Result<Value, Diagnostic> {
+    match res {
+        Ok(value) => Ok(value),
+        Err(Unwind::Error(err)) => Err(err),
+        Err(Unwind::Break) | Err(Unwind::Continue) => panic!("ICE: Loop control outside of a loop"),
+        Err(Unwind::Return(_)) => panic!("ICE: `return` outside of a method"),
+    }
+}
+
+/// The Interpreter holds onto the toplevel environment, such that the
+/// bindings made by one call to `eval_toplevel` are visible to later calls.
+pub struct Interpreter {
+    env: Env,
+}
+
+impl Clone for Interpreter {
+    /// Copies the toplevel environment, so that the clone's bindings can be
+    /// thrown away without affecting the original. The values themselves are
+    /// shared, so changes to records and lists are seen by both.
+    fn clone(&self) -> Interpreter {
+        Interpreter{ env: self.env.copy_frame() }
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        Interpreter{ env: Env::new() }
+    }
+
+    /// Evaluates toplevel statements, returning the value of each binding
+    /// and expression statement, in order.
+    pub fn eval_toplevel(&mut self, stmts: &[Stmt]) -> Result<Vec<Value>, Diagnostic> {
+        declare_lets(&self.env, stmts);
+
+        let mut values = vec![];
+        for stmt in stmts.iter() {
+            match stmt.kind {
+                StmtKind::Let(..) => {
+                    values.push(try!(finish(eval_stmt(&self.env, stmt))));
+                }
+                StmtKind::Expr(ref expr) => {
+                    values.push(try!(finish(eval_expr(&self.env, expr))));
+                }
+                StmtKind::Type(..) | StmtKind::Name(_) | StmtKind::Empty => {}
+            }
+        }
+        Ok(values)
+    }
+}
+
+/// Evaluates a program, producing the value of its final expression
+pub fn eval_program(stmts: &[Stmt]) -> Result<Value, Diagnostic> {
+    finish(eval_block(&Env::new(), stmts))
+}
+
+fn runtime_type_error(message: String, span: Span, label: &str) -> Diagnostic {
+    Diagnostic::error(Kind::RuntimeTypeError, message).with_primary(span, label)
+}
+
+fn declare_lets(env: &Env, stmts: &[Stmt]) {
+    // Every binding in a block is visible throughout the block, which is
+    // what lets functions refer to themselves and each other.
+    for stmt in stmts.iter() {
+        match stmt.kind {
+            StmtKind::Let(ref id, _, _) => env.declare(id),
+            StmtKind::Type(ref id, ref params, ref body) => {
+                env.0.aliases.borrow_mut().insert(id.clone(), (params.clone(), body.node.clone()));
+            }
+            _ => {}
+        }
+    }
+}
+
+fn eval_block(env: &Env, stmts: &[Stmt]) -> Eval {
+    declare_lets(env, stmts);
+
+    // The value of a block is its last statement, if it is an expression
+    let mut value = Value::Null;
+    for stmt in stmts.iter() {
+        value = match stmt.kind {
+            StmtKind::Expr(ref expr) => try!(eval_expr(env, expr)),
+            _ => {
+                try!(eval_stmt(env, stmt));
+                Value::Null
+            }
+        };
+    }
+    Ok(value)
+}
+
+/// Evaluates a statement, returning the value which it bound or produced
+fn eval_stmt(env: &Env, stmt: &Stmt) -> Eval {
+    match stmt.kind {
+        StmtKind::Let(ref id, _, ref expr) => {
+            let value = try!(eval_expr(env, expr));
+            env.define(id.clone(), value.clone());
+            Ok(value)
+        }
+        StmtKind::Expr(ref expr) => eval_expr(env, expr),
+        StmtKind::Type(..) | StmtKind::Name(_) | StmtKind::Empty => Ok(Value::Null),
+    }
+}
+
+pub fn eval_expr(env: &Env, e: &Expr) -> Eval {
+    match e.kind {
+        ExprKind::Literal(ref lit) => {
+            Ok(match *lit {
+                Literal::Str(ref s) => Value::Str(Rc::new(s.as_slice().to_string())),
+                Literal::Int(i) => Value::Int(i),
+                Literal::Float(f) => Value::Float(f),
+                Literal::Bool(b) => Value::Bool(b),
+            })
+        }
+        ExprKind::Ident(ref id) => {
+            if let Ident(ref atom, BuiltIn) = *id {
+                return match atom.as_slice() {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    "null" => Ok(Value::Null),
+                    _ => Err(Unwind::Error(runtime_type_error(format!("{} is not a value", atom),
+                                                              e.span, "used as a value here"))),
+                };
+            }
+
+            match env.lookup(id) {
+                Some(Some(value)) => Ok(value),
+                _ => {
+                    Err(Unwind::Error(Diagnostic::error(
+                        Kind::UninitializedVariable,
+                        format!("Variable {} was used before it was bound", id.0)
+                    ).with_primary(e.span, "used here before its `let` was evaluated")))
+                }
+            }
+        }
+        ExprKind::Rec(ref props) => eval_rec(env, props.as_slice(), None),
+        ExprKind::Brand(ref brand, box ref rec) => {
+            match rec.kind {
+                ExprKind::Rec(ref props) => eval_rec(env, props.as_slice(), Some(brand.clone())),
+                _ => panic!("ICE: Only record literals can be branded"),
+            }
+        }
+        ExprKind::List(ref elems) => {
+            let mut items = Vec::with_capacity(elems.len());
+            for elem in elems.iter() {
+                items.push(try!(eval_expr(env, elem)));
+            }
+            Ok(Value::List(Rc::new(RefCell::new(items))))
+        }
+        ExprKind::Update(box ref base, ref props) => {
+            match try!(eval_expr(env, base)) {
+                Value::Rec(ref base_rec) => {
+                    // The copy isn't named, like the type which infer gives it
+                    let mut rec = Record{
+                        brand: None,
+                        props: RefCell::new(base_rec.props.borrow().clone()),
+                        mutable: base_rec.mutable.clone(),
+                        methods: base_rec.methods.clone(),
+                    };
+                    try!(add_props(env, &mut rec, props.as_slice()));
+                    Ok(Value::Rec(Rc::new(rec)))
+                }
+                value => {
+                    Err(Unwind::Error(runtime_type_error(
+                        format!("Only records can be updated, not {}", value.type_name()),
+                        base.span, "expected a record")))
+                }
+            }
+        }
+        ExprKind::Member(box ref obj, ref symb) => {
+            match try!(eval_expr(env, obj)) {
+                Value::Rec(ref rec) => {
+                    match rec.props.borrow().get(symb) {
+                        Some(value) => Ok(value.clone()),
+                        None => {
+                            Err(Unwind::Error(Diagnostic::error(
+                                Kind::UndefinedMember,
+                                format!("Record has no property `{:?}`", symb)
+                            ).with_primary(e.span, "property not found")))
+                        }
+                    }
+                }
+                value => {
+                    Err(Unwind::Error(runtime_type_error(
+                        format!("{} has no property `{:?}`", value.type_name(), symb),
+                        e.span, "property not found")))
+                }
+            }
+        }
+        ExprKind::Assign(box ref obj, ref symb, box ref value) => {
+            match try!(eval_expr(env, obj)) {
+                Value::Rec(ref rec) => {
+                    if ! rec.mutable.contains(symb) {
+                        return Err(Unwind::Error(runtime_type_error(
+                            format!("Field `{:?}` isn't mutable", symb),
+                            e.span, "assigned here")));
+                    }
+                    let value = try!(eval_expr(env, value));
+                    rec.props.borrow_mut().insert(symb.clone(), value);
+                    Ok(Value::Null)
+                }
+                value => {
+                    Err(Unwind::Error(runtime_type_error(
+                        format!("{} has no property `{:?}`", value.type_name(), symb),
+                        e.span, "property not found")))
+                }
+            }
+        }
+        ExprKind::Call(box ref obj, ref symb, ref args) => {
+            let recv = try!(eval_expr(env, obj));
+            let mut vals = Vec::with_capacity(args.len());
+            for arg in args.iter() {
+                vals.push(try!(eval_expr(env, arg)));
+            }
+            call(&recv, symb, vals, e.span).map_err(Unwind::Error)
+        }
+        ExprKind::Block(ref stmts) => {
+            eval_block(&env.child(), stmts.as_slice())
+        }
+        ExprKind::If(box ref cond, box ref thn, box ref els) => {
+            match try!(eval_expr(env, cond)) {
+                Value::Bool(true) => eval_expr(env, thn),
+                Value::Bool(false) => {
+                    match *els {
+                        Some(ref els) => eval_expr(env, els),
+                        None => Ok(Value::Null),
+                    }
+                }
+                value => {
+                    Err(Unwind::Error(runtime_type_error(
+                        format!("Condition must be a Bool, not {}", value.type_name()),
+                        cond.span, "expected a Bool")))
+                }
+            }
+        }
+        ExprKind::Match(box ref scrut, ref arms) => {
+            let value = try!(eval_expr(env, scrut));
+            for arm in arms.iter() {
+                let arm_env = env.child();
+                if matches(&arm_env, &value, &arm.pat) {
+                    return eval_expr(&arm_env, &arm.body);
+                }
+            }
+            Err(Unwind::Error(runtime_type_error(
+                format!("No pattern matched the {} value {:?}", value.type_name(), value),
+                scrut.span, "in this match")))
+        }
+        ExprKind::While(box ref cond, box ref body) => {
+            loop {
+                match try!(eval_expr(env, cond)) {
+                    Value::Bool(true) => {}
+                    Value::Bool(false) => return Ok(Value::Null),
+                    value => {
+                        return Err(Unwind::Error(runtime_type_error(
+                            format!("Condition must be a Bool, not {}", value.type_name()),
+                            cond.span, "expected a Bool")));
+                    }
+                }
+                match eval_expr(env, body) {
+                    Ok(_) | Err(Unwind::Continue) => {}
+                    Err(Unwind::Break) => return Ok(Value::Null),
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+        ExprKind::For(ref var, box ref iter, box ref body) => {
+            let iter = try!(eval_expr(env, iter));
+            let next = Symbol::from_slice("next");
+            loop {
+                let item = match try!(call(&iter, &next, vec![iter.clone()], e.span).map_err(Unwind::Error)) {
+                    Value::Null => return Ok(Value::Null),
+                    item => item,
+                };
+
+                let body_env = env.child();
+                body_env.define(var.clone(), item);
+                match eval_expr(&body_env, body) {
+                    Ok(_) | Err(Unwind::Continue) => {}
+                    Err(Unwind::Break) => return Ok(Value::Null),
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+        ExprKind::Break => Err(Unwind::Break),
+        ExprKind::Continue => Err(Unwind::Continue),
+        ExprKind::Return(box ref value) => Err(Unwind::Return(try!(eval_expr(env, value)))),
+        ExprKind::Ascribe(box ref expr, _) => eval_expr(env, expr),
+        ExprKind::Error => {
+            Err(Unwind::Error(runtime_type_error(
+                format!("Can't evaluate code which failed to parse"),
+                e.span, "syntax error here")))
+        }
+    }
+}
+
+fn eval_rec(env: &Env, props: &[Prop], brand: Brand) -> Eval {
+    let mut rec = Record{
+        brand: brand,
+        props: RefCell::new(HashMap::new()),
+        mutable: HashSet::new(),
+        methods: HashMap::new(),
+    };
+    try!(add_props(env, &mut rec, props));
+    Ok(Value::Rec(Rc::new(rec)))
+}
+
+/// Evaluates props, adding them to rec. They replace any properties of the
+/// same name, whether those are fields or methods.
+fn add_props(env: &Env, rec: &mut Record, props: &[Prop]) -> Result<(), Unwind> {
+    for prop in props.iter() {
+        match *prop {
+            Prop::Val(ref symb, ref expr, mutability, _) => {
+                rec.methods.remove(symb);
+                match mutability {
+                    Mutability::Mutable => rec.mutable.insert(symb.clone()),
+                    Mutability::Immutable => rec.mutable.remove(symb),
+                };
+                let value = try!(eval_expr(env, expr));
+                rec.props.borrow_mut().insert(symb.clone(), value);
+            }
+            Prop::Method(ref symb, ref params, _, ref body, _) => {
+                rec.props.borrow_mut().remove(symb);
+                rec.mutable.remove(symb);
+                rec.methods.insert(symb.clone(), Rc::new(Method{
+                    params: params.clone(),
+                    body: body.clone(),
+                    env: env.clone(),
+                }));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether value matches pat, binding the pattern's variables in env if it does
+fn matches(env: &Env, value: &Value, pat: &Pat) -> bool {
+    match pat.kind {
+        PatKind::Wild => true,
+        PatKind::Bind(ref id) => {
+            env.define(id.clone(), value.clone());
+            true
+        }
+        PatKind::Typed(ref id, ref annot) => {
+            if has_shape_of(env, value, &annot.node) {
+                env.define(id.clone(), value.clone());
+                true
+            } else { false }
+        }
+        PatKind::Rec(ref brand, ref fields) => {
+            let rec = match *value {
+                Value::Rec(ref rec) if brand.is_none() || rec.brand == *brand => rec,
+                _ => return false,
+            };
+            fields.iter().all(|&(ref symb, ref pat)| {
+                let field = rec.props.borrow().get(symb).cloned();
+                field.map_or(false, |field| matches(env, &field, pat))
+            })
+        }
+    }
+}
+
+/// Whether value has the shape of ty. Builtin values are matched by their
+/// name, and records by their name and the names of their fields and methods.
+fn has_shape_of(env: &Env, value: &Value, ty: &Ty) -> bool {
+    match *ty {
+        Ty::Ident(Ident(ref atom, BuiltIn)) => atom.as_slice() == value.type_name(),
+        // Type variables match anything
+        Ty::Ident(_) => true,
+        Ty::Rec(ref extends, ref brand, ref props) => {
+            let extends_ok = match *extends {
+                Some(box ref extends) => has_shape_of(env, value, extends),
+                None => true,
+            };
+            match *value {
+                Value::Rec(ref rec) => {
+                    extends_ok && (brand.is_none() || rec.brand == *brand) &&
+                        props.iter().all(|prop| {
+                            match *prop {
+                                TyProp::Val(ref symb, _, Mutability::Immutable) => {
+                                    rec.props.borrow().contains_key(symb)
+                                }
+                                TyProp::Val(ref symb, _, Mutability::Mutable) => rec.mutable.contains(symb),
+                                TyProp::Method(ref symb, _, _) => rec.methods.contains_key(symb),
+                            }
+                        })
+                }
+                // Builtin values aren't records, so they only match their own name
+                _ => match *brand {
+                    Some(Ident(ref atom, BuiltIn)) => atom.as_slice() == value.type_name(),
+                    _ => false,
+                },
+            }
+        }
+        Ty::Union(ref opts) => opts.iter().any(|opt| has_shape_of(env, value, opt)),
+        // The builtin aliases aren't declared in any frame
+        Ty::Alias(Ident(ref atom, BuiltIn), _) => atom.as_slice() == value.type_name(),
+        Ty::Alias(ref id, ref args) => has_shape_of(env, value, &env.expand_alias(id, args.as_slice())),
+    }
+}
+
+/// Calls the method `symb` on `recv`
+pub fn call(recv: &Value, symb: &Symbol, args: Vec<Value>, span: Span) -> Result<Value, Diagnostic> {
+    let method = match *recv {
+        Value::Rec(ref rec) => rec.methods.get(symb).cloned(),
+        _ => return call_builtin(recv, symb, args.as_slice(), span),
+    };
+
+    match method {
+        Some(method) => {
+            if method.params.len() != args.len() {
+                return Err(runtime_type_error(
+                    format!("Method `{:?}` takes {} arguments, but {} were supplied",
+                            symb, method.params.len(), args.len()),
+                    span, "in this method call"));
+            }
+
+            let env = method.env.child();
+            for (param, arg) in method.params.iter().zip(args.into_iter()) {
+                env.define(param.clone(), arg);
+            }
+            match eval_expr(&env, &method.body) {
+                Err(Unwind::Return(value)) => Ok(value),
+                res => finish(res),
+            }
+        }
+        None => {
+            Err(Diagnostic::error(
+                Kind::UndefinedMember,
+                format!("Record has no method `{:?}`", symb)
+            ).with_primary(span, "method not found"))
+        }
+    }
+}
+
+fn call_builtin(recv: &Value, symb: &Symbol, args: &[Value], span: Span) -> Result<Value, Diagnostic> {
+    let op = symb.0.as_slice();
+    let res = match *recv {
+        Value::Int(a) => int_method(a, op, args, span),
+        Value::Float(a) => float_method(a, op, args),
+        Value::Str(ref s) => str_method(s, op, args),
+        Value::Bool(b) => bool_method(b, op, args),
+        Value::List(ref items) => list_method(items, op, args, span),
+        _ => None,
+    };
+
+    match res {
+        Some(res) => res,
+        None => {
+            Err(Diagnostic::error(
+                Kind::UndefinedMember,
+                format!("{} has no method `{}` taking {} arguments", recv.type_name(), op, args.len())
+            ).with_primary(span, "method not found"))
+        }
+    }
+}
+
+fn int_method(a: i64, op: &str, args: &[Value], span: Span) -> Option<Result<Value, Diagnostic>> {
+    let overflow = || {
+        Diagnostic::error(Kind::ArithmeticError, format!("Integer overflow"))
+            .with_primary(span, "this operation overflowed")
+    };
+
+    if args.len() == 0 {
+        return match op {
+            "negate" => Some(0i64.checked_sub(a).map(Value::Int).ok_or_else(overflow)),
+            "to_float" => Some(Ok(Value::Float(a as f64))),
+            "to_str" => Some(Ok(Value::Str(Rc::new(a.to_string())))),
+            _ => None,
+        };
+    }
+
+    let b = match (args.len(), &args[0]) {
+        (1, &Value::Int(b)) => b,
+        _ => return None,
+    };
+    Some(match op {
+        "+" => a.checked_add(b).map(Value::Int).ok_or_else(overflow),
+        "-" => a.checked_sub(b).map(Value::Int).ok_or_else(overflow),
+        "*" => a.checked_mul(b).map(Value::Int).ok_or_else(overflow),
+        "/" | "%" if b == 0 => {
+            Err(Diagnostic::error(Kind::ArithmeticError, format!("Division by zero"))
+                .with_primary(span, "attempted to divide by zero"))
+        }
+        "/" | "%" if a == i64::MIN && b == -1 => Err(overflow()),
+        "/" => Ok(Value::Int(a / b)),
+        "%" => Ok(Value::Int(a % b)),
+        "&" => Ok(Value::Int(a & b)),
+        "|" => Ok(Value::Int(a | b)),
+        "^" => Ok(Value::Int(a ^ b)),
+        "==" => Ok(Value::Bool(a == b)),
+        "!=" => Ok(Value::Bool(a != b)),
+        "<" => Ok(Value::Bool(a < b)),
+        "<=" => Ok(Value::Bool(a <= b)),
+        ">" => Ok(Value::Bool(a > b)),
+        ">=" => Ok(Value::Bool(a >= b)),
+        _ => return None,
+    })
+}
+
+fn float_method(a: f64, op: &str, args: &[Value]) -> Option<Result<Value, Diagnostic>> {
+    if args.len() == 0 {
+        return match op {
+            "negate" => Some(Ok(Value::Float(-a))),
+            "to_int" => Some(Ok(Value::Int(a as i64))),
+            "to_str" => Some(Ok(Value::Str(Rc::new(a.to_string())))),
+            _ => None,
+        };
+    }
+
+    let b = match (args.len(), &args[0]) {
+        (1, &Value::Float(b)) => b,
+        _ => return None,
+    };
+    Some(Ok(match op {
+        "+" => Value::Float(a + b),
+        "-" => Value::Float(a - b),
+        "*" => Value::Float(a * b),
+        "/" => Value::Float(a / b),
+        "==" => Value::Bool(a == b),
+        "!=" => Value::Bool(a != b),
+        "<" => Value::Bool(a < b),
+        "<=" => Value::Bool(a <= b),
+        ">" => Value::Bool(a > b),
+        ">=" => Value::Bool(a >= b),
+        _ => return None,
+    }))
+}
+
+fn str_method(a: &Rc<String>, op: &str, args: &[Value]) -> Option<Result<Value, Diagnostic>> {
+    match (op, args.len(), args.get(0)) {
+        ("len", 0, _) => Some(Ok(Value::Int(a.chars().count() as i64))),
+        ("to_str", 0, _) => Some(Ok(Value::Str(a.clone()))),
+        ("+", 1, Some(&Value::Str(ref b))) => Some(Ok(Value::Str(Rc::new(format!("{}{}", a, b))))),
+        ("==", 1, Some(&Value::Str(ref b))) => Some(Ok(Value::Bool(a == b))),
+        ("!=", 1, Some(&Value::Str(ref b))) => Some(Ok(Value::Bool(a != b))),
+        _ => None,
+    }
+}
+
+fn bool_method(a: bool, op: &str, args: &[Value]) -> Option<Result<Value, Diagnostic>> {
+    match (op, args.len(), args.get(0)) {
+        ("not", 0, _) => Some(Ok(Value::Bool(!a))),
+        ("to_str", 0, _) => Some(Ok(Value::Str(Rc::new(a.to_string())))),
+        ("==", 1, Some(&Value::Bool(b))) => Some(Ok(Value::Bool(a == b))),
+        ("!=", 1, Some(&Value::Bool(b))) => Some(Ok(Value::Bool(a != b))),
+        _ => None,
+    }
+}
+
+fn list_method(items: &Rc<RefCell<Vec<Value>>>, op: &str, args: &[Value], span: Span)
+               -> Option<Result<Value, Diagnostic>> {
+    let call_symb = Symbol::from_slice("call");
+    Some(match (op, args.len()) {
+        ("len", 0) => Ok(Value::Int(items.borrow().len() as i64)),
+        ("get", 1) => {
+            let i = match args[0] {
+                Value::Int(i) => i,
+                _ => return None,
+            };
+            let items = items.borrow();
+            if i < 0 || i as usize >= items.len() {
+                Err(Diagnostic::error(
+                    Kind::IndexOutOfBounds,
+                    format!("Index {} is out of bounds for a list of length {}", i, items.len())
+                ).with_primary(span, "indexed here"))
+            } else {
+                Ok(items[i as usize].clone())
+            }
+        }
+        ("push", 1) => {
+            items.borrow_mut().push(args[0].clone());
+            Ok(Value::Null)
+        }
+        ("map", 1) => {
+            // The function may push onto the list, so we iterate over a copy
+            let snapshot = items.borrow().clone();
+            let mut mapped = Vec::with_capacity(snapshot.len());
+            for item in snapshot.into_iter() {
+                match call(&args[0], &call_symb, vec![item], span) {
+                    Ok(value) => mapped.push(value),
+                    Err(err) => return Some(Err(err)),
+                }
+            }
+            Ok(Value::List(Rc::new(RefCell::new(mapped))))
+        }
+        ("fold", 2) => {
+            let snapshot = items.borrow().clone();
+            let mut acc = args[0].clone();
+            for item in snapshot.into_iter() {
+                acc = match call(&args[1], &call_symb, vec![acc, item], span) {
+                    Ok(value) => value,
+                    Err(err) => return Some(Err(err)),
+                };
+            }
+            Ok(acc)
+        }
+        _ => return None,
+    })
+}
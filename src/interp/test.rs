@@ -0,0 +1,209 @@
+use interp::{self, Value};
+use lexer;
+use parser;
+use scope;
+use diagnostic::{Diagnostic, Kind};
+
+/// Compiles some code, and then evaluates it
+fn eval_code(code: &str) -> Result<Value, Diagnostic> {
+    let tokens = try!(lexer::lex(code));
+    let ast = try!(parser::parse_program(&mut parser::State::new(tokens.as_slice())));
+    let scoped_ast = try!(scope::scoped_block(&mut scope::Scope::new(), ast.as_slice()));
+    interp::eval_program(scoped_ast.as_slice())
+}
+
+/// Asserts that the given code evaluates to a value which prints as `expected`
+fn eval_eq(code: &str, expected: &str) {
+    match eval_code(code) {
+        Ok(value) => assert_eq!(format!("{:?}", value), expected),
+        Err(e) => panic!("\nUnexpected error evaluating code:\n\n{}\n\n{}\n", e, code),
+    }
+}
+
+/// Asserts that evaluating the given code fails with the given kind of error
+fn eval_err(code: &str, kind: Kind) {
+    match eval_code(code) {
+        Ok(value) => panic!("\nUnexpected success evaluating code:\n\n{}\n\n{:?}\n", code, value),
+        Err(e) => assert_eq!(e.kind, kind),
+    }
+}
+
+#[test]
+fn arithmetic() {
+    eval_eq("1 + 2 * 3", "7");
+    eval_eq("(1 + 2) * 3", "9");
+    eval_eq("7 / 2 - 7 % 2", "2");
+    eval_eq("-(5)", "-5");
+}
+
+#[test]
+fn operator_precedence() {
+    eval_eq("1 + 2 == 3", "true");
+    eval_eq("1 < 2 && 2 < 1 || 3 >= 3", "true");
+    eval_eq("6 & 3 | 8 ^ 1", "11");
+    eval_eq("2 + 2 != 4", "false");
+}
+
+#[test]
+fn logical_operators_short_circuit() {
+    eval_eq("false && 1 / 0 == 0", "false");
+    eval_eq("true || 1 / 0 == 0", "true");
+    eval_err("true && 1 / 0 == 0", Kind::ArithmeticError);
+}
+
+#[test]
+fn arithmetic_errors() {
+    eval_err("1 / 0", Kind::ArithmeticError);
+    eval_err("9223372036854775807 + 1", Kind::ArithmeticError);
+}
+
+#[test]
+fn blocks_and_lets() {
+    eval_eq("let x = 5; let y = x * 2; y + 1", "11");
+    eval_eq("let x = 5; if true { let x = 1; x }; x", "5");
+    eval_eq("let x = 5;", "null");
+}
+
+#[test]
+fn closures_capture_env() {
+    eval_eq(stringify!{
+        let make_adder = fn(x) { fn(y) { x + y } };
+        let add_two = make_adder(2);
+        add_two(40)
+    }, "42");
+}
+
+#[test]
+fn records_with_methods() {
+    eval_eq(stringify!{
+        let counter = {
+            count: 10,
+            fn next(by) { by + 1 }
+        };
+        counter.next(counter.count)
+    }, "11");
+
+    eval_eq("{ b: 2, a: 1, fn f() { 0 } }", "{ a: 1, b: 2, fn f }");
+    eval_err("{ a: 1 }.b", Kind::UndefinedMember);
+}
+
+#[test]
+fn use_before_binding() {
+    eval_err(stringify!{
+        let x = y;
+        let y = 5;
+    }, Kind::UninitializedVariable);
+
+    // Functions can refer to later bindings, as long as they run after them
+    eval_eq(stringify!{
+        let f = fn() { y };
+        let y = 5;
+        f()
+    }, "5");
+}
+
+#[test]
+fn if_expressions() {
+    eval_eq("if true { 1 } else { 2 }", "1");
+    eval_eq("if false { 1 } else { 2 }", "2");
+    eval_eq("if false { 1 }", "null");
+    eval_err("if 1 { 2 }", Kind::RuntimeTypeError);
+}
+
+#[test]
+fn parens_and_ascriptions() {
+    eval_eq("(1 + 2) * 3", "9");
+    eval_eq("(2 * 3 : Int) + 1", "7");
+}
+
+#[test]
+fn named_records() {
+    eval_eq("name P; let p = #P#{ x: 1 }; p.x + 1", "2");
+    eval_eq("let v = { x: 1, fn +(o) { o.x + 1 } }; v + { x: 2 }", "3");
+}
+
+#[test]
+fn record_update() {
+    eval_eq("let p = { x: 1, y: 2 }; p with { x: 10, z: 3 }", "{ x: 10, y: 2, z: 3 }");
+    eval_eq("let p = { x: 1 }; let q = p with { x: 2 }; p.x + q.x", "3");
+    eval_eq("{ x: 1, fn f() { 0 } } with { f: 2, fn x() { 3 } }", "{ f: 2, fn x }");
+    eval_eq("name P; #P#{ x: 1 } with { y: 2 }", "{ x: 1, y: 2 }");
+    eval_err("1 with { x: 2 }", Kind::RuntimeTypeError);
+}
+
+#[test]
+fn mutable_fields() {
+    eval_eq("let r = { mut n: 1 }; r.n <- r.n + 1; r", "{ mut n: 2 }");
+    eval_eq("let r = { mut n: 1 }; let s = r; s.n <- 5; r.n", "5");
+    eval_eq("let r = { mut n: 1 }; let s = r with { m: 2 }; s.n <- 5; r.n + s.n", "6");
+    eval_eq("{ mut n: 1 }.n <- 2", "null");
+    eval_err("{ n: 1 }.n <- 2", Kind::RuntimeTypeError);
+    eval_err("true.n <- 2", Kind::RuntimeTypeError);
+}
+
+#[test]
+fn loops() {
+    let range = "let range = fn(lo, hi) {
+        let s = { mut i: lo };
+        { fn next(self) { if s.i < hi { let i = s.i; s.i <- i + 1; i } else { null } } }
+    };";
+
+    eval_eq("let s = { mut i: 0 }; while s.i < 5 { s.i <- s.i + 1 }; s.i", "5");
+    eval_eq("let s = { mut i: 0 }; while true { s.i <- s.i + 1; if s.i > 3 { break } }; s.i", "4");
+    eval_eq(format!("{} let s = {{ mut n: 0 }}; for x in range(0, 5) {{ s.n <- s.n + x }}; s.n",
+                    range).as_slice(), "10");
+    eval_eq(format!("{} let xs = []; for x in range(0, 6) {{
+                         if x % 2 == 0 {{ continue }};
+                         if x > 4 {{ break }};
+                         xs:push(x)
+                     }}; xs", range).as_slice(), "[1, 3]");
+    eval_eq("let s = { mut n: 0 };
+             let count = {
+                 mut i: 0,
+                 fn next(self) { if self.i < 3 { self.i <- self.i + 1; self.i } else { null } }
+             };
+             for x in count { s.n <- s.n + x }; s.n", "6");
+    eval_eq("while false { 1 }", "null");
+    eval_err("while 1 { 2 }", Kind::RuntimeTypeError);
+    eval_err("for x in 1 { x }", Kind::UndefinedMember);
+}
+
+#[test]
+fn early_return() {
+    eval_eq("let f = fn(x) { if x > 0 { return \"pos\" }; \"neg\" }; f(1) + f(-1)", "\"posneg\"");
+    eval_eq("let f = fn() { let s = { mut i: 0 }; while true { s.i <- s.i + 1; if s.i == 3 { return s.i } } };
+             f() * 2", "6");
+    // Returning from a function doesn't leave the function which called it
+    eval_eq("let f = fn(g) { g(); 1 }; f(fn() { return 2 }) + 10", "11");
+    // A bare return produces null
+    eval_eq("let f = fn(x) { if x { return }; 1 }; f(true)", "null");
+}
+
+#[test]
+fn recursive_bindings() {
+    eval_eq("let fib = fn(n) { if n < 2 { n } else { fib(n - 1) + fib(n - 2) } }; fib(15)", "610");
+    eval_eq("let even = fn(n) { if n == 0 { true } else { odd(n - 1) } };
+             let odd = fn(n) { if n == 0 { false } else { even(n - 1) } };
+             even(10)", "true");
+}
+
+#[test]
+fn lists() {
+    eval_eq("let xs = [1, 2, 3]; xs[1] + xs:len()", "5");
+    eval_eq("let xs = []; xs:push(\"a\"); xs", "[\"a\"]");
+    eval_eq("[1, 2]:map(fn(x) { x * 10 })", "[10, 20]");
+    eval_eq("[1, 2, 3]:fold(0, fn(acc, x) { acc + x })", "6");
+    eval_eq("match [1] { xs: List[Int] => xs:len(), _ => 0 }", "1");
+    eval_err("[1, 2][2]", Kind::IndexOutOfBounds);
+    eval_err("[1][-1]", Kind::IndexOutOfBounds);
+}
+
+#[test]
+fn match_expressions() {
+    eval_eq("let v = { x: 1, y: 2 }; match v { {x, y} => x + y }", "3");
+    eval_eq("match 5 { {x} => x, n: Int => n * 2 }", "10");
+    eval_eq("name P; match #P#{ x: 1 } { {x: s: Str} => 0, #P#{ x } => x + 1 }", "2");
+    eval_eq("type Pt = { x: Int }; match { x: 3 } { p: Pt => p.x, _ => 0 }", "3");
+    eval_eq("match null { n: Null => 1, _ => 2 }", "1");
+    eval_err("match 1 { s: Str => s }", Kind::RuntimeTypeError);
+}
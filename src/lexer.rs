@@ -1,19 +1,11 @@
-use std::str::FromStr;
+use std::fmt;
+use std::char;
+use std::i64;
 use intern::Atom;
+use span::{Span, Spanned};
+use diagnostic::{Diagnostic, Kind};
 use self::Token::*;
 
-macro_rules! nom {
-    ($stream:ident | $var: ident | -> { $($patt:expr => $action:expr),+ }) => (
-        $(if let Some((0, len)) = regex!($patt).find($stream) {
-            let $var = $stream.slice_to(len);
-            $stream = $stream.slice_from(len);
-            Some($action)
-        })else+ else {
-            None
-        }
-    )
-}
-
 #[allow(non_camel_case_types)]
 #[derive(Debug)]
 pub enum Token {
@@ -51,6 +43,7 @@ pub enum Token {
     RARROW,
     LARROW,
     FAT_ARROW,
+    HASH,
 
     // Keywords
     FN,
@@ -59,6 +52,16 @@ pub enum Token {
     FALSE,
     IF,
     ELSE,
+    TYPE,
+    MATCH,
+    WITH,
+    MUT,
+    WHILE,
+    FOR,
+    IN,
+    BREAK,
+    CONTINUE,
+    RETURN,
 
     // Literals
     LIT_INTEGER(i64),
@@ -67,81 +70,630 @@ pub enum Token {
 
     // Identifier
     IDENT(Atom),
+
+    // Trivia
+    DOC_COMMENT(Atom),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            EQ => "=", LT => "<", LE => "<=", EQEQ => "==", NE => "!=",
+            GE => ">=", GT => ">", ANDAND => "&&", OROR => "||", NOT => "!",
+            PLUS => "+", MINUS => "-", STAR => "*", SLASH => "/",
+            PERCENT => "%", CARET => "^", AND => "&", OR => "|",
+
+            LBRACE => "{", RBRACE => "}", LBRACKET => "[", RBRACKET => "]",
+            LPAREN => "(", RPAREN => ")", DOT => ".", COMMA => ",",
+            SEMI => ";", COLON => ":", RARROW => "->", LARROW => "<-",
+            FAT_ARROW => "=>", HASH => "#",
+
+            FN => "fn", LET => "let", TRUE => "true", FALSE => "false",
+            IF => "if", ELSE => "else", TYPE => "type", MATCH => "match",
+            WITH => "with", MUT => "mut", WHILE => "while", FOR => "for",
+            IN => "in", BREAK => "break", CONTINUE => "continue", RETURN => "return",
+
+            LIT_INTEGER(i) => return write!(f, "integer `{}`", i),
+            LIT_FLOAT(n) => return write!(f, "float `{}`", n),
+            LIT_STR(ref s) => return write!(f, "string {:?}", s.as_slice()),
+            IDENT(ref id) => return write!(f, "identifier `{}`", id),
+            DOC_COMMENT(_) => return write!(f, "doc comment"),
+        };
+        write!(f, "`{}`", s)
+    }
+}
+
+/// An error which stops the lexer
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexError {
+    UnexpectedChar(char, Span),
+    UnterminatedComment(Span),
+    UnterminatedString(Span),
+    UnknownEscape(char, Span),
+    InvalidUnicodeEscape(Span),
+    InvalidDigit(char, u32, Span),
+    MissingDigits(Span),
+    MissingExponent(Span),
+    NumberOverflow(Span),
+}
+
+impl LexError {
+    pub fn span(&self) -> Span {
+        match *self {
+            LexError::UnexpectedChar(_, span) => span,
+            LexError::UnterminatedComment(span) => span,
+            LexError::UnterminatedString(span) => span,
+            LexError::UnknownEscape(_, span) => span,
+            LexError::InvalidUnicodeEscape(span) => span,
+            LexError::InvalidDigit(_, _, span) => span,
+            LexError::MissingDigits(span) => span,
+            LexError::MissingExponent(span) => span,
+            LexError::NumberOverflow(span) => span,
+        }
+    }
+
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let span = self.span();
+        match *self {
+            LexError::UnexpectedChar(c, _) => {
+                Diagnostic::error(Kind::UnexpectedChar, format!("Unexpected character {:?}", c))
+                    .with_primary(span, "unexpected character")
+            }
+            LexError::UnterminatedComment(_) => {
+                Diagnostic::error(Kind::UnterminatedComment, format!("Unterminated block comment"))
+                    .with_primary(span, "this comment is never closed")
+            }
+            LexError::UnterminatedString(_) => {
+                Diagnostic::error(Kind::UnterminatedString, format!("Unterminated string literal"))
+                    .with_primary(span, "this string is never closed")
+            }
+            LexError::UnknownEscape(c, _) => {
+                Diagnostic::error(Kind::InvalidEscape, format!("Unknown escape sequence `\\{}`", c))
+                    .with_primary(span, "unknown escape")
+                    .with_note(format!("valid escapes are \\n, \\t, \\r, \\0, \\\\, \\\", \\' and \\u{{...}}"))
+            }
+            LexError::InvalidUnicodeEscape(_) => {
+                Diagnostic::error(Kind::InvalidEscape, format!("Invalid unicode escape"))
+                    .with_primary(span, "expected \\u{...} with 1-6 hex digits")
+                    .with_note(format!("the value must be a valid unicode scalar value"))
+            }
+            LexError::InvalidDigit(c, radix, _) => {
+                Diagnostic::error(Kind::InvalidNumber,
+                                  format!("Invalid digit {:?} in base {} literal", c, radix))
+                    .with_primary(span, "invalid digit")
+            }
+            LexError::MissingDigits(_) => {
+                Diagnostic::error(Kind::InvalidNumber, format!("Integer literal has no digits"))
+                    .with_primary(span, "expected at least one digit")
+            }
+            LexError::MissingExponent(_) => {
+                Diagnostic::error(Kind::InvalidNumber, format!("Float literal has an empty exponent"))
+                    .with_primary(span, "expected at least one digit in the exponent")
+            }
+            LexError::NumberOverflow(_) => {
+                Diagnostic::error(Kind::NumberOverflow, format!("Number literal is too large"))
+                    .with_primary(span, "does not fit in 64 bits")
+                    .with_note(format!("integer literals can be at most {}, as `-` is applied \
+                                        after the literal is read", i64::MAX))
+            }
+        }
+    }
 }
 
-pub fn lex(program: &str) -> Result<Vec<Token>, String> {
-    let mut toks: Vec<Token> = vec![];
-    let mut stream = program.clone();
+/// The Lexer turns the source program into a stream of tokens. It looks
+/// at each character at most a couple of times, so lexing is linear in
+/// the length of the program.
+pub struct Lexer<'a> {
+    src: &'a str,
+    offset: usize,
+    line: u32,
+    col: u32,
+    /// The lexer doesn't try to recover from errors, so it stops after one
+    done: bool,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(src: &'a str) -> Lexer<'a> {
+        Lexer{ src: src, offset: 0, line: 1, col: 1, done: false }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.src[self.offset..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn peek_nth(&self, n: usize) -> Option<char> {
+        self.rest().chars().nth(n)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek();
+        if let Some(c) = c {
+            self.offset += c.len_utf8();
+            if c == '\n' {
+                self.line += 1;
+                self.col = 1;
+            } else {
+                self.col += 1;
+            }
+        }
+        c
+    }
+
+    /// Consumes the next character if it is `c`
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.bump();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_while<F: Fn(char) -> bool>(&mut self, f: F) -> &'a str {
+        let start = self.offset;
+        while let Some(c) = self.peek() {
+            if ! f(c) { break }
+            self.bump();
+        }
+        &self.src[start..self.offset]
+    }
+
+    /// An empty span at the current position
+    fn pos(&self) -> Span {
+        Span{ lo: self.offset, hi: self.offset, line: self.line, col: self.col,
+              end_line: self.line, end_col: self.col }
+    }
+
+    /// A span from lo to the current position
+    fn span_from(&self, lo: Span) -> Span {
+        Span{ hi: self.offset, end_line: self.line, end_col: self.col, ..lo }
+    }
+
+    fn next_token(&mut self) -> Option<Result<Spanned<Token>, LexError>> {
+        loop {
+            let lo = self.pos();
+            let c = match self.peek() {
+                Some(c) => c,
+                None => return None,
+            };
 
-    let start_len = stream.len(); // TODO(michael): Figure out why char_len doesn't work anymore
+            if c.is_whitespace() {
+                self.bump();
+            } else if c == '/' && self.peek_nth(1) == Some('/') {
+                // Line comments are skipped, except for doc comments (`///`),
+                // which are kept so that the parser can attach them to bindings
+                let text = self.eat_while(|c| c != '\n');
+                if text.starts_with("///") && ! text.starts_with("////") {
+                    let doc = &text[3..];
+                    let doc = if doc.starts_with(" ") { &doc[1..] } else { doc };
+                    let tok = DOC_COMMENT(Atom::from_slice(doc.trim_right()));
+                    return Some(Ok(Spanned::new(tok, self.span_from(lo))));
+                }
+            } else if c == '/' && self.peek_nth(1) == Some('*') {
+                if let Err(err) = self.block_comment(lo) {
+                    return Some(Err(err));
+                }
+            } else {
+                return Some(self.lex_token(lo, c).map(|tok| Spanned::new(tok, self.span_from(lo))));
+            }
+        }
+    }
+
+    fn lex_token(&mut self, lo: Span, c: char) -> Result<Token, LexError> {
+        if c == '"' {
+            return self.lex_str(lo);
+        }
+        if c == 'r' && self.rest()[1..].trim_left_matches('#').starts_with("\"") {
+            return self.lex_raw_str(lo);
+        }
+        if c.is_alphabetic() || c == '_' {
+            let ident = self.eat_while(|c| c.is_alphanumeric() || c == '_');
+            return Ok(match ident {
+                "fn" => FN,
+                "let" => LET,
+                "true" => TRUE,
+                "false" => FALSE,
+                "if" => IF,
+                "else" => ELSE,
+                "type" => TYPE,
+                "match" => MATCH,
+                "with" => WITH,
+                "mut" => MUT,
+                "while" => WHILE,
+                "for" => FOR,
+                "in" => IN,
+                "break" => BREAK,
+                "continue" => CONTINUE,
+                "return" => RETURN,
+                _ => IDENT(Atom::from_slice(ident)),
+            });
+        }
+        if c.is_digit(10) {
+            return self.lex_number(lo);
+        }
 
-    while stream.len() > 0 {
-        if let Some((0, len)) = regex!(r"^\s+").find(stream) {
-            // Skip all spaces
-            stream = &stream[len..];
-        } else if let Some(tok) = nom!(stream |_v| -> {
+        self.bump();
+        Ok(match c {
             // Brackets, Braces, and Parens
-            r"^\{" => LBRACE,
-            r"^\}" => RBRACE,
-            r"^\(" => LPAREN,
-            r"^\)" => RPAREN,
-            r"^\[" => LBRACKET,
-            r"^\]" => RBRACKET,
-
-            // Arrows
-            r"^->" => RARROW,
-            r"^<-" => LARROW,
-            r"^=>" => FAT_ARROW,
-
-            // Logical Operators
-            r"^<=" => LE,
-            r"^>=" => GE,
-            r"^!=" => NE,
-            r"^<"  => LT,
-            r"^>"  => GT,
-            r"^==" => EQEQ,
-            r"^="  => EQ,
-            r"^&&" => ANDAND,
-            r"^&"  => AND,
-            r"^\|\|" => OROR,
-            r"^\|" => OR,
-            r"^!"  => NOT,
+            '{' => LBRACE,
+            '}' => RBRACE,
+            '(' => LPAREN,
+            ')' => RPAREN,
+            '[' => LBRACKET,
+            ']' => RBRACKET,
+
+            // Operators which may be the start of a longer operator
+            '-' => if self.eat('>') { RARROW } else { MINUS },
+            '<' => {
+                if self.eat('-') { LARROW } else if self.eat('=') { LE } else { LT }
+            }
+            '=' => {
+                if self.eat('>') { FAT_ARROW } else if self.eat('=') { EQEQ } else { EQ }
+            }
+            '>' => if self.eat('=') { GE } else { GT },
+            '!' => if self.eat('=') { NE } else { NOT },
+            '&' => if self.eat('&') { ANDAND } else { AND },
+            '|' => if self.eat('|') { OROR } else { OR },
 
             // Mathematical Operators
-            r"^\+" => PLUS,
-            r"^-"  => MINUS,
-            r"^\*" => STAR,
-            r"^/"  => SLASH,
-            r"^%"  => PERCENT,
-            r"^\^" => CARET,
+            '+' => PLUS,
+            '*' => STAR,
+            '/' => SLASH,
+            '%' => PERCENT,
+            '^' => CARET,
 
             // Structural
-            r"^\." => DOT,
-            r"^,"  => COMMA,
-            r"^;"  => SEMI,
-            r"^:"  => COLON,
-
-            // The interesting ones
-            r"^[a-zA-Z_][a-zA-Z0-9_]*" => {
-                match _v {
-                    "fn" => FN,
-                    "let" => LET,
-                    "true" => TRUE,
-                    "false" => FALSE,
-                    "if" => IF,
-                    "else" => ELSE,
-                    _ => IDENT(Atom::from_slice(_v)),
+            '.' => DOT,
+            ',' => COMMA,
+            ';' => SEMI,
+            ':' => COLON,
+            '#' => HASH,
+
+            _ => return Err(LexError::UnexpectedChar(c, self.span_from(lo))),
+        })
+    }
+
+    fn lex_number(&mut self, lo: Span) -> Result<Token, LexError> {
+        // Integers can be written in binary, octal or hexadecimal
+        let radix = match (self.peek(), self.peek_nth(1)) {
+            (Some('0'), Some('b')) => 2,
+            (Some('0'), Some('o')) => 8,
+            (Some('0'), Some('x')) => 16,
+            _ => 10,
+        };
+        if radix != 10 {
+            self.bump();
+            self.bump();
+            let digits_lo = self.pos();
+            let digits = self.eat_while(|c| c.is_alphanumeric() || c == '_');
+            if let Some((i, c)) = digits.char_indices().find(|&(_, c)| c != '_' && ! c.is_digit(radix)) {
+                let col = digits_lo.col + digits[..i].chars().count() as u32;
+                let span = Span{ lo: digits_lo.lo + i, hi: digits_lo.lo + i + c.len_utf8(),
+                                 line: digits_lo.line, col: col,
+                                 end_line: digits_lo.line, end_col: col + 1 };
+                return Err(LexError::InvalidDigit(c, radix, span));
+            }
+            return self.int_literal(lo, digits, radix);
+        }
+
+        let digits = self.eat_while(|c| c.is_digit(10) || c == '_');
+        let mut is_float = false;
+
+        // A dot is only part of the number if it isn't followed by an
+        // identifier, so that methods can still be called on integers (`1.foo`)
+        if self.peek() == Some('.') {
+            let is_frac = match self.peek_nth(1) {
+                Some(c) => ! (c.is_alphabetic() || c == '_' || c == '.'),
+                None => true,
+            };
+            if is_frac {
+                self.bump();
+                self.eat_while(|c| c.is_digit(10) || c == '_');
+                is_float = true;
+            }
+        }
+
+        if self.peek() == Some('e') || self.peek() == Some('E') {
+            self.bump();
+            if self.peek() == Some('+') || self.peek() == Some('-') {
+                self.bump();
+            }
+            let exp = self.eat_while(|c| c.is_digit(10) || c == '_');
+            if exp.chars().all(|c| c == '_') {
+                return Err(LexError::MissingExponent(self.span_from(lo)));
+            }
+            is_float = true;
+        }
+
+        if ! is_float {
+            return self.int_literal(lo, digits, 10);
+        }
+
+        let text: String = self.src[lo.lo..self.offset].chars().filter(|&c| c != '_').collect();
+        match text.parse::<f64>() {
+            Ok(f) if f.is_finite() => Ok(LIT_FLOAT(f)),
+            _ => Err(LexError::NumberOverflow(self.span_from(lo))),
+        }
+    }
+
+    fn int_literal(&self, lo: Span, digits: &str, radix: u32) -> Result<Token, LexError> {
+        let digits: String = digits.chars().filter(|&c| c != '_').collect();
+        if digits.is_empty() {
+            return Err(LexError::MissingDigits(self.span_from(lo)));
+        }
+
+        // The digits have been checked, so parsing can only fail on overflow
+        match i64::from_str_radix(digits.as_slice(), radix) {
+            Ok(i) => Ok(LIT_INTEGER(i)),
+            Err(_) => Err(LexError::NumberOverflow(self.span_from(lo))),
+        }
+    }
+
+    fn lex_str(&mut self, lo: Span) -> Result<Token, LexError> {
+        self.bump(); // The opening quote
+        let mut value = String::new();
+        loop {
+            let esc_lo = self.pos();
+            match self.bump() {
+                Some('"') => return Ok(LIT_STR(Atom::from_slice(value.as_slice()))),
+                Some('\\') => value.push(try!(self.lex_escape(lo, esc_lo))),
+                Some(c) => value.push(c),
+                None => {
+                    let quote = Span{ hi: lo.lo + 1, end_col: lo.col + 1, ..lo };
+                    return Err(LexError::UnterminatedString(quote))
                 }
-            },
-            r#"^"([^"]|\\")""# => LIT_STR(Atom::from_slice(_v)), // TODO: Better string parsing
-            r"^[0-9]*\.[0-9]+" => LIT_FLOAT(FromStr::from_str(_v).unwrap()),
-            r"^[0-9]+" => LIT_INTEGER(FromStr::from_str(_v).unwrap())
-        }) {
-            toks.push(tok);
-        } else {
-            return Err(format!("{}: {}", start_len - stream.len(), stream.char_at(0)));
+            }
+        }
+    }
+
+    /// Lexes the escape sequence after a backslash, returning the character
+    /// which it represents. lo is the start of the escape sequence.
+    fn lex_escape(&mut self, str_lo: Span, lo: Span) -> Result<char, LexError> {
+        let esc = match self.bump() {
+            Some(esc) => esc,
+            None => {
+                let quote = Span{ hi: str_lo.lo + 1, end_col: str_lo.col + 1, ..str_lo };
+                return Err(LexError::UnterminatedString(quote))
+            }
+        };
+
+        Ok(match esc {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '\\' => '\\',
+            '"' => '"',
+            '\'' => '\'',
+            'u' => {
+                // Unicode escapes look like \u{1F986}
+                if ! self.eat('{') {
+                    return Err(LexError::InvalidUnicodeEscape(self.span_from(lo)));
+                }
+                let digits = self.eat_while(|c| c.is_digit(16));
+                let closed = self.eat('}');
+                let c = if closed && digits.len() >= 1 && digits.len() <= 6 {
+                    u32::from_str_radix(digits, 16).ok().and_then(char::from_u32)
+                } else {
+                    None
+                };
+
+                match c {
+                    Some(c) => c,
+                    None => return Err(LexError::InvalidUnicodeEscape(self.span_from(lo))),
+                }
+            }
+            _ => return Err(LexError::UnknownEscape(esc, self.span_from(lo))),
+        })
+    }
+
+    /// Raw strings (r"...", r#"..."#, ...) don't have escapes. The hashes
+    /// allow the string to contain quotes.
+    fn lex_raw_str(&mut self, lo: Span) -> Result<Token, LexError> {
+        self.bump(); // r
+        let hashes = self.eat_while(|c| c == '#').len();
+        self.bump(); // The opening quote
+
+        let mut close = String::from_str("\"");
+        for _ in 0..hashes {
+            close.push('#');
+        }
+
+        match self.rest().find(close.as_slice()) {
+            Some(len) => {
+                let value = &self.rest()[..len];
+                // Step over the contents, so that line numbers stay correct
+                while self.offset < lo.lo + hashes + 2 + len + close.len() {
+                    self.bump();
+                }
+                Ok(LIT_STR(Atom::from_slice(value)))
+            }
+            None => Err(LexError::UnterminatedString(self.span_from(lo))),
         }
     }
 
-    Ok(toks)
+    /// Skips a block comment. Block comments nest.
+    fn block_comment(&mut self, lo: Span) -> Result<(), LexError> {
+        self.bump();
+        self.bump();
+
+        let mut depth = 1;
+        while depth > 0 {
+            match (self.bump(), self.peek()) {
+                (Some('/'), Some('*')) => { self.bump(); depth += 1; }
+                (Some('*'), Some('/')) => { self.bump(); depth -= 1; }
+                (Some(_), _) => {}
+                (None, _) => {
+                    let open = Span{ hi: lo.lo + 2, end_col: lo.col + 2, ..lo };
+                    return Err(LexError::UnterminatedComment(open))
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<Spanned<Token>, LexError>;
+
+    fn next(&mut self) -> Option<Result<Spanned<Token>, LexError>> {
+        if self.done {
+            return None;
+        }
+
+        let res = self.next_token();
+        if let Some(Err(_)) = res {
+            self.done = true;
+        }
+        res
+    }
+}
+
+pub fn lex(program: &str) -> Result<Vec<Spanned<Token>>, Diagnostic> {
+    Lexer::new(program).map(|res| res.map_err(|err| err.to_diagnostic())).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use lexer::*;
+    use lexer::Token::*;
+    use diagnostic::Kind;
+
+    fn tokens(code: &str) -> Vec<String> {
+        lex(code).unwrap().iter().map(|tok| format!("{}", tok.node)).collect()
+    }
+
+    #[test]
+    fn positions_and_operators() {
+        let toks: Vec<_> = Lexer::new("x <- y\n  <= z->").map(|tok| tok.unwrap()).collect();
+        let found: Vec<_> = toks.iter().map(|tok| {
+            (format!("{}", tok.node), tok.span.line, tok.span.col)
+        }).collect();
+        assert_eq!(found, vec![
+            ("identifier `x`".to_string(), 1, 1),
+            ("`<-`".to_string(), 1, 3),
+            ("identifier `y`".to_string(), 1, 6),
+            ("`<=`".to_string(), 2, 3),
+            ("identifier `z`".to_string(), 2, 6),
+            ("`->`".to_string(), 2, 7),
+        ]);
+    }
+
+    #[test]
+    fn unicode_identifiers() {
+        assert_eq!(tokens("café _naïve2 π"),
+                   vec!["identifier `café`", "identifier `_naïve2`", "identifier `π`"]);
+        let toks = lex("café + 1").unwrap();
+        assert_eq!((toks[1].span.lo, toks[1].span.col), (6, 6));
+        assert_eq!((toks[0].span.end_line, toks[0].span.end_col), (1, 5));
+
+        // Tokens can end on a later line than they start on
+        let toks = lex("\"é\nab\" x").unwrap();
+        assert_eq!((toks[0].span.end_line, toks[0].span.end_col), (2, 4));
+        assert_eq!((toks[1].span.line, toks[1].span.col), (2, 5));
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(tokens("12 3.5 4.foo"),
+                   vec!["integer `12`", "float `3.5`", "integer `4`", "`.`", "identifier `foo`"]);
+        assert_eq!(tokens("0xff 0o17 0b1010 1_000_000"),
+                   vec!["integer `255`", "integer `15`", "integer `10`", "integer `1000000`"]);
+        assert_eq!(tokens("1e3 2.5E-1 3. 4.)"),
+                   vec!["float `1000`", "float `0.25`", "float `3`", "float `4`", "`)`"]);
+        assert_eq!(tokens("9223372036854775807"), vec!["integer `9223372036854775807`"]);
+    }
+
+    #[test]
+    fn number_errors() {
+        let err = lex("x + 9223372036854775808").unwrap_err();
+        assert_eq!(err.kind, Kind::NumberOverflow);
+        let span = err.primary_span().unwrap();
+        assert_eq!((span.col, span.hi - span.lo), (5, 19));
+
+        // Negation is a method call on the literal, so the most negative
+        // integer can't be written directly
+        assert_eq!(lex("-9223372036854775808").unwrap_err().kind, Kind::NumberOverflow);
+
+        assert_eq!(lex("1e400").unwrap_err().kind, Kind::NumberOverflow);
+        assert_eq!(lex("0x").unwrap_err().kind, Kind::InvalidNumber);
+        assert_eq!(lex("1e+").unwrap_err().kind, Kind::InvalidNumber);
+
+        let err = lex("0b1021").unwrap_err();
+        assert_eq!(err.kind, Kind::InvalidNumber);
+        assert_eq!(err.primary_span().unwrap().col, 6);
+    }
+
+    #[test]
+    fn stops_after_an_error() {
+        let mut lexer = Lexer::new("a $ b");
+        assert!(lexer.next().unwrap().is_ok());
+        match lexer.next() {
+            Some(Err(LexError::UnexpectedChar('$', span))) => assert_eq!(span.col, 3),
+            other => panic!("Expected an unexpected char error, found {:?}", other),
+        }
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn skips_comments() {
+        assert_eq!(tokens("1 // one\n/* two /* nested */ */ 2"),
+                   vec!["integer `1`", "integer `2`"]);
+        assert_eq!(tokens("//// not a doc comment\nx"), vec!["identifier `x`"]);
+    }
+
+    #[test]
+    fn keeps_doc_comments() {
+        let toks = lex("/// Adds one\nlet").unwrap();
+        match toks[0].node {
+            DOC_COMMENT(ref doc) => assert_eq!(doc.as_slice(), "Adds one"),
+            ref tok => panic!("Expected a doc comment, found {}", tok),
+        }
+        assert_eq!(toks[1].span.line, 2);
+    }
+
+    #[test]
+    fn string_literals() {
+        assert_eq!(tokens(r#""" "hello, world" "a\tb\n" "\"\\""#),
+                   vec![r#"string """#, r#"string "hello, world""#, r#"string "a\tb\n""#,
+                        r#"string "\"\\""#]);
+        assert_eq!(tokens("\"two\nlines\""), vec![r#"string "two\nlines""#]);
+        assert_eq!(tokens(r##"r"C:\path" r#"say "hi""#"##),
+                   vec![r#"string "C:\\path""#, r#"string "say \"hi\"""#]);
+    }
+
+    #[test]
+    fn unicode_escapes() {
+        match lex(r#""\u{1F986}!""#).unwrap()[0].node {
+            LIT_STR(ref s) => assert_eq!(s.as_slice(), "\u{1F986}!"),
+            ref tok => panic!("Expected a string, found {}", tok),
+        }
+    }
+
+    #[test]
+    fn string_errors() {
+        let err = lex("x = \"abc").unwrap_err();
+        assert_eq!(err.kind, Kind::UnterminatedString);
+        assert_eq!(err.primary_span().unwrap().col, 5);
+
+        let err = lex("\"a\\qb\"").unwrap_err();
+        assert_eq!(err.kind, Kind::InvalidEscape);
+        let span = err.primary_span().unwrap();
+        assert_eq!((span.col, span.hi - span.lo), (3, 2));
+
+        assert_eq!(lex(r#""\u{110000}""#).unwrap_err().kind, Kind::InvalidEscape);
+        assert_eq!(lex(r#""\u{}""#).unwrap_err().kind, Kind::InvalidEscape);
+        assert_eq!(lex(r##"r#"abc"##).unwrap_err().kind, Kind::UnterminatedString);
+    }
+
+    #[test]
+    fn unterminated_block_comment() {
+        let err = lex("1 /* /* */ 2").unwrap_err();
+        assert_eq!(err.kind, Kind::UnterminatedComment);
+        assert_eq!(err.primary_span().unwrap().col, 3);
+    }
 }
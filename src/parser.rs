@@ -1,6 +1,10 @@
+use std::mem;
 use lexer::{Token};
 use lexer::Token::*;
-use il::{Expr, Prop, Ident, Symbol, Literal, Stmt, Ty, TyProp};
+use span::{Span, Spanned};
+use diagnostic::{Diagnostic, Kind};
+use intern::Atom;
+use il::{Expr, ExprKind, Prop, Ident, Symbol, Literal, Stmt, StmtKind, Ty, TyProp, Doc, Annot, Sig};
 
 // TODO: Desugaring shouldn't happen inline!
 
@@ -8,7 +12,7 @@ macro_rules! expect {
     ($st:expr, $patt:pat) => {
         match $st.peek() {
             Some(& $patt) => { $st.eat(); },
-            unexpected => { return Err(format!("Unexpected {:?}! {:?}", unexpected, line!())); }
+            unexpected => { return Err(unexpected_token($st, unexpected)); }
         }
     };
     ($st:expr, $patt:pat => $expr:expr) => {
@@ -17,7 +21,21 @@ macro_rules! expect {
                 $st.eat();
                 $expr
             },
-            unexpected  => { return Err(format!("Unexpected {:?}! {:?}", unexpected, line!())); }
+            unexpected  => { return Err(unexpected_token($st, unexpected)); }
+        }
+    }
+}
+
+/// Produces the diagnostic for when the parser finds a token it can't handle
+fn unexpected_token<'a>(st: &State<'a>, tok: Option<&Token>) -> Diagnostic {
+    match tok {
+        Some(tok) => {
+            Diagnostic::error(Kind::UnexpectedToken, format!("Unexpected {}", tok))
+                .with_primary(st.span(), "unexpected token")
+        }
+        None => {
+            Diagnostic::error(Kind::UnexpectedToken, format!("Unexpected end of file"))
+                .with_primary(st.span(), "unexpected end of file")
         }
     }
 }
@@ -27,60 +45,269 @@ macro_rules! expect {
 ///
 /// TODO: This should probably be in a tokens module/the lexer module
 pub struct State<'a> {
-    tokens: &'a [Token],
-    // TODO: Spans etc.
+    tokens: &'a [Spanned<Token>],
+    /// The span of the most recently eaten token
+    last: Span,
+    /// The syntax errors which the parser has recovered from
+    errors: Vec<Diagnostic>,
 }
 
 impl<'a> State<'a> {
-    pub fn new(tokens: &'a [Token]) -> State<'a> {
-        State{tokens: tokens}
+    pub fn new(tokens: &'a [Spanned<Token>]) -> State<'a> {
+        State{tokens: tokens, last: Span{ lo: 0, hi: 0, line: 1, col: 1, end_line: 1, end_col: 1 }, errors: vec![]}
+    }
+
+    /// The remaining tokens, without any leading doc comments. Doc comments
+    /// are trivia, so they are invisible to the parser unless it explicitly
+    /// asks for them with `take_doc`.
+    fn rest(&self) -> &'a [Spanned<Token>] {
+        let n = self.tokens.iter().take_while(|tok| {
+            if let DOC_COMMENT(_) = tok.node { true } else { false }
+        }).count();
+        &self.tokens[n..]
     }
 
     fn peek(&self) -> Option<&'a Token> {
-        self.tokens.first()
+        self.rest().first().map(|tok| &tok.node)
+    }
+
+    /// Looks n tokens past the next one
+    fn peek_nth(&self, n: usize) -> Option<&'a Token> {
+        self.rest().get(n).map(|tok| &tok.node)
     }
 
     fn eat(&mut self) -> Option<&'a Token> {
-        let tok = self.tokens.first();
-        self.tokens = self.tokens.tail();
-        tok
+        let rest = self.rest();
+        let tok = rest.first();
+        if let Some(tok) = tok {
+            self.last = tok.span;
+        }
+        self.tokens = rest.tail();
+        tok.map(|tok| &tok.node)
+    }
+
+    /// Consumes the doc comments before the next token, joining their lines
+    fn take_doc(&mut self) -> Doc {
+        let mut lines = vec![];
+        while let Some(&Spanned{ node: DOC_COMMENT(ref line), .. }) = self.tokens.first() {
+            lines.push(line.as_slice());
+            self.tokens = self.tokens.tail();
+        }
+
+        if lines.is_empty() {
+            None
+        } else {
+            Some(Atom::from_slice(lines.connect("\n").as_slice()))
+        }
+    }
+
+    /// The span of the next token. At the end of the token stream,
+    /// this is an empty span just past the last token.
+    fn span(&self) -> Span {
+        match self.rest().first() {
+            Some(tok) => tok.span,
+            None => self.last.end(),
+        }
+    }
+
+    /// A span from lo to the end of the most recently eaten token
+    fn span_from(&self, lo: Span) -> Span {
+        lo.to(self.last)
     }
 }
 
-pub fn parse_stmt<'a>(st: &mut State<'a>) -> Result<Stmt, String> {
+pub fn parse_stmt<'a>(st: &mut State<'a>) -> Result<Stmt, Diagnostic> {
+    let doc = st.take_doc();
+    let lo = st.span();
     match st.peek() {
-        Some(&LET) => { // let IDENT = EXPR
+        Some(&LET) => { // let IDENT (: TY)? = EXPR
             st.eat();
             // Get the identifier
             expect!(st, IDENT(ref ident) => {
+                let annot = if let Some(&COLON) = st.peek() {
+                    st.eat();
+                    Some(try!(parse_annot(st, true)))
+                } else { None };
+
                 expect!(st, EQ => {
                     let expr = try!(parse_expr(st));
-                    Ok(Stmt::Let(Ident::from_atom(ident), expr))
+                    let mut stmt = Stmt::new(StmtKind::Let(Ident::from_atom(ident), annot, expr),
+                                             st.span_from(lo));
+                    stmt.doc = doc;
+                    Ok(stmt)
                 })
             })
         },
-        None | Some(&SEMI) => {
-            Ok(Stmt::Empty)
+        Some(&TYPE) => { // type IDENT ([IDENT, ...])? = TY
+            st.eat();
+            expect!(st, IDENT(ref ident) => {
+                let mut params = vec![];
+                if let Some(&LBRACKET) = st.peek() {
+                    st.eat();
+                    while let Some(&IDENT(ref param)) = st.peek() {
+                        st.eat();
+                        params.push(Ident::from_atom(param));
+                        match st.peek() {
+                            Some(&COMMA) => st.eat(),
+                            _ => break
+                        };
+                    }
+                    expect!(st, RBRACKET);
+                }
+
+                expect!(st, EQ => {
+                    let body = try!(parse_annot(st, true));
+                    let mut stmt = Stmt::new(StmtKind::Type(Ident::from_atom(ident), params, body),
+                                             st.span_from(lo));
+                    stmt.doc = doc;
+                    Ok(stmt)
+                })
+            })
+        }
+        Some(&IDENT(ref kw)) if *kw == "name" && is_ident(st.peek_nth(1)) => { // name IDENT
+            // `name` is only a keyword when it is followed by an identifier
+            st.eat();
+            expect!(st, IDENT(ref ident) => {
+                let mut stmt = Stmt::new(StmtKind::Name(Ident::from_atom(ident)), st.span_from(lo));
+                stmt.doc = doc;
+                Ok(stmt)
+            })
+        }
+        None | Some(&SEMI) | Some(&RBRACE) => {
+            Ok(Stmt::new(StmtKind::Empty, lo.start()))
         }
         _ => { // EXPR
             let expr = try!(parse_expr(st));
-            Ok(Stmt::Expr(expr))
+            let span = expr.span;
+            Ok(Stmt::new(StmtKind::Expr(expr), span))
         }
     }
 }
 
-pub fn parse_expr<'a>(st: &mut State<'a>) -> Result<Expr, String> {
-    // TODO: Add lower precidence operators! (like ==, >= etc)
-    parse_pm(st)
+fn is_ident(tok: Option<&Token>) -> bool {
+    if let Some(&IDENT(_)) = tok { true } else { false }
+}
+
+pub fn parse_expr<'a>(st: &mut State<'a>) -> Result<Expr, Diagnostic> {
+    parse_assign(st)
+}
+
+/// Assignment `obj.symb <- value`, which has the lowest precedence
+fn parse_assign<'a>(st: &mut State<'a>) -> Result<Expr, Diagnostic> {
+    let lhs = try!(parse_oror(st));
+    if let Some(&LARROW) = st.peek() {
+        let arrow_span = st.span();
+        st.eat();
+        let rhs = try!(parse_assign(st));
+        let span = lhs.span.to(rhs.span);
+
+        return match lhs.kind {
+            ExprKind::Member(obj, symb) => Ok(Expr::new(ExprKind::Assign(obj, symb, box rhs), span)),
+            _ => {
+                Err(Diagnostic::error(
+                    Kind::InvalidAssignment,
+                    format!("Only the fields of records can be assigned to")
+                ).with_primary(lhs.span, "expected a field, such as `r.x`")
+                 .with_secondary(arrow_span, "in this assignment"))
+            }
+        };
+    }
+    Ok(lhs)
 }
 
 /// Infix expressions are just method calls on the lhs argument
 fn mk_infix(op: &str, lhs: Expr, rhs: Expr) -> Expr {
-    Expr::Call(box lhs, Symbol::from_slice(op), vec![rhs])
+    let span = lhs.span.to(rhs.span);
+    Expr::new(ExprKind::Call(box lhs, Symbol::from_slice(op), vec![rhs]), span)
+}
+
+/// The logical operators short-circuit, so they can't be method calls.
+/// Instead, they are lowered to if expressions.
+fn mk_logical(lhs: Expr, rhs: Expr, is_and: bool) -> Expr {
+    let span = lhs.span.to(rhs.span);
+    let short = Expr::new(ExprKind::Literal(Literal::Bool(! is_and)), span);
+    let kind = if is_and {
+        ExprKind::If(box lhs, box rhs, box Some(short))
+    } else {
+        ExprKind::If(box lhs, box short, box Some(rhs))
+    };
+    Expr::new(kind, span)
+}
+
+/// Infix operator ||
+fn parse_oror<'a>(st: &mut State<'a>) -> Result<Expr, Diagnostic> {
+    let mut lhs = try!(parse_andand(st));
+    while let Some(&OROR) = st.peek() {
+        st.eat();
+        let rhs = try!(parse_andand(st));
+        lhs = mk_logical(lhs, rhs, false);
+    }
+    Ok(lhs)
+}
+
+/// Infix operator &&
+fn parse_andand<'a>(st: &mut State<'a>) -> Result<Expr, Diagnostic> {
+    let mut lhs = try!(parse_cmp(st));
+    while let Some(&ANDAND) = st.peek() {
+        st.eat();
+        let rhs = try!(parse_cmp(st));
+        lhs = mk_logical(lhs, rhs, true);
+    }
+    Ok(lhs)
+}
+
+/// Comparison operators. These don't associate, so `a < b < c` is an error.
+fn parse_cmp<'a>(st: &mut State<'a>) -> Result<Expr, Diagnostic> {
+    let lhs = try!(parse_bitor(st));
+    let op = match st.peek() {
+        Some(&EQEQ) => "==",
+        Some(&NE) => "!=",
+        Some(&LT) => "<",
+        Some(&LE) => "<=",
+        Some(&GT) => ">",
+        Some(&GE) => ">=",
+        _ => return Ok(lhs),
+    };
+    st.eat();
+    let rhs = try!(parse_bitor(st));
+    Ok(mk_infix(op, lhs, rhs))
+}
+
+/// Infix operator |
+fn parse_bitor<'a>(st: &mut State<'a>) -> Result<Expr, Diagnostic> {
+    let mut lhs = try!(parse_bitxor(st));
+    while let Some(&OR) = st.peek() {
+        st.eat();
+        let rhs = try!(parse_bitxor(st));
+        lhs = mk_infix("|", lhs, rhs);
+    }
+    Ok(lhs)
+}
+
+/// Infix operator ^
+fn parse_bitxor<'a>(st: &mut State<'a>) -> Result<Expr, Diagnostic> {
+    let mut lhs = try!(parse_bitand(st));
+    while let Some(&CARET) = st.peek() {
+        st.eat();
+        let rhs = try!(parse_bitand(st));
+        lhs = mk_infix("^", lhs, rhs);
+    }
+    Ok(lhs)
+}
+
+/// Infix operator &
+fn parse_bitand<'a>(st: &mut State<'a>) -> Result<Expr, Diagnostic> {
+    let mut lhs = try!(parse_pm(st));
+    while let Some(&AND) = st.peek() {
+        st.eat();
+        let rhs = try!(parse_pm(st));
+        lhs = mk_infix("&", lhs, rhs);
+    }
+    Ok(lhs)
 }
 
 /// Infix operators + and -
-fn parse_pm<'a>(st: &mut State<'a>) -> Result<Expr, String> {
+fn parse_pm<'a>(st: &mut State<'a>) -> Result<Expr, Diagnostic> {
     let mut lhs = try!(parse_tdm(st));
     loop {
         match st.peek() {
@@ -100,7 +327,7 @@ fn parse_pm<'a>(st: &mut State<'a>) -> Result<Expr, String> {
     Ok(lhs)
 }
 
-fn parse_tdm<'a>(st: &mut State<'a>) -> Result<Expr, String> {
+fn parse_tdm<'a>(st: &mut State<'a>) -> Result<Expr, Diagnostic> {
     let mut lhs = try!(parse_unary(st));
     loop {
         match st.peek() {
@@ -126,68 +353,85 @@ fn parse_tdm<'a>(st: &mut State<'a>) -> Result<Expr, String> {
 }
 
 /// Unary prefix operators
-fn parse_unary<'a>(st: &mut State<'a>) -> Result<Expr, String> {
-    fn mk_unary(op: &str, arg: Expr) -> Expr {
-        Expr::Call(box arg, Symbol::from_slice(op), vec![])
+fn parse_unary<'a>(st: &mut State<'a>) -> Result<Expr, Diagnostic> {
+    fn mk_unary(op: &str, arg: Expr, span: Span) -> Expr {
+        Expr::new(ExprKind::Call(box arg, Symbol::from_slice(op), vec![]), span)
     }
 
+    let lo = st.span();
     match st.peek() {
         Some(&NOT) => {
             st.eat();
             let rhs = try!(parse_deref(st));
-            Ok(mk_unary("not", rhs))
+            Ok(mk_unary("not", rhs, st.span_from(lo)))
         }
         Some(&MINUS) => {
             st.eat();
             let rhs = try!(parse_deref(st));
-            Ok(mk_unary("negate", rhs))
+            Ok(mk_unary("negate", rhs, st.span_from(lo)))
         }
         _ => parse_deref(st)
     }
 }
 
 /// Dereferences, Array accesses, and function calls!
-fn parse_deref<'a>(st: &mut State<'a>) -> Result<Expr, String> {
+fn parse_deref<'a>(st: &mut State<'a>) -> Result<Expr, Diagnostic> {
+    let lo = st.span();
     let mut lhs = try!(parse_value(st));
     loop {
         match st.peek() {
             Some(&DOT) => {
                 st.eat();
                 expect!(st, IDENT(ref ident) => {
-                    lhs = Expr::Member(box lhs, Symbol::from_atom(ident));
+                    lhs = Expr::new(ExprKind::Member(box lhs, Symbol::from_atom(ident)),
+                                    st.span_from(lo));
                 })
             }
             Some(&COLON) => {
+                // Within parentheses, `expr : Ty` is a type ascription
+                match (st.peek_nth(1), st.peek_nth(2)) {
+                    (Some(&IDENT(_)), Some(&LPAREN)) => {}
+                    _ => break
+                }
                 st.eat();
                 expect!(st, IDENT(ref ident) => {
                     expect!(st, LPAREN);
                     let args = try!(parse_args(st));
                     expect!(st, RPAREN);
-                    lhs = Expr::Call(box lhs, Symbol::from_atom(ident), args);
+                    lhs = Expr::new(ExprKind::Call(box lhs, Symbol::from_atom(ident), args),
+                                    st.span_from(lo));
                 })
             }
             Some(&LPAREN) => {
                 st.eat();
                 let args = try!(parse_args(st));
                 expect!(st, RPAREN);
-                lhs = Expr::Call(box lhs, Symbol::from_slice("call"), args);
+                lhs = Expr::new(ExprKind::Call(box lhs, Symbol::from_slice("call"), args),
+                                st.span_from(lo));
             }
             Some(&LBRACKET) => {
-                // TODO: Implement arrays n' shit
-                unimplemented!()
-            }
-            /// @TODO: Reassess, conflicts with `match x {}` and `if x {}`
-            // Some(&LBRACE) => {
-            //     // TODO: Implement object update
-            //     unimplemented!()
-            // }
+                // Indexing is a call to the `get` method, like infix operators
+                st.eat();
+                let index = try!(parse_expr(st));
+                expect!(st, RBRACKET);
+                lhs = Expr::new(ExprKind::Call(box lhs, Symbol::from_slice("get"), vec![index]),
+                                st.span_from(lo));
+            }
+            Some(&WITH) => {
+                // `expr { ... }` would conflict with `match x {}` and `if x {}`
+                st.eat();
+                expect!(st, LBRACE);
+                let props = try!(parse_props(st));
+                expect!(st, RBRACE);
+                lhs = Expr::new(ExprKind::Update(box lhs, props), st.span_from(lo));
+            }
             _ => break
         }
     }
     Ok(lhs)
 }
 
-fn parse_args<'a>(st: &mut State<'a>) -> Result<Vec<Expr>, String> {
+fn parse_args<'a>(st: &mut State<'a>) -> Result<Vec<Expr>, Diagnostic> {
     let mut args = vec![];
     loop {
         // Check if we should finish here
@@ -206,12 +450,19 @@ fn parse_args<'a>(st: &mut State<'a>) -> Result<Vec<Expr>, String> {
     Ok(args)
 }
 
-fn parse_params<'a>(st: &mut State<'a>) -> Result<Vec<Ident>, String> {
+/// Parses the parameters of a method, and the annotations on them
+fn parse_params<'a>(st: &mut State<'a>) -> Result<(Vec<Ident>, Vec<Option<Annot>>), Diagnostic> {
     let mut params = vec![];
+    let mut annots = vec![];
     loop {
         if let Some(&IDENT(ref ident)) = st.peek() {
             st.eat();
             params.push(Ident::from_atom(ident));
+            annots.push(if let Some(&COLON) = st.peek() {
+                st.eat();
+                Some(try!(parse_annot(st, true)))
+            } else { None });
+
             match st.peek() {
                 Some(&COMMA) => st.eat(),
                 _ => break
@@ -220,22 +471,36 @@ fn parse_params<'a>(st: &mut State<'a>) -> Result<Vec<Ident>, String> {
             break
         }
     }
-    Ok(params)
+    Ok((params, annots))
 }
 
-fn parse_value<'a>(st: &mut State<'a>) -> Result<Expr, String> {
+/// Parses the parameter list of a method, and its result annotation, if any
+fn parse_sig<'a>(st: &mut State<'a>) -> Result<(Vec<Ident>, Sig), Diagnostic> {
+    expect!(st, LPAREN);
+    let (params, annots) = try!(parse_params(st));
+    expect!(st, RPAREN);
+
+    let res = if let Some(&RARROW) = st.peek() {
+        st.eat();
+        // The method body follows the result type, so `Ty {` isn't an extended record
+        Some(try!(parse_annot(st, false)))
+    } else { None };
+
+    Ok((params, Sig{ params: annots, res: res }))
+}
+
+fn parse_value<'a>(st: &mut State<'a>) -> Result<Expr, Diagnostic> {
+    let lo = st.span();
     // TODO: Add if and match
-    match st.peek() {
+    let kind = match st.peek() {
         Some(&FN) => { // Function Literal
             st.eat();
-            expect!(st, LPAREN);
-            let params = try!(parse_params(st));
-            expect!(st, RPAREN);
+            let (params, sig) = try!(parse_sig(st));
             let body = try!(parse_block_expr(st));
 
-            Ok(Expr::Rec(vec![
-                Prop::Method(Symbol::from_slice("call"), params, body)
-                    ]))
+            ExprKind::Rec(vec![
+                Prop::Method(Symbol::from_slice("call"), params, sig, body, None)
+                    ])
         }
         Some(&IF) => {
             st.eat();
@@ -246,73 +511,254 @@ fn parse_value<'a>(st: &mut State<'a>) -> Result<Expr, String> {
                 Some(try!(parse_block_expr(st)))
             } else { None };
 
-            Ok(Expr::If(box cond, box then, box els))
+            ExprKind::If(box cond, box then, box els)
+        }
+        Some(&WHILE) => {
+            st.eat();
+            let cond = try!(parse_expr(st));
+            let body = try!(parse_block_expr(st));
+
+            ExprKind::While(box cond, box body)
+        }
+        Some(&FOR) => {
+            st.eat();
+            let var = expect!(st, IDENT(ref ident) => Ident::from_atom(ident));
+            expect!(st, IN);
+            let iter = try!(parse_expr(st));
+            let body = try!(parse_block_expr(st));
+
+            ExprKind::For(var, box iter, box body)
+        }
+        Some(&BREAK) => {
+            st.eat();
+            ExprKind::Break
+        }
+        Some(&CONTINUE) => {
+            st.eat();
+            ExprKind::Continue
+        }
+        Some(&RETURN) => {
+            st.eat();
+            // A bare `return` returns null
+            let value = match st.peek() {
+                None | Some(&SEMI) | Some(&RBRACE) => {
+                    Expr::new(ExprKind::Ident(Ident::from_slice("null")), st.last.end())
+                }
+                _ => try!(parse_expr(st)),
+            };
+            ExprKind::Return(box value)
+        }
+        Some(&MATCH) => {
+            st.eat();
+            let scrut = try!(parse_expr(st));
+            expect!(st, LBRACE);
+            let mut arms = vec![];
+            loop {
+                if let Some(&RBRACE) = st.peek() { break }
+                let pat = try!(parse_pat(st));
+                expect!(st, FAT_ARROW);
+                let body = try!(parse_expr(st));
+                arms.push(Arm{ pat: pat, body: body });
+
+                match st.peek() {
+                    Some(&COMMA) => st.eat(),
+                    _ => break
+                };
+            }
+            expect!(st, RBRACE);
+
+            ExprKind::Match(box scrut, arms)
         }
         Some(&LBRACE) => { // Object Literal
             st.eat();
             let props = try!(parse_props(st));
             expect!(st, RBRACE);
 
-            Ok(Expr::Rec(props))
+            ExprKind::Rec(props)
+        }
+        Some(&HASH) => { // Named Record Literal
+            let brand = try!(parse_brand(st));
+            let rec_lo = st.span();
+            expect!(st, LBRACE);
+            let props = try!(parse_props(st));
+            expect!(st, RBRACE);
+
+            ExprKind::Brand(brand, box Expr::new(ExprKind::Rec(props), st.span_from(rec_lo)))
+        }
+        Some(&LBRACKET) => { // List Literal
+            st.eat();
+            let mut elems = vec![];
+            loop {
+                if let Some(&RBRACKET) = st.peek() { break }
+                elems.push(try!(parse_expr(st)));
+
+                match st.peek() {
+                    Some(&COMMA) => st.eat(),
+                    _ => break
+                };
+            }
+            expect!(st, RBRACKET);
+
+            ExprKind::List(elems)
+        }
+        Some(&LPAREN) => { // Parenthesized Expression or Type Ascription
+            st.eat();
+            let expr = try!(parse_expr(st));
+            let kind = if let Some(&COLON) = st.peek() {
+                st.eat();
+                ExprKind::Ascribe(box expr, try!(parse_annot(st, true)))
+            } else { expr.kind };
+            expect!(st, RPAREN);
+
+            kind
         }
         // Trivial Cases
         Some(&IDENT(ref ident)) => {
             st.eat();
-            Ok(Expr::Ident(Ident::from_atom(ident)))
+            ExprKind::Ident(Ident::from_atom(ident))
         }
         Some(&LIT_INTEGER(i)) => {
             st.eat();
-            Ok(Expr::Literal(Literal::Int(i)))
+            ExprKind::Literal(Literal::Int(i))
         }
         Some(&LIT_FLOAT(f)) => {
             st.eat();
-            Ok(Expr::Literal(Literal::Float(f)))
+            ExprKind::Literal(Literal::Float(f))
         }
         Some(&LIT_STR(ref string)) => {
             st.eat();
-            Ok(Expr::Literal(Literal::Str(string.clone())))
+            ExprKind::Literal(Literal::Str(string.clone()))
         }
         Some(&TRUE) => {
             st.eat();
-            Ok(Expr::Literal(Literal::Bool(true)))
+            ExprKind::Literal(Literal::Bool(true))
         }
         Some(&FALSE) => {
             st.eat();
-            Ok(Expr::Literal(Literal::Bool(false)))
+            ExprKind::Literal(Literal::Bool(false))
         }
 
-        unexpected => Err(format!("Unexpected {:?}!", unexpected)),
-    }
+        unexpected => return Err(unexpected_token(st, unexpected)),
+    };
+
+    Ok(Expr::new(kind, st.span_from(lo)))
+}
+
+/// Methods can be named by the infix operators, which call them
+fn parse_method_name<'a>(st: &mut State<'a>) -> Result<Symbol, Diagnostic> {
+    let op = match st.peek() {
+        Some(&IDENT(ref ident)) => {
+            st.eat();
+            return Ok(Symbol::from_atom(ident));
+        }
+        Some(&PLUS) => "+", Some(&MINUS) => "-", Some(&STAR) => "*",
+        Some(&SLASH) => "/", Some(&PERCENT) => "%", Some(&CARET) => "^",
+        Some(&AND) => "&", Some(&OR) => "|",
+        Some(&EQEQ) => "==", Some(&NE) => "!=", Some(&LT) => "<",
+        Some(&LE) => "<=", Some(&GT) => ">", Some(&GE) => ">=",
+        unexpected => return Err(unexpected_token(st, unexpected)),
+    };
+    st.eat();
+    Ok(Symbol::from_slice(op))
+}
+
+/// Parses a pattern in a match arm
+fn parse_pat<'a>(st: &mut State<'a>) -> Result<Pat, Diagnostic> {
+    let lo = st.span();
+    let kind = match st.peek() {
+        Some(&IDENT(ref ident)) => {
+            st.eat();
+            if let Some(&COLON) = st.peek() {
+                st.eat();
+                PatKind::Typed(Ident::from_atom(ident), try!(parse_annot(st, true)))
+            } else if *ident == "_" {
+                PatKind::Wild
+            } else {
+                PatKind::Bind(Ident::from_atom(ident))
+            }
+        }
+        Some(&HASH) | Some(&LBRACE) => {
+            let brand = if let Some(&HASH) = st.peek() {
+                Some(try!(parse_brand(st)))
+            } else { None };
+
+            expect!(st, LBRACE);
+            let mut fields = vec![];
+            while let Some(&IDENT(ref ident)) = st.peek() {
+                let field_lo = st.span();
+                st.eat();
+                // `{ a }` is short for `{ a: a }`
+                let pat = if let Some(&COLON) = st.peek() {
+                    st.eat();
+                    try!(parse_pat(st))
+                } else {
+                    Pat::new(PatKind::Bind(Ident::from_atom(ident)), st.span_from(field_lo))
+                };
+                fields.push((Symbol::from_atom(ident), pat));
+
+                match st.peek() {
+                    Some(&COMMA) => st.eat(),
+                    _ => break
+                };
+            }
+            expect!(st, RBRACE);
+
+            PatKind::Rec(brand, fields)
+        }
+        unexpected => return Err(unexpected_token(st, unexpected)),
+    };
+
+    Ok(Pat::new(kind, st.span_from(lo)))
 }
 
-fn parse_block_expr<'a>(st: &mut State<'a>) -> Result<Expr, String> {
+/// Parses the `#Name#` which brands a named record
+fn parse_brand<'a>(st: &mut State<'a>) -> Result<Ident, Diagnostic> {
+    expect!(st, HASH);
+    expect!(st, IDENT(ref ident) => {
+        expect!(st, HASH);
+        Ok(Ident::from_atom(ident))
+    })
+}
+
+fn parse_block_expr<'a>(st: &mut State<'a>) -> Result<Expr, Diagnostic> {
+    let lo = st.span();
     expect!(st, LBRACE);
-    let stmts = try!(parse_stmts(st));
+    let stmts = parse_stmts(st);
     expect!(st, RBRACE);
-    Ok(Expr::Block(stmts))
+    Ok(Expr::new(ExprKind::Block(stmts), st.span_from(lo)))
+}
+
+/// Parses the `mut` which may come before the name of a field
+fn parse_mutability<'a>(st: &mut State<'a>) -> Mutability {
+    if let Some(&MUT) = st.peek() {
+        st.eat();
+        Mutability::Mutable
+    } else {
+        Mutability::Immutable
+    }
 }
 
-fn parse_props<'a>(st: &mut State<'a>) -> Result<Vec<Prop>, String> {
+fn parse_props<'a>(st: &mut State<'a>) -> Result<Vec<Prop>, Diagnostic> {
     let mut props = vec![];
     loop {
+        let doc = st.take_doc();
         match st.peek() {
             Some(&FN) => {
                 st.eat();
-                expect!(st, IDENT(ref ident) => {
-                    expect!(st, LPAREN);
-                    let params = try!(parse_params(st));
-                    expect!(st, RPAREN);
-                    let body = try!(parse_block_expr(st));
+                let symb = try!(parse_method_name(st));
+                let (params, sig) = try!(parse_sig(st));
+                let body = try!(parse_block_expr(st));
 
-                    props.push(Prop::Method(Symbol::from_atom(ident), params, body));
-                })
+                props.push(Prop::Method(symb, params, sig, body, doc));
             },
-            Some(&IDENT(ref ident)) => {
-                st.eat();
-                expect!(st, COLON);
-                let value = try!(parse_expr(st));
+            Some(&MUT) | Some(&IDENT(_)) => {
+                let mutability = parse_mutability(st);
+                expect!(st, IDENT(ref ident) => {
+                    expect!(st, COLON);
+                    let value = try!(parse_expr(st));
 
-                props.push(Prop::Val(Symbol::from_atom(ident), value));
+                    props.push(Prop::Val(Symbol::from_atom(ident), value, mutability, doc));
+                })
             }
             _ => break
         };
@@ -325,36 +771,114 @@ fn parse_props<'a>(st: &mut State<'a>) -> Result<Vec<Prop>, String> {
     Ok(props)
 }
 
-fn parse_stmts<'a>(st: &mut State<'a>) -> Result<Vec<Stmt>, String> {
+/// Parses a sequence of statements. Syntax errors are recorded in the State,
+/// and the statements which caused them are replaced with placeholders.
+fn parse_stmts<'a>(st: &mut State<'a>) -> Vec<Stmt> {
     let mut stmts = vec![];
     loop {
-        stmts.push(try!(parse_stmt(st)));
+        let lo = st.span();
+        match parse_stmt(st) {
+            Ok(stmt) => stmts.push(stmt),
+            Err(diag) => {
+                st.errors.push(diag);
+                synchronize(st);
+
+                let span = if st.last.hi > lo.lo { lo.to(st.last) } else { lo.start() };
+                stmts.push(Stmt::new(StmtKind::Expr(Expr::new(ExprKind::Error, span)), span));
+
+                // A let is the start of a new statement, even without a `;`
+                match st.peek() {
+                    Some(&LET) | Some(&TYPE) => continue,
+                    _ => {}
+                }
+            }
+        }
+
         match st.peek() {
-            Some(&SEMI) => st.eat(),
-            _ => break
-        };
+            Some(&SEMI) => { st.eat(); }
+            None | Some(&RBRACE) => break,
+            Some(tok) => {
+                let diag = unexpected_token(st, Some(tok))
+                    .with_note(format!("statements must be separated by `;`"));
+                st.errors.push(diag);
+                synchronize(st);
+                match st.peek() {
+                    Some(&SEMI) => { st.eat(); }
+                    Some(&LET) | Some(&TYPE) => {}
+                    _ => break,
+                }
+            }
+        }
+    }
+    stmts
+}
+
+/// After a syntax error, skips tokens until one which parsing can resume
+/// from: a `;`, `}`, `let` or `type` which isn't nested within brackets.
+fn synchronize<'a>(st: &mut State<'a>) {
+    let mut depth = 0u32;
+    loop {
+        match st.peek() {
+            None => return,
+            Some(&SEMI) | Some(&RBRACE) | Some(&LET) | Some(&TYPE) if depth == 0 => return,
+            Some(&LBRACE) | Some(&LPAREN) | Some(&LBRACKET) => depth += 1,
+            Some(&RBRACE) | Some(&RPAREN) | Some(&RBRACKET) if depth > 0 => depth -= 1,
+            _ => {}
+        }
+        st.eat();
     }
-    Ok(stmts)
 }
 
-#[allow(dead_code)]
-fn parse_ty<'a>(st: &mut State<'a>) -> Result<Ty, String> {
+/// Parses a type annotation, recording the span which it was read from.
+/// When `ext` is false, `Ident {` isn't parsed as an extended record.
+fn parse_annot<'a>(st: &mut State<'a>, ext: bool) -> Result<Annot, Diagnostic> {
+    let lo = st.span();
+    let ty = try!(parse_union_ty(st, ext));
+    Ok(Spanned::new(ty, st.span_from(lo)))
+}
+
+fn parse_ty<'a>(st: &mut State<'a>) -> Result<Ty, Diagnostic> {
+    parse_union_ty(st, true)
+}
+
+fn parse_union_ty<'a>(st: &mut State<'a>, ext: bool) -> Result<Ty, Diagnostic> {
+    let first = try!(parse_atom_ty(st, ext));
+    if let Some(&OR) = st.peek() {
+        let mut opts = vec![first];
+        while let Some(&OR) = st.peek() {
+            st.eat();
+            opts.push(try!(parse_atom_ty(st, ext)));
+        }
+        Ok(Ty::Union(opts))
+    } else {
+        Ok(first)
+    }
+}
+
+fn parse_atom_ty<'a>(st: &mut State<'a>, ext: bool) -> Result<Ty, Diagnostic> {
     match st.peek() {
-        Some(&FN) => {
+        Some(&LPAREN) => {
             st.eat();
-            // Function Type
-            expect!(st, LBRACKET);
-            let param_tys = try!(parse_paramtys(st));
-            expect!(st, RBRACKET);
-            expect!(st, RARROW);
-            let result_type = try!(parse_ty(st));
+            let mut param_tys = try!(parse_paramtys(st));
+            expect!(st, RPAREN);
+
+            match st.peek() {
+                Some(&RARROW) => {
+                    // Function Type
+                    st.eat();
+                    let result_type = try!(parse_atom_ty(st, ext));
 
-            Ok(Ty::Rec(None,
-                       vec![
-                           TyProp::Method(
-                               Symbol::from_slice("call"),
-                               param_tys,
-                               result_type)]))
+                    Ok(Ty::Rec(None, None,
+                               vec![
+                                   TyProp::Method(
+                                       Symbol::from_slice("call"),
+                                       param_tys,
+                                       result_type)]))
+                }
+                // Parenthesized Type
+                _ if param_tys.len() == 1 => Ok(param_tys.pop().unwrap()),
+                unexpected => Err(unexpected_token(st, unexpected)),
+            }
         }
         Some(&LBRACE) => {
             st.eat();
@@ -362,23 +886,44 @@ fn parse_ty<'a>(st: &mut State<'a>) -> Result<Ty, String> {
             let props = try!(parse_proptys(st));
             expect!(st, RBRACE);
 
-            Ok(Ty::Rec(None, props))
+            Ok(Ty::Rec(None, None, props))
+        }
+        Some(&HASH) => {
+            // Named Record, which may list some of its properties
+            let brand = try!(parse_brand(st));
+            let props = match st.peek() {
+                Some(&LBRACE) if ext => {
+                    st.eat();
+                    let props = try!(parse_proptys(st));
+                    expect!(st, RBRACE);
+                    props
+                }
+                _ => vec![],
+            };
+
+            Ok(Ty::Rec(None, Some(brand), props))
         }
         Some(&IDENT(ref id)) => {
             st.eat();
-            let ident_ty = Ty::Ident(Ident::from_atom(id));
-            // Identifier type or extended record
+            // Identifier type, or a type alias with arguments
+            let ident_ty = if let Some(&LBRACKET) = st.peek() {
+                st.eat();
+                let args = try!(parse_paramtys(st));
+                expect!(st, RBRACKET);
+                Ty::Alias(Ident::from_atom(id), args)
+            } else {
+                Ty::Ident(Ident::from_atom(id))
+            };
+
+            // Possibly an extended record
             match st.peek() {
-                Some(&COLON) => {
+                Some(&LBRACE) if ext => {
                     // Extended Record!
-                    // Parse the base record, and then extend it
                     st.eat();
-                    let record = try!(parse_ty(st));
-                    if let Ty::Rec(None, props) = record {
-                        Ok(Ty::Rec(Some(box ident_ty), props))
-                    } else {
-                        Err(format!("Unexpected non-record type!"))
-                    }
+                    let props = try!(parse_proptys(st));
+                    expect!(st, RBRACE);
+
+                    Ok(Ty::Rec(Some(box ident_ty), None, props))
                 }
                 _ => {
                     // Its just an identifier
@@ -386,17 +931,19 @@ fn parse_ty<'a>(st: &mut State<'a>) -> Result<Ty, String> {
                 }
             }
         }
-        unexpected => Err(format!("Unexpected {:?}!", unexpected)),
+        unexpected => Err(unexpected_token(st, unexpected)),
     }
 }
 
-#[allow(dead_code)]
-fn parse_paramtys<'a>(st: &mut State<'a>) -> Result<Vec<Ty>, String> {
+fn parse_paramtys<'a>(st: &mut State<'a>) -> Result<Vec<Ty>, Diagnostic> {
     let mut paramtys = vec![];
 
     loop {
         // Check if we should finish here
-        if let Some(&RPAREN) = st.peek() { break }
+        match st.peek() {
+            Some(&RPAREN) | Some(&RBRACKET) => break,
+            _ => {}
+        }
 
         let paramty = try!(parse_ty(st));
         paramtys.push(paramty);
@@ -412,29 +959,30 @@ fn parse_paramtys<'a>(st: &mut State<'a>) -> Result<Vec<Ty>, String> {
     Ok(paramtys)
 }
 
-#[allow(dead_code)]
-fn parse_proptys<'a>(st: &mut State<'a>) -> Result<Vec<TyProp>, String> {
+fn parse_proptys<'a>(st: &mut State<'a>) -> Result<Vec<TyProp>, Diagnostic> {
     let mut props = vec![];
 
     loop {
         match st.peek() {
             Some(&FN) => {
                 st.eat();
-                expect!(st, IDENT(ref ident) => {
-                    expect!(st, LPAREN);
-                    let params = try!(parse_paramtys(st));
-                    expect!(st, RPAREN);
-                    let body = try!(parse_ty(st));
+                let symb = try!(parse_method_name(st));
+                expect!(st, LPAREN);
+                let params = try!(parse_paramtys(st));
+                expect!(st, RPAREN);
+                expect!(st, RARROW);
+                let body = try!(parse_ty(st));
 
-                    props.push(TyProp::Method(Symbol::from_atom(ident), params, body));
-                })
+                props.push(TyProp::Method(symb, params, body));
             },
-            Some(&IDENT(ref ident)) => {
-                st.eat();
-                expect!(st, COLON);
-                let value = try!(parse_ty(st));
+            Some(&MUT) | Some(&IDENT(_)) => {
+                let mutability = parse_mutability(st);
+                expect!(st, IDENT(ref ident) => {
+                    expect!(st, COLON);
+                    let value = try!(parse_ty(st));
 
-                props.push(TyProp::Val(Symbol::from_atom(ident), value));
+                    props.push(TyProp::Val(Symbol::from_atom(ident), value, mutability));
+                })
             }
             _ => break
         };
@@ -448,7 +996,379 @@ fn parse_proptys<'a>(st: &mut State<'a>) -> Result<Vec<TyProp>, String> {
     Ok(props)
 }
 
-pub fn parse_program<'a>(st: &mut State<'a>) -> Result<Vec<Stmt>, String> {
+/// Parses a program, failing with the first syntax error
+pub fn parse_program<'a>(st: &mut State<'a>) -> Result<Vec<Stmt>, Diagnostic> {
+    let (stmts, mut errors) = parse_program_recovering(st);
+    if errors.is_empty() {
+        Ok(stmts)
+    } else {
+        Err(errors.remove(0))
+    }
+}
+
+/// Parses a program, recovering from syntax errors such that all of them can
+/// be reported at once. The code which couldn't be parsed is replaced with
+/// `ExprKind::Error` placeholders, so later stages can run on the rest.
+pub fn parse_program_recovering<'a>(st: &mut State<'a>) -> (Vec<Stmt>, Vec<Diagnostic>) {
     // Right now programs are just lists of statements
-    parse_stmts(st)
+    let mut stmts = parse_stmts(st);
+    while let Some(tok) = st.peek() {
+        // parse_stmts stops at a token which can't continue the program,
+        // such as an unmatched `}`
+        let diag = unexpected_token(st, Some(tok));
+        st.errors.push(diag);
+        st.eat();
+        stmts.extend(parse_stmts(st).into_iter());
+    }
+
+    (stmts, mem::replace(&mut st.errors, vec![]))
+}
+
+#[cfg(test)]
+mod test {
+    use il::*;
+    use lexer;
+    use parser::*;
+    use diagnostic::{Diagnostic, Kind};
+
+    fn parse(code: &str) -> Vec<Stmt> {
+        let toks = lexer::lex(code).unwrap();
+        parse_program(&mut State::new(toks.as_slice())).unwrap()
+    }
+
+    #[test]
+    fn attaches_doc_comments() {
+        let stmts = parse("
+            /// The answer
+            /// to everything
+            let x = {
+                /// A value
+                a: 1,
+                b: 2 /// Not attached to anything
+            };
+            x
+        ");
+
+        assert_eq!(stmts[0].doc.as_ref().map(|doc| doc.as_slice()),
+                   Some("The answer\nto everything"));
+        assert!(stmts[1].doc.is_none());
+
+        match stmts[0].kind {
+            StmtKind::Let(_, _, Expr{ kind: ExprKind::Rec(ref props), .. }) => {
+                match props[0] {
+                    Prop::Val(_, _, _, Some(ref doc)) => assert_eq!(doc.as_slice(), "A value"),
+                    ref prop => panic!("Expected a documented prop, found {:?}", prop),
+                }
+                match props[1] {
+                    Prop::Val(_, _, _, None) => {}
+                    ref prop => panic!("Expected an undocumented prop, found {:?}", prop),
+                }
+            }
+            ref kind => panic!("Expected a let, found {:?}", kind),
+        }
+    }
+
+    fn parse_recovering(code: &str) -> (Vec<Stmt>, Vec<Diagnostic>) {
+        let toks = lexer::lex(code).unwrap();
+        parse_program_recovering(&mut State::new(toks.as_slice()))
+    }
+
+    fn is_error(stmt: &Stmt) -> bool {
+        if let StmtKind::Expr(Expr{ kind: ExprKind::Error, .. }) = stmt.kind { true } else { false }
+    }
+
+    #[test]
+    fn reports_every_syntax_error() {
+        let (stmts, errors) = parse_recovering("let x = 1 +; let y = 2\nlet z = ) 3; z");
+        let positions: Vec<_> = errors.iter().map(|err| {
+            let span = err.primary_span().unwrap();
+            (span.line, span.col)
+        }).collect();
+        assert_eq!(positions, vec![(1, 12), (2, 1), (2, 9)]);
+
+        // The statements around the errors are still parsed
+        assert_eq!(stmts.len(), 4);
+        assert!(is_error(&stmts[0]));
+        assert!(! is_error(&stmts[1]));
+        assert!(is_error(&stmts[2]));
+        assert!(! is_error(&stmts[3]));
+    }
+
+    #[test]
+    fn recovers_within_blocks() {
+        let (stmts, errors) = parse_recovering("let f = fn(x) { x + ; x }; f(1)");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(stmts.len(), 2);
+        assert!(! is_error(&stmts[0]));
+    }
+
+    #[test]
+    fn unmatched_close_brace() {
+        let (stmts, errors) = parse_recovering("1; }; 2");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, Kind::UnexpectedToken);
+        assert_eq!(stmts.len(), 4);
+    }
+
+    #[test]
+    fn parses_type_annotations() {
+        let stmts = parse("let f: (Int, a) -> a{ x: Int } | { fn m(Str) -> Int } = g");
+        let ident = |name| Ty::Ident(Ident::from_slice(name));
+        let expected = Ty::Union(vec![
+            Ty::Rec(None, None, vec![TyProp::Method(
+                Symbol::from_slice("call"),
+                vec![ident("Int"), ident("a")],
+                Ty::Rec(Some(box ident("a")), None, vec![TyProp::Val(Symbol::from_slice("x"), ident("Int"), Mutability::Immutable)]))]),
+            Ty::Rec(None, None, vec![TyProp::Method(Symbol::from_slice("m"), vec![ident("Str")], ident("Int"))]),
+        ]);
+
+        match stmts[0].kind {
+            StmtKind::Let(_, Some(ref annot), _) => {
+                assert_eq!(annot.node, expected);
+                assert_eq!((annot.span.line, annot.span.col), (1, 8));
+            }
+            ref kind => panic!("Expected an annotated let, found {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn ascription_and_method_calls() {
+        let stmts = parse("(x : Int); (x:m(1)); fn(a: Int, b) -> Int { a }");
+        match stmts[0].kind {
+            StmtKind::Expr(Expr{ kind: ExprKind::Ascribe(..), .. }) => {}
+            ref kind => panic!("Expected an ascription, found {:?}", kind),
+        }
+        match stmts[1].kind {
+            StmtKind::Expr(Expr{ kind: ExprKind::Call(..), .. }) => {}
+            ref kind => panic!("Expected a method call, found {:?}", kind),
+        }
+        match stmts[2].kind {
+            StmtKind::Expr(Expr{ kind: ExprKind::Rec(ref props), .. }) => {
+                if let Prop::Method(_, _, ref sig, _, _) = props[0] {
+                    assert!(sig.params[0].is_some() && sig.params[1].is_none());
+                    assert!(sig.res.is_some());
+                } else { panic!("Expected a method") }
+            }
+            ref kind => panic!("Expected a function, found {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn parses_type_aliases() {
+        let stmts = parse("type Cons[A, B] = List[A]{ car: A }; type Nat = { pred: Nat }");
+        match stmts[0].kind {
+            StmtKind::Type(ref id, ref params, ref body) => {
+                assert_eq!(*id, Ident::from_slice("Cons"));
+                assert_eq!(*params, vec![Ident::from_slice("A"), Ident::from_slice("B")]);
+                let list = Ty::Alias(Ident::from_slice("List"), vec![Ty::Ident(Ident::from_slice("A"))]);
+                assert_eq!(body.node, Ty::Rec(Some(box list), None, vec![
+                    TyProp::Val(Symbol::from_slice("car"), Ty::Ident(Ident::from_slice("A")),
+                                Mutability::Immutable)]));
+            }
+            ref kind => panic!("Expected a type alias, found {:?}", kind),
+        }
+        match stmts[1].kind {
+            StmtKind::Type(_, ref params, _) => assert!(params.is_empty()),
+            ref kind => panic!("Expected a type alias, found {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn parses_named_records() {
+        let stmts = parse("name Duck; let d: #Duck#{ quack: Str } = #Duck#{ quack: \"hi\" }; name + 1");
+        match stmts[0].kind {
+            StmtKind::Name(ref id) => assert_eq!(*id, Ident::from_slice("Duck")),
+            ref kind => panic!("Expected a name declaration, found {:?}", kind),
+        }
+        match stmts[1].kind {
+            StmtKind::Let(_, Some(ref annot), Expr{ kind: ExprKind::Brand(ref brand, _), .. }) => {
+                assert_eq!(*brand, Ident::from_slice("Duck"));
+                assert_eq!(annot.node, Ty::Rec(None, Some(Ident::from_slice("Duck")), vec![
+                    TyProp::Val(Symbol::from_slice("quack"), Ty::Ident(Ident::from_slice("Str")),
+                                Mutability::Immutable)]));
+            }
+            ref kind => panic!("Expected a named record, found {:?}", kind),
+        }
+
+        // `name` is still an ordinary identifier elsewhere
+        match stmts[2].kind {
+            StmtKind::Expr(_) => {}
+            ref kind => panic!("Expected an expression, found {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn parses_match() {
+        let stmts = parse("match p { {x, y: _} => x, n: Int | Str => 1, #P#{} => 2, z => 3 }");
+        let arms = match stmts[0].kind {
+            StmtKind::Expr(Expr{ kind: ExprKind::Match(_, ref arms), .. }) => arms,
+            ref kind => panic!("Expected a match, found {:?}", kind),
+        };
+        assert_eq!(arms.len(), 4);
+        match arms[0].pat.kind {
+            PatKind::Rec(None, ref fields) => {
+                assert_eq!(fields[0].0, Symbol::from_slice("x"));
+                match fields[1].1.kind {
+                    PatKind::Wild => {}
+                    ref kind => panic!("Expected a wildcard, found {:?}", kind),
+                }
+            }
+            ref kind => panic!("Expected a record pattern, found {:?}", kind),
+        }
+        match arms[1].pat.kind {
+            PatKind::Typed(_, ref annot) => assert!(annot.node.is_union()),
+            ref kind => panic!("Expected a typed pattern, found {:?}", kind),
+        }
+        match arms[2].pat.kind {
+            PatKind::Rec(Some(_), ref fields) => assert!(fields.is_empty()),
+            ref kind => panic!("Expected a named record pattern, found {:?}", kind),
+        }
+        assert_eq!(arms[3].pat.bindings(), vec![Ident::from_slice("z")]);
+    }
+
+    #[test]
+    fn parses_mutable_fields_and_assignment() {
+        let stmts = parse("let r: { mut n: Int } = { mut n: 0, m: 1 }; r.n <- r.n + 1");
+        match stmts[0].kind {
+            StmtKind::Let(_, Some(ref annot), Expr{ kind: ExprKind::Rec(ref props), .. }) => {
+                assert_eq!(annot.node, Ty::Rec(None, None, vec![
+                    TyProp::Val(Symbol::from_slice("n"), Ty::Ident(Ident::from_slice("Int")),
+                                Mutability::Mutable)]));
+                match (&props[0], &props[1]) {
+                    (&Prop::Val(_, _, Mutability::Mutable, _), &Prop::Val(_, _, Mutability::Immutable, _)) => {}
+                    props => panic!("Expected a mutable and an immutable field, found {:?}", props),
+                }
+            }
+            ref kind => panic!("Expected a let, found {:?}", kind),
+        }
+        match stmts[1].kind {
+            StmtKind::Expr(Expr{ kind: ExprKind::Assign(_, ref symb, box ref value), .. }) => {
+                assert_eq!(*symb, Symbol::from_slice("n"));
+                match value.kind {
+                    ExprKind::Call(..) => {}
+                    ref kind => panic!("Expected the whole sum to be assigned, found {:?}", kind),
+                }
+            }
+            ref kind => panic!("Expected an assignment, found {:?}", kind),
+        }
+
+        let toks = lexer::lex("x <- 1").unwrap();
+        let err = parse_program(&mut State::new(toks.as_slice())).unwrap_err();
+        assert_eq!(err.kind, Kind::InvalidAssignment);
+    }
+
+    #[test]
+    fn points_past_the_last_token() {
+        // The end of the last token is counted in characters, across lines
+        for &(code, pos) in [("f(\"é\"", (1, 6)), ("f(\"a\nbc\"", (2, 4))].iter() {
+            let toks = lexer::lex(code).unwrap();
+            let err = parse_program(&mut State::new(toks.as_slice())).unwrap_err();
+            let span = err.primary_span().unwrap();
+            assert_eq!((span.line, span.col), pos);
+        }
+    }
+
+    #[test]
+    fn parses_loops() {
+        let stmts = parse("while x < 10 { if y { break } else { continue } }; for a in xs { f(a) }");
+        match stmts[0].kind {
+            StmtKind::Expr(Expr{ kind: ExprKind::While(box ref cond, box ref body), .. }) => {
+                match cond.kind {
+                    ExprKind::Call(_, ref symb, _) => assert_eq!(*symb, Symbol::from_slice("<")),
+                    ref kind => panic!("Expected a comparison, found {:?}", kind),
+                }
+                match body.kind {
+                    ExprKind::Block(ref stmts) => assert_eq!(stmts.len(), 1),
+                    ref kind => panic!("Expected a block, found {:?}", kind),
+                }
+            }
+            ref kind => panic!("Expected a while loop, found {:?}", kind),
+        }
+        match stmts[1].kind {
+            StmtKind::Expr(Expr{ kind: ExprKind::For(ref var, box ref iter, _), .. }) => {
+                assert_eq!(*var, Ident::from_slice("a"));
+                match iter.kind {
+                    ExprKind::Ident(ref id) => assert_eq!(*id, Ident::from_slice("xs")),
+                    ref kind => panic!("Expected the iterator to be xs, found {:?}", kind),
+                }
+            }
+            ref kind => panic!("Expected a for loop, found {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn parses_return() {
+        let stmts = parse("fn(x) { if x { return 1 + 2 }; 3 }");
+        let body = match stmts[0].kind {
+            StmtKind::Expr(Expr{ kind: ExprKind::Rec(ref props), .. }) => match props[0] {
+                Prop::Method(_, _, _, Expr{ kind: ExprKind::Block(ref body), .. }, _) => body,
+                ref prop => panic!("Expected a method, found {:?}", prop),
+            },
+            ref kind => panic!("Expected a function, found {:?}", kind),
+        };
+        match body[0].kind {
+            StmtKind::Expr(Expr{ kind: ExprKind::If(_, box Expr{ kind: ExprKind::Block(ref thn), .. }, _), .. }) => {
+                match thn[0].kind {
+                    StmtKind::Expr(Expr{ kind: ExprKind::Return(box ref value), .. }) => {
+                        match value.kind {
+                            ExprKind::Call(..) => {}
+                            ref kind => panic!("Expected the whole sum to be returned, found {:?}", kind),
+                        }
+                    }
+                    ref kind => panic!("Expected a return, found {:?}", kind),
+                }
+            }
+            ref kind => panic!("Expected an if, found {:?}", kind),
+        }
+
+        // Without a value, it returns null
+        for code in ["fn(x) { if x { return }; 3 }", "fn() { return; 3 }"].iter() {
+            let stmts = parse(code);
+            let mut returned = vec![];
+            if let StmtKind::Expr(ref func) = stmts[0].kind {
+                func.walk(&mut |e: &Expr| {
+                    if let ExprKind::Return(box Expr{ kind: ExprKind::Ident(ref id), .. }) = e.kind {
+                        returned.push(id.clone());
+                    }
+                });
+            }
+            assert_eq!(returned, vec![Ident::from_slice("null")]);
+        }
+    }
+
+    #[test]
+    fn parses_record_update() {
+        let stmts = parse("p with { x: 1, fn m() { 2 } }.x");
+        match stmts[0].kind {
+            StmtKind::Expr(Expr{ kind: ExprKind::Member(box ref update, _), .. }) => {
+                match update.kind {
+                    ExprKind::Update(box Expr{ kind: ExprKind::Ident(_), .. }, ref props) => {
+                        assert_eq!(props.len(), 2);
+                    }
+                    ref kind => panic!("Expected a record update, found {:?}", kind),
+                }
+            }
+            ref kind => panic!("Expected a property access, found {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn parses_lists_and_indexing() {
+        let stmts = parse("[1, [], x,]; xs[i + 1][0]");
+        match stmts[0].kind {
+            StmtKind::Expr(Expr{ kind: ExprKind::List(ref elems), .. }) => assert_eq!(elems.len(), 3),
+            ref kind => panic!("Expected a list, found {:?}", kind),
+        }
+        match stmts[1].kind {
+            StmtKind::Expr(Expr{ kind: ExprKind::Call(box ref inner, ref symb, ref args), .. }) => {
+                assert_eq!(*symb, Symbol::from_slice("get"));
+                assert_eq!(args.len(), 1);
+                match inner.kind {
+                    ExprKind::Call(_, ref symb, _) => assert_eq!(*symb, Symbol::from_slice("get")),
+                    ref kind => panic!("Expected an index, found {:?}", kind),
+                }
+            }
+            ref kind => panic!("Expected an index, found {:?}", kind),
+        }
+    }
 }
@@ -0,0 +1,173 @@
+use std::io::{self, BufRead, Write};
+use lexer;
+use parser;
+use scope;
+use infer;
+use simplify;
+use interp;
+use diagnostic::{Diagnostic, Kind};
+use emitter::Emitter;
+
+/// The state which persists between inputs to the REPL. The scoping and
+/// inference environments, and the interpreter's bindings, are kept alive,
+/// so that bindings made in one input can be used by later inputs.
+pub struct Repl {
+    scope: scope::Scope,
+    env: infer::env::Scope,
+    interp: interp::Interpreter,
+}
+
+impl Repl {
+    pub fn new() -> Repl {
+        Repl{
+            scope: scope::Scope::new(),
+            env: infer::env::Scope::new(),
+            interp: interp::Interpreter::new(),
+        }
+    }
+
+    /// Processes one complete input, returning the lines to show to the user
+    pub fn eval(&mut self, input: &str) -> Result<Vec<String>, Diagnostic> {
+        let tokens = try!(lexer::lex(input));
+        let ast = try!(parser::parse_program(&mut parser::State::new(tokens.as_slice())));
+
+        // Work on copies of the environments, so that an input which fails
+        // doesn't leave half of its bindings behind.
+        let mut scope = self.scope.clone();
+        let scoped = try!(scope::scoped_toplevel(&mut scope, ast.as_slice()));
+        let mut env = self.env.clone();
+        let tys = try!(infer::infer_toplevel(&mut env, scoped.as_slice()));
+
+        // Evaluation can fail after some of the input's bindings are made,
+        // so the interpreter is only replaced once all of it has run. The
+        // rollback only covers bindings: records and lists are shared with
+        // the copy, so assignments and pushes made before the failure stay.
+        let mut interp = self.interp.clone();
+        let values = try!(interp.eval_toplevel(scoped.as_slice()));
+
+        let mut out: Vec<String> = env.take_warnings().iter().map(|warning| {
+            format!("{}", warning)
+        }).collect();
+        out.extend(tys.iter().zip(values.iter()).map(|(&(ref id, ref ty), value)| {
+            let ty = simplify::simplify_ty(ty, env.type_vars());
+            match *id {
+                Some(ref id) => format!("{} : {} = {:?}", id.0, ty, value),
+                None => format!("- : {} = {:?}", ty, value),
+            }
+        }));
+
+        self.scope = scope;
+        self.env = env;
+        self.interp = interp;
+        Ok(out)
+    }
+}
+
+/// Whether the diagnostic was caused by the input ending early, in which
+/// case we should wait for more lines rather than reporting it. Strings and
+/// block comments which are still open can only end on a later line.
+fn is_incomplete(diag: &Diagnostic, input: &str) -> bool {
+    match diag.kind {
+        Kind::UnterminatedString | Kind::UnterminatedComment => true,
+        Kind::UnexpectedToken => {
+            diag.primary_span().map(|span| span.lo >= input.trim_right().len()).unwrap_or(false)
+        }
+        _ => false,
+    }
+}
+
+/// Runs the REPL on stdin until it is closed, returning the exit code
+pub fn run(color: bool) -> i32 {
+    let mut repl = Repl::new();
+    let stdin = io::stdin();
+    let mut stdin = stdin.lock();
+    let mut input = String::new();
+
+    loop {
+        print!("{}", if input.is_empty() { "> " } else { "... " });
+        let _ = io::stdout().flush();
+
+        let mut line = String::new();
+        match stdin.read_line(&mut line) {
+            Ok(0) => {
+                println!("");
+                return 0;
+            }
+            Ok(_) => {}
+            Err(err) => {
+                println!("error: {}", err);
+                return 1;
+            }
+        }
+
+        input.push_str(line.as_slice());
+        if input.trim().is_empty() {
+            input.clear();
+            continue;
+        }
+
+        match repl.eval(input.as_slice()) {
+            Ok(lines) => {
+                for line in lines.iter() {
+                    println!("{}", line);
+                }
+            }
+            Err(ref diag) if is_incomplete(diag, input.as_slice()) => continue,
+            Err(diag) => Emitter::new("<repl>", input.as_slice(), color).emit(&diag),
+        }
+        input.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Repl, is_incomplete};
+
+    /// Whether the REPL would wait for more lines after the input
+    fn incomplete(input: &str) -> bool {
+        match Repl::new().eval(input) {
+            Ok(_) => false,
+            Err(diag) => is_incomplete(&diag, input),
+        }
+    }
+
+    #[test]
+    fn bindings_persist() {
+        let mut repl = Repl::new();
+        assert_eq!(repl.eval("let x = 1;").unwrap().len(), 1);
+        let out = repl.eval("let f = fn(y) { x + y };").unwrap();
+        assert!(out[0].starts_with("f : ("), "{:?}", out);
+        let out = repl.eval("f(2)").unwrap();
+        assert!(out[0].starts_with("- : Int") && out[0].ends_with("= 3"), "{:?}", out);
+    }
+
+    #[test]
+    fn failed_inputs_are_rolled_back() {
+        let mut repl = Repl::new();
+        repl.eval("let x = 1;").unwrap();
+
+        // A type error, and a runtime error after y is bound
+        assert!(repl.eval("let x = \"s\"; x:len() + x;").is_err());
+        assert!(repl.eval("let y = 2; [][0];").is_err());
+        assert!(repl.eval("y").is_err());
+        assert!(repl.eval("x + 1").unwrap()[0].ends_with("= 2"));
+
+        // The values of the bindings are shared, so their changes aren't undone
+        repl.eval("let r = { mut n: 0 };").unwrap();
+        assert!(repl.eval("r.n <- 1; [][0];").is_err());
+        assert!(repl.eval("r.n").unwrap()[0].ends_with("= 1"));
+    }
+
+    #[test]
+    fn waits_for_open_input() {
+        assert!(incomplete("let s = \"abc"));
+        assert!(incomplete("/* a comment"));
+        assert!(incomplete("let f = fn(x) {"));
+        assert!(incomplete("let xs = [1, 2,"));
+        assert!(incomplete("let x = 1 +"));
+
+        assert!(! incomplete("let x = 1;"));
+        assert!(! incomplete("let x = 1 + ;"));
+        assert!(! incomplete("let x = \"s\" + 1;"));
+    }
+}
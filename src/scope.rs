@@ -3,6 +3,8 @@ use std::iter::{Counter, count};
 use std::cell::RefCell;
 use std::rc::Rc;
 use il::*;
+use span::{Span, Spanned};
+use diagnostic::{Diagnostic, Kind};
 
 macro_rules! builtin {
     ($subs:expr , [$($ident:expr),+]) => {
@@ -18,6 +20,19 @@ macro_rules! builtin {
 pub struct Scope {
     counter: Rc<RefCell<Counter<u32>>>,
     subs: HashMap<Ident, (Ident, i32)>,
+    /// The type names which are in scope, including type variables
+    types: HashMap<Ident, Ident>,
+    /// The number of parameters which each type alias takes
+    aliases: HashMap<Ident, usize>,
+    /// The names which records can be branded with. Like variables, a name is
+    /// only visible within the block which declares it, so that block is the
+    /// only code which can create records with it.
+    brands: HashMap<Ident, Ident>,
+    /// Whether `break` and `continue` have a loop to leave. Function bodies
+    /// can't leave the loops around them.
+    in_loop: bool,
+    /// Whether `return` has a method to leave
+    in_method: bool,
 }
 
 impl Scope {
@@ -31,9 +46,27 @@ impl Scope {
             "Int"
             ]);
 
+        let types = ["Int", "Bool", "Str", "Float", "Null", "List"].iter().map(|name| {
+            (Ident::from_slice(name), Ident::from_builtin_slice(name))
+        }).collect();
+
+        // `List[A]` is the only builtin type which takes parameters
+        let mut aliases = HashMap::new();
+        aliases.insert(Ident::from_builtin_slice("List"), 1);
+
+        // The builtin brands can be named in types, but never constructed
+        let brands = ["Int", "Bool", "Str", "Float", "Null", "List"].iter().map(|name| {
+            (Ident::from_slice(name), Ident::from_builtin_slice(name))
+        }).collect();
+
         Scope{
             counter: Rc::new(RefCell::new(count(0, 1))),
             subs: subs,
+            types: types,
+            aliases: aliases,
+            brands: brands,
+            in_loop: false,
+            in_method: false,
         }
     }
 
@@ -42,103 +75,409 @@ impl Scope {
     }
 }
 
-pub fn scoped_expr(scope: &mut Scope, expr: &Expr) -> Result<Expr, String> {
-    match *expr {
-        Expr::Literal(_) => Ok(expr.clone()),
-        Expr::Ident(ref id) => {
-            if let Some(&mut (ref id, ref mut use_count)) = scope.subs.get_mut(id) {
-                *use_count += 1;
-                Ok(Expr::Ident(id.clone()))
-            } else {
-                Err(format!("Use of undeclared variable: {:?}", id))
+fn scoped_brand(scope: &Scope, brand: &Ident, span: Span) -> Result<Ident, Diagnostic> {
+    match scope.brands.get(brand) {
+        Some(nbrand) => Ok(nbrand.clone()),
+        None => Err(undeclared_type(brand, span)),
+    }
+}
+
+fn undeclared_type(id: &Ident, span: Span) -> Diagnostic {
+    Diagnostic::error(
+        Kind::UndeclaredType,
+        format!("Use of undeclared type: {}", id.0)
+    ).with_primary(span, "not found in this scope")
+}
+
+/// Resolves the names in a type. When `bind_vars` is set, lowercase names
+/// which aren't in scope are type variables, and are bound in `scope`, such
+/// that other annotations in the same scope can refer to them.
+fn scoped_ty(scope: &mut Scope, ty: &Ty, span: Span, bind_vars: bool) -> Result<Ty, Diagnostic> {
+    match *ty {
+        Ty::Ident(ref id) => {
+            if let Some(nid) = scope.types.get(id).cloned() {
+                // Aliases without parameters can be written without brackets
+                return match scope.aliases.get(&nid) {
+                    Some(&0) => Ok(Ty::Alias(nid, vec![])),
+                    Some(&params) => Err(type_argument_mismatch(id, params, 0, span)),
+                    None => Ok(Ty::Ident(nid)),
+                };
+            }
+
+            let Ident(ref atom, _) = *id;
+            if ! bind_vars || atom.as_slice().chars().next().map_or(false, |c| c.is_uppercase()) {
+                return Err(undeclared_type(id, span));
+            }
+
+            let nid = id.scoped_with_depth(scope.next());
+            scope.types.insert(id.clone(), nid.clone());
+            Ok(Ty::Ident(nid))
+        }
+        Ty::Alias(ref id, ref args) => {
+            let nid = match scope.types.get(id) {
+                Some(nid) => nid.clone(),
+                None => return Err(undeclared_type(id, span)),
+            };
+
+            let params = scope.aliases.get(&nid).cloned().unwrap_or(0);
+            if params != args.len() {
+                return Err(type_argument_mismatch(id, params, args.len(), span));
             }
+
+            let nargs = try!(args.iter().map(|arg| scoped_ty(scope, arg, span, bind_vars)).collect());
+            Ok(Ty::Alias(nid, nargs))
         }
-        Expr::Rec(ref props) => {
-            Ok(Expr::Rec(try!(props.iter().map(|prop| {
+        Ty::Rec(ref extends, ref brand, ref props) => {
+            let nbrand = match *brand {
+                Some(ref brand) => Some(try!(scoped_brand(scope, brand, span))),
+                None => None,
+            };
+            let nextends = match *extends {
+                Some(box ref extends) => Some(box try!(scoped_ty(scope, extends, span, bind_vars))),
+                None => None,
+            };
+
+            let nprops = try!(props.iter().map(|prop| {
                 match *prop {
-                    Prop::Val(ref symb, ref expr) => {
-                        Ok(Prop::Val(symb.clone(), try!(scoped_expr(scope, expr))))
+                    TyProp::Val(ref symb, ref ty, mutability) => {
+                        Ok(TyProp::Val(symb.clone(), try!(scoped_ty(scope, ty, span, bind_vars)), mutability))
                     }
-                    Prop::Method(ref symb, ref args, ref body) => {
-                        let mut nscope = scope.clone();
-
-                        // Bind all of the variables in args!
-                        for arg in args.iter() {
-                            let sub = arg.scoped_with_depth(nscope.next());
-                            nscope.subs.insert(arg.clone(), (sub, 0));
-                        }
-                        let nargs = args.iter().map(|arg| {
-                            let sub = arg.scoped_with_depth(nscope.next());
-                            nscope.subs.insert(arg.clone(), (sub.clone(), 0));
-                            sub
-                        }).collect();
-
-                        Ok(Prop::Method(symb.clone(),
-                                        nargs,
-                                        try!(scoped_expr(&mut nscope, body))))
+                    TyProp::Method(ref symb, ref params, ref res) => {
+                        let nparams = try!(params.iter().map(|param| {
+                            scoped_ty(scope, param, span, bind_vars)
+                        }).collect());
+                        let nres = try!(scoped_ty(scope, res, span, bind_vars));
+                        Ok(TyProp::Method(symb.clone(), nparams, nres))
                     }
                 }
+            }).collect());
+
+            Ok(Ty::Rec(nextends, nbrand, nprops))
+        }
+        Ty::Union(ref opts) => {
+            Ok(Ty::Union(try!(opts.iter().map(|opt| {
+                scoped_ty(scope, opt, span, bind_vars)
             }).collect())))
         }
+    }
+}
+
+fn type_argument_mismatch(id: &Ident, expected: usize, found: usize, span: Span) -> Diagnostic {
+    Diagnostic::error(
+        Kind::TypeArgumentMismatch,
+        format!("`{}` takes {} type arguments, but {} were given", id.0, expected, found)
+    ).with_primary(span, "in this type")
+}
+
+/// Rejects type aliases which refer to themselves other than through the
+/// properties of a record, as they could never be expanded into a type.
+fn check_alias_recursion(stmts: &[Stmt]) -> Result<(), Diagnostic> {
+    let bodies: HashMap<Ident, Ty> = stmts.iter().filter_map(|stmt| {
+        if let StmtKind::Type(ref id, _, ref body) = stmt.kind {
+            Some((id.clone(), body.node.clone()))
+        } else { None }
+    }).collect();
+
+    // Whether expanding ty could produce target without entering a record
+    fn reaches(bodies: &HashMap<Ident, Ty>, target: &Ident, ty: &Ty, seen: &mut Vec<Ident>) -> bool {
+        match *ty {
+            Ty::Alias(ref id, _) => {
+                if id == target { return true }
+                if seen.contains(id) { return false }
+                seen.push(id.clone());
+                bodies.get(id).map_or(false, |body| reaches(bodies, target, body, seen))
+            }
+            Ty::Rec(Some(box ref extends), _, _) => reaches(bodies, target, extends, seen),
+            Ty::Union(ref opts) => opts.iter().any(|opt| reaches(bodies, target, opt, seen)),
+            _ => false,
+        }
+    }
+
+    for stmt in stmts.iter() {
+        if let StmtKind::Type(ref id, _, ref body) = stmt.kind {
+            if reaches(&bodies, id, &body.node, &mut vec![]) {
+                return Err(Diagnostic::error(
+                    Kind::RecursiveTypeAlias,
+                    format!("Type alias `{}` expands to itself", id.0)
+                ).with_primary(body.span, "recursive without a record in between")
+                 .with_note(format!("aliases may only refer to themselves within record \
+                                     properties, such as `type List = {{ next: List }}`")));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn scoped_annot(scope: &mut Scope, annot: &Annot) -> Result<Annot, Diagnostic> {
+    Ok(Spanned::new(try!(scoped_ty(scope, &annot.node, annot.span, true)), annot.span))
+}
+
+fn scoped_maybe_annot(scope: &mut Scope, annot: &Option<Annot>) -> Result<Option<Annot>, Diagnostic> {
+    match *annot {
+        Some(ref annot) => Ok(Some(try!(scoped_annot(scope, annot)))),
+        None => Ok(None),
+    }
+}
+
+fn scoped_props(scope: &mut Scope, props: &[Prop]) -> Result<Vec<Prop>, Diagnostic> {
+    props.iter().map(|prop| {
+        match *prop {
+            Prop::Val(ref symb, ref expr, mutability, ref doc) => {
+                Ok(Prop::Val(symb.clone(), try!(scoped_expr(scope, expr)), mutability, doc.clone()))
+            }
+            Prop::Method(ref symb, ref args, ref sig, ref body, ref doc) => {
+                let mut nscope = scope.clone();
+                nscope.in_loop = false;
+                nscope.in_method = true;
+
+                // The type variables in the signature are visible in the body
+                let nsig = Sig{
+                    params: try!(sig.params.iter().map(|annot| {
+                        scoped_maybe_annot(&mut nscope, annot)
+                    }).collect()),
+                    res: try!(scoped_maybe_annot(&mut nscope, &sig.res)),
+                };
+
+                // Bind all of the variables in args!
+                for arg in args.iter() {
+                    let sub = arg.scoped_with_depth(nscope.next());
+                    nscope.subs.insert(arg.clone(), (sub, 0));
+                }
+                let nargs = args.iter().map(|arg| {
+                    let sub = arg.scoped_with_depth(nscope.next());
+                    nscope.subs.insert(arg.clone(), (sub.clone(), 0));
+                    sub
+                }).collect();
+
+                Ok(Prop::Method(symb.clone(),
+                                nargs,
+                                nsig,
+                                try!(scoped_expr(&mut nscope, body)),
+                                doc.clone()))
+            }
+        }
+    }).collect()
+}
+
+pub fn scoped_expr(scope: &mut Scope, expr: &Expr) -> Result<Expr, Diagnostic> {
+    let kind = match expr.kind {
+        ExprKind::Literal(_) => expr.kind.clone(),
+        ExprKind::Ident(ref id) => {
+            if let Some(&mut (ref id, ref mut use_count)) = scope.subs.get_mut(id) {
+                *use_count += 1;
+                ExprKind::Ident(id.clone())
+            } else {
+                return Err(Diagnostic::error(
+                    Kind::UndeclaredVariable,
+                    format!("Use of undeclared variable: {}", id.0)
+                ).with_primary(expr.span, "not found in this scope"))
+            }
+        }
+        ExprKind::Rec(ref props) => ExprKind::Rec(try!(scoped_props(scope, props.as_slice()))),
+        ExprKind::Update(box ref base, ref props) => {
+            ExprKind::Update(box try!(scoped_expr(scope, base)),
+                             try!(scoped_props(scope, props.as_slice())))
+        }
+
+        ExprKind::Brand(ref brand, box ref rec) => {
+            let nbrand = try!(scoped_brand(scope, brand, expr.span));
+            if let Ident(_, BuiltIn) = nbrand {
+                return Err(Diagnostic::error(
+                    Kind::PrivateBrand,
+                    format!("Records named `{}` can't be created by user code", brand.0)
+                ).with_primary(expr.span, "can only be created by the code which declares the name"))
+            }
+            ExprKind::Brand(nbrand, box try!(scoped_expr(scope, rec)))
+        }
 
-        Expr::Member(box ref expr, ref symb) => {
-            Ok(Expr::Member(box try!(scoped_expr(scope, expr)), symb.clone()))
+        ExprKind::List(ref elems) => {
+            ExprKind::List(try!(elems.iter().map(|x| scoped_expr(scope, x)).collect()))
         }
-        Expr::Call(box ref callee, ref symb, ref args) => {
-            Ok(Expr::Call(
+
+        ExprKind::Member(box ref expr, ref symb) => {
+            ExprKind::Member(box try!(scoped_expr(scope, expr)), symb.clone())
+        }
+        ExprKind::Assign(box ref obj, ref symb, box ref value) => {
+            ExprKind::Assign(box try!(scoped_expr(scope, obj)),
+                             symb.clone(),
+                             box try!(scoped_expr(scope, value)))
+        }
+        ExprKind::Call(box ref callee, ref symb, ref args) => {
+            ExprKind::Call(
                 box try!(scoped_expr(scope, callee)),
                 symb.clone(),
-                try!(args.iter().map(|x| scoped_expr(scope, x)).collect())))
+                try!(args.iter().map(|x| scoped_expr(scope, x)).collect()))
         }
 
-        Expr::Block(ref stmts) => {
-            Ok(Expr::Block(try!(scoped_block(scope, stmts.as_slice()))))
+        ExprKind::Block(ref stmts) => {
+            ExprKind::Block(try!(scoped_block(scope, stmts.as_slice())))
         }
-        Expr::If(box ref cond, box ref cons, box ref alt) => {
-            Ok(Expr::If(
+        ExprKind::If(box ref cond, box ref cons, box ref alt) => {
+            ExprKind::If(
                 box try!(scoped_expr(scope, cond)),
                 box try!(scoped_expr(scope, cons)),
                 box match *alt {
                     Some(ref x) => Some(try!(scoped_expr(scope, x))),
                     None => None
-                }))
+                })
+        }
+        ExprKind::Match(box ref scrut, ref arms) => {
+            let nscrut = try!(scoped_expr(scope, scrut));
+            ExprKind::Match(box nscrut, try!(arms.iter().map(|arm| {
+                // The variables bound by the pattern are only visible in its arm
+                let mut nscope = scope.clone();
+                let npat = try!(scoped_pat(&mut nscope, &arm.pat));
+                Ok(Arm{ pat: npat, body: try!(scoped_expr(&mut nscope, &arm.body)) })
+            }).collect()))
+        }
+        ExprKind::While(box ref cond, box ref body) => {
+            let ncond = try!(scoped_expr(scope, cond));
+            let mut nscope = scope.clone();
+            nscope.in_loop = true;
+            ExprKind::While(box ncond, box try!(scoped_expr(&mut nscope, body)))
+        }
+        ExprKind::For(ref var, box ref iter, box ref body) => {
+            let niter = try!(scoped_expr(scope, iter));
+
+            // The variable is only visible in the body
+            let mut nscope = scope.clone();
+            nscope.in_loop = true;
+            let nvar = var.scoped_with_depth(nscope.next());
+            nscope.subs.insert(var.clone(), (nvar.clone(), 0));
+            ExprKind::For(nvar, box niter, box try!(scoped_expr(&mut nscope, body)))
+        }
+        ExprKind::Break | ExprKind::Continue => {
+            if ! scope.in_loop {
+                let keyword = if let ExprKind::Break = expr.kind { "break" } else { "continue" };
+                return Err(Diagnostic::error(
+                    Kind::BreakOutsideLoop,
+                    format!("`{}` outside of a loop", keyword)
+                ).with_primary(expr.span, "not within a `while` or `for` loop")
+                 .with_note(format!("functions can't leave the loops which they are created in")));
+            }
+            expr.kind.clone()
         }
+        ExprKind::Return(box ref value) => {
+            if ! scope.in_method {
+                return Err(Diagnostic::error(
+                    Kind::ReturnOutsideFunction,
+                    format!("`return` outside of a function")
+                ).with_primary(expr.span, "not within a function or method"))
+            }
+            ExprKind::Return(box try!(scoped_expr(scope, value)))
+        }
+        ExprKind::Ascribe(box ref expr, ref annot) => {
+            ExprKind::Ascribe(box try!(scoped_expr(scope, expr)),
+                              try!(scoped_annot(&mut scope.clone(), annot)))
+        }
+        ExprKind::Error => ExprKind::Error,
+
+    };
 
+    Ok(Expr::new(kind, expr.span))
+}
+
+fn scoped_pat(scope: &mut Scope, pat: &Pat) -> Result<Pat, Diagnostic> {
+    fn bind(scope: &mut Scope, id: &Ident) -> Ident {
+        let sub = id.scoped_with_depth(scope.next());
+        scope.subs.insert(id.clone(), (sub.clone(), 0));
+        sub
     }
+
+    let kind = match pat.kind {
+        PatKind::Wild => PatKind::Wild,
+        PatKind::Bind(ref id) => PatKind::Bind(bind(scope, id)),
+        PatKind::Typed(ref id, ref annot) => {
+            let nannot = try!(scoped_annot(scope, annot));
+            PatKind::Typed(bind(scope, id), nannot)
+        }
+        PatKind::Rec(ref brand, ref fields) => {
+            // Matching on a name doesn't create a record, so any name can be used
+            let nbrand = match *brand {
+                Some(ref brand) => Some(try!(scoped_brand(scope, brand, pat.span))),
+                None => None,
+            };
+            PatKind::Rec(nbrand, try!(fields.iter().map(|&(ref symb, ref pat)| {
+                Ok((symb.clone(), try!(scoped_pat(scope, pat))))
+            }).collect()))
+        }
+    };
+
+    Ok(Pat::new(kind, pat.span))
 }
 
-pub fn scoped_block(scope: &mut Scope, stmts: &[Stmt]) -> Result<Vec<Stmt>, String> {
+pub fn scoped_block(scope: &mut Scope, stmts: &[Stmt]) -> Result<Vec<Stmt>, Diagnostic> {
     // Create a new scope
     let mut nscope = scope.clone();
+    scoped_toplevel(&mut nscope, stmts)
+}
 
-    // Add the variables bound in this context
+/// Scopes the statements without creating a new scope, so the variables which
+/// they bind are added to `scope`, and are visible to later calls. This is used
+/// by the REPL, where each input can refer to variables bound by previous ones.
+pub fn scoped_toplevel(scope: &mut Scope, stmts: &[Stmt]) -> Result<Vec<Stmt>, Diagnostic> {
+    // Add the variables and type aliases bound in this context
     for stmt in stmts.iter() {
-        if let Stmt::Let(ref id, _) = *stmt {
-            let sub = id.scoped_with_depth(nscope.next());
-            nscope.subs.insert(id.clone(), (sub, 0));
+        match stmt.kind {
+            StmtKind::Let(ref id, _, _) => {
+                let sub = id.scoped_with_depth(scope.next());
+                scope.subs.insert(id.clone(), (sub, 0));
+            }
+            StmtKind::Type(ref id, ref params, _) => {
+                let sub = id.scoped_with_depth(scope.next());
+                scope.aliases.insert(sub.clone(), params.len());
+                scope.types.insert(id.clone(), sub);
+            }
+            StmtKind::Name(ref id) => {
+                let sub = id.scoped_with_depth(scope.next());
+                scope.brands.insert(id.clone(), sub);
+            }
+            _ => {}
         }
     }
 
     // Recur on statements
-    stmts.iter().map(|stmt| {
-        match *stmt {
-            Stmt::Let(ref id, ref expr) => {
+    let nstmts: Vec<Stmt> = try!(stmts.iter().map(|stmt| {
+        let kind = match stmt.kind {
+            StmtKind::Let(ref id, ref annot, ref expr) => {
                 // This is safe because we just created it!
                 let nid = {
-                    let &(ref nid, _) = nscope.subs.get(id).unwrap();
+                    let &(ref nid, _) = scope.subs.get(id).unwrap();
                     nid.clone()
                 };
 
-                let nexpr = try!(scoped_expr(&mut nscope, expr));
-                Ok(Stmt::Let(nid, nexpr))
+                // Type variables in the annotation are local to it
+                let nannot = try!(scoped_maybe_annot(&mut scope.clone(), annot));
+                let nexpr = try!(scoped_expr(scope, expr));
+                StmtKind::Let(nid, nannot, nexpr)
             }
-            Stmt::Expr(ref expr) => {
-                let nexpr = try!(scoped_expr(&mut nscope, expr));
-                Ok(Stmt::Expr(nexpr))
+            StmtKind::Expr(ref expr) => {
+                let nexpr = try!(scoped_expr(scope, expr));
+                StmtKind::Expr(nexpr)
             }
-            Stmt::Empty => Ok(Stmt::Empty)
-        }
-    }).collect()
+            StmtKind::Type(ref id, ref params, ref body) => {
+                let nid = scope.types.get(id).unwrap().clone();
+
+                // The parameters are only visible within the body
+                let mut tscope = scope.clone();
+                let nparams = params.iter().map(|param| {
+                    let sub = param.scoped_with_depth(tscope.next());
+                    tscope.types.insert(param.clone(), sub.clone());
+                    sub
+                }).collect();
+
+                let nbody = try!(scoped_ty(&mut tscope, &body.node, body.span, false));
+                StmtKind::Type(nid, nparams, Spanned::new(nbody, body.span))
+            }
+            StmtKind::Name(ref id) => StmtKind::Name(scope.brands.get(id).unwrap().clone()),
+            StmtKind::Empty => StmtKind::Empty
+        };
+        Ok(Stmt{ kind: kind, span: stmt.span, doc: stmt.doc.clone() })
+    }).collect());
+
+    try!(check_alias_recursion(nstmts.as_slice()));
+    Ok(nstmts)
 }
 
 
@@ -146,10 +485,11 @@ pub fn scoped_block(scope: &mut Scope, stmts: &[Stmt]) -> Result<Vec<Stmt>, Stri
 mod test {
     use il::*;
     use scope::*;
+    use diagnostic::{Diagnostic, Kind};
     use lexer;
     use parser;
 
-    fn scope(code: &str) -> Result<Vec<Stmt>, String> {
+    fn scope(code: &str) -> Result<Vec<Stmt>, Diagnostic> {
         let toks = lexer::lex(code).unwrap();
         let ast = parser::parse_program(&mut parser::State::new(toks.as_slice())).unwrap();
         scoped_block(&mut Scope::new(), ast.as_slice())
@@ -251,4 +591,108 @@ mod test {
             let z = x;
         });
     }
+
+    #[test]
+    fn undeclared_var_reports_position() {
+        let err = scope("let x = 10;\nx + y").unwrap_err();
+        assert_eq!(err.kind, Kind::UndeclaredVariable);
+
+        let span = err.primary_span().unwrap();
+        assert_eq!((span.line, span.col), (2, 5));
+    }
+
+    #[test]
+    fn toplevel_bindings_persist() {
+        let mut scope = Scope::new();
+        for code in ["let x = 10", "let y = x + 1", "x + y"].iter() {
+            let toks = lexer::lex(code).unwrap();
+            let ast = parser::parse_program(&mut parser::State::new(toks.as_slice())).unwrap();
+            scoped_toplevel(&mut scope, ast.as_slice()).unwrap();
+        }
+    }
+
+    #[test]
+    fn resolves_annotation_types() {
+        scope_ok(stringify!{
+            let f = fn(x: a) -> a {
+                let y: a = x;
+                y
+            };
+            let g: { name: Str } = { name: "duck" };
+        });
+
+        let err = scope("let x: Foo = 1").unwrap_err();
+        assert_eq!(err.kind, Kind::UndeclaredType);
+        let span = err.primary_span().unwrap();
+        assert_eq!((span.line, span.col), (1, 8));
+    }
+
+    #[test]
+    fn resolves_type_aliases() {
+        scope_ok("let xs: List[Int] = [1, 2]; xs[0]");
+        scope_ok(stringify!{
+            let x: Cons[Int] = 1;
+            type Cons[A] = List[A]{ car: A };
+            type List[A] = { cdr: List[A] | Null };
+        });
+
+        let errors = [
+            ("type T = T", Kind::RecursiveTypeAlias),
+            ("type A = B | Int; type B = A{ y: Int }", Kind::RecursiveTypeAlias),
+            ("type F[A] = { x: A }; let x: F = 1", Kind::TypeArgumentMismatch),
+            ("let x: Int[Int] = 1", Kind::TypeArgumentMismatch),
+            ("let x: List = [1]", Kind::TypeArgumentMismatch),
+            ("type T = { x: a }", Kind::UndeclaredType),
+        ];
+        for &(code, kind) in errors.iter() {
+            assert_eq!(scope(code).unwrap_err().kind, kind);
+        }
+    }
+
+    #[test]
+    fn named_records_are_private() {
+        scope_ok(stringify!{
+            let i: #Int# = 1;
+            let mk = fn(x) {
+                name P;
+                let p: #P#{ x: Int } = #P#{ x: x };
+                p
+            };
+        });
+
+        let errors = [
+            ("#Int#{ to_str: \"1\" }", Kind::PrivateBrand),
+            ("let f = fn() { name P; 1 }; #P#{}", Kind::UndeclaredType),
+            ("let p: #Q# = 1", Kind::UndeclaredType),
+        ];
+        for &(code, kind) in errors.iter() {
+            assert_eq!(scope(code).unwrap_err().kind, kind);
+        }
+    }
+
+    #[test]
+    fn patterns_bind_within_their_arm() {
+        scope_ok("match 1 { {x, y: z} => x + z, n: Int => n, _ => 0 }");
+        scope_err("match 1 { n => n }; n");
+        scope_err("match 1 { n => 1, _ => n }");
+    }
+
+    #[test]
+    fn control_flow() {
+        scope_ok("let xs = {}; for x in xs { if x { break } else { continue } }");
+        scope_ok("let i = 0; while i { for x in i { x; break }; continue }");
+        scope_err("let xs = {}; for x in xs { 1 }; x");
+        scope_ok("let f = fn(x) { while x { return x }; { fn m() { return 1 } } }");
+
+        let errors = [
+            ("break", Kind::BreakOutsideLoop),
+            ("if true { continue }", Kind::BreakOutsideLoop),
+            ("while true { fn() { break } }", Kind::BreakOutsideLoop),
+            ("return 1", Kind::ReturnOutsideFunction),
+            ("let x = { y: return 2 }", Kind::ReturnOutsideFunction),
+        ];
+        for &(code, kind) in errors.iter() {
+            assert_eq!(scope(code).unwrap_err().kind, kind);
+        }
+    }
 }
@@ -20,9 +20,102 @@ pub fn simplify(iv: &InferValue) -> InferValue {
     prune_tyvars(&InferValue {
         data_vars: data_vars,
         type_vars: type_vars,
+        warnings: iv.warnings.clone(),
     })
 }
 
+/// Inlines all of the internal type variables in ty, producing a type which
+/// can be shown to the user. A type variable is never expanded inside of its
+/// own expansion, so recursive types still terminate.
+pub fn simplify_ty(ty: &Ty, type_vars: &HashMap<Ident, Ty>) -> Ty {
+    _simplify_ty(ty, type_vars, &mut vec![])
+}
+
+fn _simplify_ty(ty: &Ty, type_vars: &HashMap<Ident, Ty>, expanding: &mut Vec<Ident>) -> Ty {
+    match *ty {
+        Ty::Ident(ref ident) => {
+            if let Ident(_, Internal(_)) = *ident {
+                if let Some(nty) = type_vars.get(ident) {
+                    if ! expanding.contains(ident) {
+                        expanding.push(ident.clone());
+                        let simplified = _simplify_ty(nty, type_vars, expanding);
+                        expanding.pop();
+                        return simplified;
+                    }
+                }
+            }
+            ty.clone()
+        }
+        Ty::Rec(ref extends, ref brand, ref props) => {
+            let props: Vec<_> = props.iter().map(|prop| {
+                match *prop {
+                    TyProp::Val(ref symb, ref ty, mutability) => {
+                        TyProp::Val(symb.clone(), _simplify_ty(ty, type_vars, expanding), mutability)
+                    }
+                    TyProp::Method(ref symb, ref params, ref res) => {
+                        TyProp::Method(symb.clone(),
+                                       params.iter().map(|param| {
+                                           _simplify_ty(param, type_vars, expanding)
+                                       }).collect(),
+                                       _simplify_ty(res, type_vars, expanding))
+                    }
+                }
+            }).collect();
+
+            match *extends {
+                Some(box ref extends) => {
+                    let extends = _simplify_ty(extends, type_vars, expanding);
+                    extend_with(extends, brand.clone(), props)
+                }
+                None => Ty::Rec(None, brand.clone(), props),
+            }
+        }
+        Ty::Union(ref opts) => {
+            let mut nopts: Vec<Ty> = vec![];
+            for opt in opts.iter() {
+                match _simplify_ty(opt, type_vars, expanding) {
+                    Ty::Union(inner) => {
+                        for opt in inner.into_iter() {
+                            if ! nopts.contains(&opt) { nopts.push(opt); }
+                        }
+                    }
+                    opt => {
+                        if ! nopts.contains(&opt) { nopts.push(opt); }
+                    }
+                }
+            }
+
+            if nopts.len() == 1 { nopts.pop().unwrap() } else { Ty::Union(nopts) }
+        }
+        Ty::Alias(ref id, ref args) => {
+            // Aliases are shown by name, as that is what the user wrote
+            Ty::Alias(id.clone(), args.iter().map(|arg| {
+                _simplify_ty(arg, type_vars, expanding)
+            }).collect())
+        }
+    }
+}
+
+/// Adds brand and props to the (already simplified) record type base.
+/// A field which base also has was refined to be mutable, so base's entry is kept.
+fn extend_with(base: Ty, brand: Brand, props: Vec<TyProp>) -> Ty {
+    match base {
+        Ty::Rec(extends, base_brand, base_props) => {
+            let props: Vec<_> = props.into_iter().filter(|prop| {
+                ! base_props.iter().any(|base_prop| base_prop.symbol() == prop.symbol())
+            }).collect();
+            Ty::Rec(extends, brand.or(base_brand),
+                    props.into_iter().chain(base_props.into_iter()).collect())
+        }
+        Ty::Union(opts) => {
+            Ty::Union(opts.into_iter().map(|opt| {
+                extend_with(opt, brand.clone(), props.clone())
+            }).collect())
+        }
+        base => Ty::Rec(Some(box base), brand, props),
+    }
+}
+
 fn inline_type(ty: &Ty, type_vars: &HashMap<Ident, Ty>) -> Ty {
     let mut inlined = ty.clone();
 
@@ -43,18 +136,19 @@ fn inline_type(ty: &Ty, type_vars: &HashMap<Ident, Ty>) -> Ty {
                 }
 
             }
-            Ty::Rec(ref extends, ref values) => {
+            Ty::Rec(ref extends, ref brand, ref values) => {
                 match *extends {
                     Some(box Ty::Ident(ref ident)) => {
                         if let Some(ref ty) = type_vars.get(ident) {
-                            new_value = Ty::Rec(Some(box() (*ty).clone()), values.clone());
+                            new_value = Ty::Rec(Some(box() (*ty).clone()), brand.clone(), values.clone());
                         } else {
                             break;
                         }
                     }
-                    Some(box Ty::Rec(ref extends2, ref values2)) => {
+                    Some(box Ty::Rec(ref extends2, ref brand2, ref values2)) => {
                         let new_values = values.iter().chain(values2.iter()).map(|x| x.clone());
-                        new_value = Ty::Rec(extends2.clone(), new_values.collect());
+                        new_value = Ty::Rec(extends2.clone(), brand.clone().or(brand2.clone()),
+                                            new_values.collect());
                     }
                     _ => {
                         break;
@@ -84,13 +178,13 @@ fn prune_tyvars(iv: &InferValue) -> InferValue {
             Ty::Ident(ref ident) => {
                 copy(old_type_vars, type_vars, ident);
             }
-            Ty::Rec(ref extends, ref props) => {
+            Ty::Rec(ref extends, _, ref props) => {
                 if let Some(box ref ty) = *extends {
                     handle(old_type_vars, type_vars, ty);
                 }
                 for prop in props.iter() {
                     match *prop {
-                        TyProp::Val(_, ref val) => {
+                        TyProp::Val(_, ref val, _) => {
                             handle(old_type_vars, type_vars, val);
                         }
                         TyProp::Method(_, ref params, ref result) => {
@@ -102,6 +196,11 @@ fn prune_tyvars(iv: &InferValue) -> InferValue {
                     }
                 }
             }
+            Ty::Alias(_, ref args) => {
+                for arg in args.iter() {
+                    handle(old_type_vars, type_vars, arg);
+                }
+            }
             Ty::Union(_) => unimplemented!()
         }
     }
@@ -114,5 +213,6 @@ fn prune_tyvars(iv: &InferValue) -> InferValue {
     InferValue {
         data_vars: iv.data_vars.clone(),
         type_vars: type_vars,
+        warnings: iv.warnings.clone(),
     }
 }
@@ -0,0 +1,66 @@
+use std::fmt;
+
+/// A Span represents a region of the source program. `lo` and `hi` are byte
+/// offsets into the source, while `line` and `col` are the (1-indexed)
+/// position of `lo`, which is what we want to show to users. `end_line` and
+/// `end_col` are the position of `hi`, as tokens like strings can span lines.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Span {
+    pub lo: usize,
+    pub hi: usize,
+    pub line: u32,
+    pub col: u32,
+    pub end_line: u32,
+    pub end_col: u32,
+}
+
+impl Span {
+    /// A span which doesn't point anywhere in particular. Used for nodes
+    /// which are created by the compiler, rather than read in by the parser.
+    pub fn dummy() -> Span {
+        Span{ lo: 0, hi: 0, line: 0, col: 0, end_line: 0, end_col: 0 }
+    }
+
+    pub fn is_dummy(&self) -> bool {
+        self.line == 0
+    }
+
+    /// Creates a span which starts at the start of self, and ends at the
+    /// end of other.
+    pub fn to(&self, other: Span) -> Span {
+        if self.is_dummy() { return other }
+        if other.is_dummy() { return *self }
+
+        Span{ hi: other.hi, end_line: other.end_line, end_col: other.end_col, ..*self }
+    }
+
+    /// An empty span located at the start of self
+    pub fn start(&self) -> Span {
+        Span{ hi: self.lo, end_line: self.line, end_col: self.col, ..*self }
+    }
+
+    /// An empty span located at the end of self
+    pub fn end(&self) -> Span {
+        Span{ lo: self.hi, hi: self.hi, line: self.end_line, col: self.end_col,
+              end_line: self.end_line, end_col: self.end_col }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
+/// Wraps a value with the span which it was read from
+#[derive(Debug, Clone)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span,
+}
+
+impl<T> Spanned<T> {
+    pub fn new(node: T, span: Span) -> Spanned<T> {
+        Spanned{ node: node, span: span }
+    }
+}
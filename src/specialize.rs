@@ -179,8 +179,8 @@ struct SpecState {
 }
 
 fn specialize_expr(st: &mut SpecState, expr: &Expr) -> ExprImpl {
-    match *expr {
-        Expr::Literal(Literal::Str(ref atom)) => {
+    match expr.kind {
+        ExprKind::Literal(Literal::Str(ref atom)) => {
             // For now, we don't have interning (because that's like complicated)
             // in the target language, so let's just be lazy!
             unimplemented!()